@@ -0,0 +1,31 @@
+use std::error::Error;
+
+use stabilityai::{blocking::Client, types::TextToImageRequestBodyArgs};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let client = Client::new();
+
+    let response = client.user().balance()?;
+    println!("{:#?}", response);
+
+    println!("Sending request, please be patient ...");
+    let request = TextToImageRequestBodyArgs::default()
+        .text_prompts("A lighthouse on a cliff at sunset")
+        .samples(1)
+        .steps(30_u32)
+        .width(1024_u16)
+        .height(1024_u16)
+        .build()?;
+
+    let artifacts = client
+        .generate("stable-diffusion-xl-1024-v1-0")
+        .text_to_image(request)?;
+
+    let paths = artifacts.save_blocking("./data")?;
+
+    paths
+        .iter()
+        .for_each(|path| println!("Image saved at {}", path.display()));
+
+    Ok(())
+}
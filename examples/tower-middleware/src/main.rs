@@ -0,0 +1,24 @@
+use std::{error::Error, time::Duration};
+
+use stabilityai::{service::TransportService, transport::ReqwestTransport, Client};
+use tower::ServiceBuilder;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    // Every request made by the client goes through these layers
+    let service = ServiceBuilder::new()
+        .timeout(Duration::from_secs(30))
+        .concurrency_limit(2)
+        .rate_limit(10, Duration::from_secs(1))
+        .service(TransportService::new(ReqwestTransport::default()));
+
+    let client = Client::new().with_service(service);
+
+    let response = client.user().balance().await?;
+    println!("{:#?}", response);
+
+    let response = client.engines().list().await?;
+    println!("{:#?}", response);
+
+    Ok(())
+}
@@ -0,0 +1,235 @@
+//! In-process fake of the stability.ai v1 API for integration tests.
+//!
+//! [FakeServer] serves the routes of `openapi.json` on a local port: engine listing,
+//! user account and balance, and the four generation endpoints. Generation endpoints
+//! return deterministic placeholder PNGs, as JSON artifacts or as raw `image/png`,
+//! and deduct credits from the balance of the fake account.
+//!
+//! ```no_run
+//! use stabilityai::Client;
+//! use stabilityai_fake_server::{FakeError, FakeServer, StatusCode};
+//!
+//! # tokio_test::block_on(async {
+//! let server = FakeServer::start().await.unwrap();
+//! let client = Client::new()
+//!     .with_api_key("sk-test")
+//!     .with_api_base(server.api_base());
+//!
+//! let balance = client.user().balance().await.unwrap();
+//!
+//! // Fail the next request
+//! server.push_error(FakeError::new(StatusCode::TOO_MANY_REQUESTS));
+//! # });
+//! ```
+use std::{
+    collections::{HashSet, VecDeque},
+    net::{SocketAddr, TcpListener},
+    sync::{Arc, Mutex},
+};
+
+use tokio::{sync::oneshot, task::JoinHandle};
+
+pub use axum::http::StatusCode;
+
+mod png;
+mod routes;
+
+pub use png::placeholder as placeholder_png;
+
+/// Credits of the fake account when the server starts
+pub const DEFAULT_CREDITS: f64 = 100.0;
+/// Credits deducted for every generated image
+pub const DEFAULT_COST_PER_IMAGE: f64 = 0.2;
+
+/// Fake API server running on a local port until dropped.
+#[derive(Debug)]
+pub struct FakeServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    shutdown: Option<oneshot::Sender<()>>,
+    handle: Option<JoinHandle<std::io::Result<()>>>,
+}
+
+/// Error response returned instead of the next response, see [FakeServer::push_error]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FakeError {
+    pub status: StatusCode,
+    pub name: String,
+    pub message: String,
+    /// Value of the `Retry-After` header in seconds
+    pub retry_after: Option<u64>,
+}
+
+/// Request received by the server
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReceivedRequest {
+    pub method: String,
+    pub path: String,
+    pub organization: Option<String>,
+}
+
+#[derive(Debug)]
+pub(crate) struct State {
+    pub(crate) api_keys: Option<HashSet<String>>,
+    pub(crate) credits: f64,
+    pub(crate) cost_per_image: f64,
+    pub(crate) errors: VecDeque<FakeError>,
+    pub(crate) requests: Vec<ReceivedRequest>,
+    pub(crate) error_count: u64,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            api_keys: None,
+            credits: DEFAULT_CREDITS,
+            cost_per_image: DEFAULT_COST_PER_IMAGE,
+            errors: VecDeque::new(),
+            requests: Vec::new(),
+            error_count: 0,
+        }
+    }
+}
+
+impl FakeError {
+    /// Error with the name the API uses for the status
+    pub fn new(status: StatusCode) -> Self {
+        let name = match status {
+            StatusCode::BAD_REQUEST => "bad_request",
+            StatusCode::UNAUTHORIZED => "unauthorized",
+            StatusCode::PAYMENT_REQUIRED => "insufficient_balance",
+            StatusCode::FORBIDDEN => "permission_denied",
+            StatusCode::NOT_FOUND => "not_found",
+            StatusCode::TOO_MANY_REQUESTS => "rate_limit_exceeded",
+            _ => "server_error",
+        };
+
+        Self {
+            status,
+            name: name.to_string(),
+            message: status
+                .canonical_reason()
+                .unwrap_or("unknown error")
+                .to_string(),
+            retry_after: None,
+        }
+    }
+
+    pub fn with_message<S: Into<String>>(mut self, message: S) -> Self {
+        self.message = message.into();
+        self
+    }
+
+    pub fn with_retry_after(mut self, seconds: u64) -> Self {
+        self.retry_after = Some(seconds);
+        self
+    }
+}
+
+impl FakeServer {
+    /// Start the server on a free port of the loopback interface
+    pub async fn start() -> std::io::Result<Self> {
+        Self::start_on(SocketAddr::from(([127, 0, 0, 1], 0))).await
+    }
+
+    /// Start the server on the given address
+    pub async fn start_on(addr: SocketAddr) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+
+        let state = Arc::new(Mutex::new(State::default()));
+        let app = routes::router(state.clone());
+        let (shutdown, shutdown_rx) = oneshot::channel();
+
+        let server = axum::Server::from_tcp(listener)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?
+            .serve(app.into_make_service())
+            .with_graceful_shutdown(async {
+                shutdown_rx.await.ok();
+            });
+        let handle = tokio::spawn(async move {
+            server
+                .await
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
+        });
+
+        Ok(Self {
+            addr,
+            state,
+            shutdown: Some(shutdown),
+            handle: Some(handle),
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Base url to give to `Client::with_api_base`
+    pub fn api_base(&self) -> String {
+        format!("http://{}/v1", self.addr)
+    }
+
+    /// Only accept the given API keys, any non empty key is accepted by default
+    pub fn set_api_keys<I, S>(&self, api_keys: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.state().api_keys = Some(api_keys.into_iter().map(Into::into).collect());
+    }
+
+    pub fn set_credits(&self, credits: f64) {
+        self.state().credits = credits;
+    }
+
+    /// Credits left after the images generated so far
+    pub fn credits(&self) -> f64 {
+        self.state().credits
+    }
+
+    pub fn set_cost_per_image(&self, cost_per_image: f64) {
+        self.state().cost_per_image = cost_per_image;
+    }
+
+    /// Return the error for the next request, errors are returned in the order pushed
+    pub fn push_error(&self, error: FakeError) {
+        self.state().errors.push_back(error);
+    }
+
+    /// Requests received so far, in order
+    pub fn requests(&self) -> Vec<ReceivedRequest> {
+        self.state().requests.clone()
+    }
+
+    /// Stop the server gracefully, returning the error it failed with, if any.
+    ///
+    /// Dropping the server stops it too, discarding the error.
+    pub async fn shutdown(mut self) -> std::io::Result<()> {
+        if let Some(shutdown) = self.shutdown.take() {
+            shutdown.send(()).ok();
+        }
+        match self.handle.take() {
+            Some(handle) => handle
+                .await
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?,
+            None => Ok(()),
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+}
+
+impl Drop for FakeServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            shutdown.send(()).ok();
+        }
+        if let Some(handle) = self.handle.take() {
+            handle.abort();
+        }
+    }
+}
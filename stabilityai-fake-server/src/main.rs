@@ -0,0 +1,19 @@
+//! Run the fake server standalone, e.g. to point examples at it:
+//! `cargo run -p stabilityai-fake-server -- 127.0.0.1:8080`
+use std::net::SocketAddr;
+
+use stabilityai_fake_server::FakeServer;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let addr: SocketAddr = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "127.0.0.1:8080".to_string())
+        .parse()?;
+
+    let server = FakeServer::start_on(addr).await?;
+    println!("fake stability.ai API listening on {}", server.api_base());
+
+    std::future::pending::<()>().await;
+    Ok(())
+}
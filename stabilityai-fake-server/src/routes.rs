@@ -0,0 +1,391 @@
+//! Handlers of the v1 routes.
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    extract::{DefaultBodyLimit, Multipart, Path, State as Extract},
+    http::{
+        header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE, RETRY_AFTER},
+        HeaderMap, Method, Uri,
+    },
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use base64::{engine::general_purpose, Engine as _};
+use serde_json::{json, Value};
+
+use crate::{png, FakeError, ReceivedRequest, State, StatusCode};
+
+type SharedState = Arc<Mutex<State>>;
+
+/// Engines listed by `/v1/engines/list`, generation with other engines fails with 404
+pub const ENGINES: [(&str, &str); 4] = [
+    ("stable-diffusion-v1-6", "Stable Diffusion v1.6"),
+    ("stable-diffusion-xl-1024-v1-0", "Stable Diffusion XL v1.0"),
+    ("esrgan-v1-x2plus", "Real-ESRGAN x2"),
+    (
+        "stable-diffusion-x4-latent-upscaler",
+        "Stable Diffusion x4 Latent Upscaler",
+    ),
+];
+
+const MAX_SAMPLES: u32 = 10;
+
+pub(crate) fn router(state: SharedState) -> Router {
+    Router::new()
+        .route("/v1/engines/list", get(engines_list))
+        .route("/v1/user/account", get(user_account))
+        .route("/v1/user/balance", get(user_balance))
+        .route(
+            "/v1/generation/:engine_id/text-to-image",
+            post(text_to_image),
+        )
+        .route(
+            "/v1/generation/:engine_id/image-to-image",
+            post(image_to_image),
+        )
+        .route(
+            "/v1/generation/:engine_id/image-to-image/upscale",
+            post(image_to_image_upscale),
+        )
+        .route(
+            "/v1/generation/:engine_id/image-to-image/masking",
+            post(image_to_image_masking),
+        )
+        .layer(DefaultBodyLimit::disable())
+        .with_state(state)
+}
+
+/// Record the request, then return a pushed error or the rejection of an invalid API key
+fn reject(
+    state: &SharedState,
+    method: &Method,
+    uri: &Uri,
+    headers: &HeaderMap,
+) -> Option<Response> {
+    let mut state = state.lock().unwrap();
+    state.requests.push(ReceivedRequest {
+        method: method.to_string(),
+        path: uri.path().to_string(),
+        organization: headers
+            .get("organization")
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string),
+    });
+
+    if let Some(error) = state.errors.pop_front() {
+        return Some(error_response(&mut state, error));
+    }
+
+    let api_key = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+
+    let valid = match &state.api_keys {
+        Some(api_keys) => api_keys.contains(api_key),
+        None => !api_key.is_empty(),
+    };
+    if !valid {
+        let error = FakeError::new(StatusCode::UNAUTHORIZED)
+            .with_message("missing or invalid API key in Authorization header");
+        return Some(error_response(&mut state, error));
+    }
+
+    None
+}
+
+fn error_response(state: &mut State, error: FakeError) -> Response {
+    state.error_count += 1;
+    let body = json!({
+        "id": format!("fake-error-{}", state.error_count),
+        "name": error.name,
+        "message": error.message,
+    });
+
+    let mut response = (error.status, Json(body)).into_response();
+    if let Some(retry_after) = error.retry_after {
+        response
+            .headers_mut()
+            .insert(RETRY_AFTER, retry_after.to_string().parse().unwrap());
+    }
+    response
+}
+
+fn bad_request(state: &SharedState, message: &str) -> Response {
+    let error = FakeError::new(StatusCode::BAD_REQUEST).with_message(message);
+    error_response(&mut state.lock().unwrap(), error)
+}
+
+async fn engines_list(
+    Extract(state): Extract<SharedState>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Result<Json<Value>, Response> {
+    if let Some(response) = reject(&state, &method, &uri, &headers) {
+        return Err(response);
+    }
+
+    let engines: Vec<Value> = ENGINES
+        .iter()
+        .map(|(id, name)| json!({"id": id, "name": name, "description": name, "type": "PICTURE"}))
+        .collect();
+    Ok(Json(Value::Array(engines)))
+}
+
+async fn user_account(
+    Extract(state): Extract<SharedState>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Result<Json<Value>, Response> {
+    if let Some(response) = reject(&state, &method, &uri, &headers) {
+        return Err(response);
+    }
+
+    Ok(Json(json!({
+        "id": "user-fake",
+        "email": "fake@example.com",
+        "profile_picture": null,
+        "organizations": [
+            {"id": "org-fake", "is_default": true, "name": "Fake Organization", "role": "MEMBER"}
+        ],
+    })))
+}
+
+async fn user_balance(
+    Extract(state): Extract<SharedState>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+) -> Result<Json<Value>, Response> {
+    if let Some(response) = reject(&state, &method, &uri, &headers) {
+        return Err(response);
+    }
+
+    let credits = state.lock().unwrap().credits;
+    Ok(Json(json!({ "credits": credits })))
+}
+
+/// Parameters of a generation request common to all endpoints
+#[derive(Debug, Default)]
+struct Generation {
+    prompt: String,
+    samples: u32,
+    seed: u32,
+}
+
+async fn text_to_image(
+    Extract(state): Extract<SharedState>,
+    Path(engine_id): Path<String>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: String,
+) -> Response {
+    if let Some(response) = reject(&state, &method, &uri, &headers) {
+        return response;
+    }
+
+    let Ok(body) = serde_json::from_str::<Value>(&body) else {
+        return bad_request(&state, "body is not valid JSON");
+    };
+
+    let prompt = body["text_prompts"]
+        .as_array()
+        .and_then(|prompts| prompts.first())
+        .and_then(|prompt| prompt["text"].as_str())
+        .unwrap_or_default();
+    let generation = Generation {
+        prompt: prompt.to_string(),
+        samples: body["samples"].as_u64().unwrap_or(1) as u32,
+        seed: body["seed"].as_u64().unwrap_or(0) as u32,
+    };
+
+    generate(&state, &engine_id, &headers, generation, true)
+}
+
+async fn image_to_image(
+    Extract(state): Extract<SharedState>,
+    Path(engine_id): Path<String>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    multipart: Multipart,
+) -> Response {
+    if let Some(response) = reject(&state, &method, &uri, &headers) {
+        return response;
+    }
+
+    generate_from_form(state, engine_id, headers, multipart, &["init_image"], true).await
+}
+
+async fn image_to_image_upscale(
+    Extract(state): Extract<SharedState>,
+    Path(engine_id): Path<String>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    multipart: Multipart,
+) -> Response {
+    if let Some(response) = reject(&state, &method, &uri, &headers) {
+        return response;
+    }
+
+    generate_from_form(state, engine_id, headers, multipart, &["image"], false).await
+}
+
+async fn image_to_image_masking(
+    Extract(state): Extract<SharedState>,
+    Path(engine_id): Path<String>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    multipart: Multipart,
+) -> Response {
+    if let Some(response) = reject(&state, &method, &uri, &headers) {
+        return response;
+    }
+
+    generate_from_form(state, engine_id, headers, multipart, &["init_image"], true).await
+}
+
+/// Read the form of an image-to-image request and generate
+async fn generate_from_form(
+    state: SharedState,
+    engine_id: String,
+    headers: HeaderMap,
+    mut multipart: Multipart,
+    required_files: &[&str],
+    requires_prompt: bool,
+) -> Response {
+    let mut texts = HashMap::new();
+    let mut files = Vec::new();
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => return bad_request(&state, &format!("invalid multipart body: {e}")),
+        };
+
+        let name = field.name().unwrap_or_default().to_string();
+        let is_file = field.file_name().is_some();
+        let Ok(bytes) = field.bytes().await else {
+            return bad_request(&state, "failed to read multipart field");
+        };
+
+        if is_file {
+            if bytes.is_empty() {
+                return bad_request(&state, &format!("{name}: file is empty"));
+            }
+            files.push(name);
+        } else {
+            texts.insert(name, String::from_utf8_lossy(&bytes).into_owned());
+        }
+    }
+
+    for required in required_files {
+        if !files.iter().any(|name| name == required) {
+            return bad_request(&state, &format!("{required}: missing file"));
+        }
+    }
+
+    let generation = Generation {
+        prompt: texts
+            .get("text_prompts[0][text]")
+            .cloned()
+            .unwrap_or_default(),
+        samples: texts
+            .get("samples")
+            .and_then(|samples| samples.parse().ok())
+            .unwrap_or(1),
+        seed: texts
+            .get("seed")
+            .and_then(|seed| seed.parse().ok())
+            .unwrap_or(0),
+    };
+
+    generate(&state, &engine_id, &headers, generation, requires_prompt)
+}
+
+/// Validate the generation, deduct credits and return placeholder images
+fn generate(
+    state: &SharedState,
+    engine_id: &str,
+    headers: &HeaderMap,
+    generation: Generation,
+    requires_prompt: bool,
+) -> Response {
+    if !ENGINES.iter().any(|(id, _)| *id == engine_id) {
+        let error = FakeError::new(StatusCode::NOT_FOUND)
+            .with_message(format!("engine {engine_id} does not exist"));
+        return error_response(&mut state.lock().unwrap(), error);
+    }
+    if requires_prompt && generation.prompt.trim().is_empty() {
+        return bad_request(state, "text_prompts: cannot be empty");
+    }
+    if generation.samples == 0 || generation.samples > MAX_SAMPLES {
+        return bad_request(state, "samples: must be between 1 and 10");
+    }
+
+    let binary = headers
+        .get(ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .map(|accept| accept.contains("image/png"))
+        .unwrap_or(false);
+    // Only a single image is returned as raw PNG
+    let samples = if binary { 1 } else { generation.samples };
+
+    {
+        let mut state = state.lock().unwrap();
+        let cost = state.cost_per_image * samples as f64;
+        if state.credits < cost {
+            let error = FakeError::new(StatusCode::PAYMENT_REQUIRED)
+                .with_message("not enough credits to complete the request");
+            return error_response(&mut state, error);
+        }
+        state.credits -= cost;
+    }
+
+    let first_seed = match generation.seed {
+        0 => fnv1a(generation.prompt.as_bytes()),
+        seed => seed,
+    };
+    let seeds = (0..samples).map(|index| first_seed.wrapping_add(index));
+
+    if binary {
+        let seed = first_seed;
+        return (
+            [
+                (CONTENT_TYPE, "image/png".to_string()),
+                ("seed".parse().unwrap(), seed.to_string()),
+                ("finish-reason".parse().unwrap(), "SUCCESS".to_string()),
+            ],
+            png::placeholder(seed),
+        )
+            .into_response();
+    }
+
+    let artifacts: Vec<Value> = seeds
+        .map(|seed| {
+            json!({
+                "base64": general_purpose::STANDARD.encode(png::placeholder(seed)),
+                "finishReason": "SUCCESS",
+                "seed": seed,
+            })
+        })
+        .collect();
+    Json(json!({ "artifacts": artifacts })).into_response()
+}
+
+/// Deterministic seed for requests without one
+fn fnv1a(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, byte| {
+        (hash ^ *byte as u32).wrapping_mul(0x0100_0193)
+    })
+}
@@ -0,0 +1,186 @@
+//! The client works end to end against the fake server.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use stabilityai::{
+    error::StabilityAIError,
+    retry::DefaultRetryPolicy,
+    transport::UploadProgress,
+    types::{
+        FinishReason, ImageToImageRequestBodyArgs, MaskSource, MaskingRequestBodyArgs,
+        RealESRGANUpscaleRequestBodyArgs, TextToImageRequestBodyArgs,
+    },
+    Client, RequestOptions,
+};
+use stabilityai_fake_server::{placeholder_png, FakeError, FakeServer, StatusCode};
+
+fn client(server: &FakeServer) -> Client {
+    let backoff = backoff::ExponentialBackoffBuilder::new()
+        .with_initial_interval(Duration::from_millis(1))
+        .with_max_elapsed_time(Some(Duration::from_secs(1)))
+        .build();
+
+    Client::new()
+        .with_api_key("sk-test")
+        .with_api_base(server.api_base())
+        .with_backoff(backoff)
+}
+
+/// Write a placeholder PNG to upload
+fn image_file(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join("stabilityai-fake-server-tests");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    std::fs::write(&path, placeholder_png(1)).unwrap();
+    path
+}
+
+#[tokio::test]
+async fn engines_and_user() {
+    let server = FakeServer::start().await.unwrap();
+    let client = client(&server);
+
+    let engines = client.engines().list().await.unwrap();
+    assert!(engines.iter().any(|e| e.id == "stable-diffusion-v1-6"));
+
+    let account = client.user().account().await.unwrap();
+    assert_eq!(account.organizations.len(), 1);
+
+    let balance = client.user().balance().await.unwrap();
+    assert_eq!(balance.credits, 100.0);
+
+    server.shutdown().await.unwrap();
+}
+
+#[tokio::test]
+async fn generation_deducts_credits() {
+    let server = FakeServer::start().await.unwrap();
+    server.set_credits(1.0);
+    server.set_cost_per_image(0.25);
+    let client = client(&server);
+    let generate = client.generate("stable-diffusion-v1-6");
+
+    let request = TextToImageRequestBodyArgs::default()
+        .text_prompts("A lighthouse")
+        .samples(2)
+        .seed(42_u32)
+        .build()
+        .unwrap();
+    let artifacts = generate.text_to_image(request.clone()).await.unwrap();
+    assert_eq!(artifacts.artifacts.len(), 2);
+    assert_eq!(artifacts.artifacts[1].seed, 43);
+    assert_eq!(server.credits(), 0.5);
+
+    let again = generate.text_to_image(request.clone()).await.unwrap();
+    assert_eq!(again, artifacts);
+
+    let result = generate.text_to_image(request).await;
+    assert!(
+        matches!(result, Err(StabilityAIError::ApiError(e)) if e.name == "insufficient_balance")
+    );
+}
+
+#[tokio::test]
+async fn multipart_generation() {
+    let server = FakeServer::start().await.unwrap();
+    let client = client(&server);
+    let generate = client.generate("stable-diffusion-v1-6");
+    let init_image = image_file("init.png");
+
+    let request = ImageToImageRequestBodyArgs::default()
+        .text_prompts("A crab")
+        .init_image(&init_image)
+        .build()
+        .unwrap();
+    let image = generate.image_to_image_binary(request).await.unwrap();
+    assert_eq!(image.finish_reason, FinishReason::Success);
+    assert_eq!(image.bytes.as_ref(), placeholder_png(image.seed as u32));
+
+    let request = MaskingRequestBodyArgs::default()
+        .text_prompts("A lake")
+        .init_image(&init_image)
+        .mask_image(image_file("mask.png"))
+        .mask_source(MaskSource::MaskImageWhite)
+        .build()
+        .unwrap();
+    generate.image_to_image_masking(request).await.unwrap();
+
+    let request = RealESRGANUpscaleRequestBodyArgs::default()
+        .image(&init_image)
+        .build()
+        .unwrap();
+    client
+        .generate("esrgan-v1-x2plus")
+        .image_to_image_upscale(request)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn upload_progress() {
+    let server = FakeServer::start().await.unwrap();
+    let progress = Arc::new(Mutex::new(Vec::<UploadProgress>::new()));
+    let reported = progress.clone();
+    let options = RequestOptions::new()
+        .with_upload_progress(move |progress| reported.lock().unwrap().push(progress));
+
+    let request = MaskingRequestBodyArgs::default()
+        .text_prompts("A lake")
+        .init_image(image_file("progress-init.png"))
+        .mask_image(image_file("progress-mask.png"))
+        .mask_source(MaskSource::MaskImageWhite)
+        .build()
+        .unwrap();
+    client(&server)
+        .generate("stable-diffusion-v1-6")
+        .with_options(options)
+        .image_to_image_masking(request)
+        .await
+        .unwrap();
+
+    let total = 2 * placeholder_png(1).len() as u64;
+    let progress = progress.lock().unwrap();
+    assert!(progress.windows(2).all(|w| w[0].sent < w[1].sent));
+    assert!(progress.iter().all(|p| p.total == total));
+    assert_eq!(progress.last().unwrap().sent, total);
+}
+
+#[tokio::test]
+async fn error_responses() {
+    let server = FakeServer::start().await.unwrap();
+    server.set_api_keys(["sk-test"]);
+    let client = client(&server).with_retry_policy(DefaultRetryPolicy::new());
+
+    // Transient errors are retried
+    server.push_error(FakeError::new(StatusCode::TOO_MANY_REQUESTS).with_retry_after(0));
+    server.push_error(FakeError::new(StatusCode::INTERNAL_SERVER_ERROR));
+    let balance = client.user().balance_with_meta().await.unwrap();
+    assert_eq!(balance.attempts, 3);
+
+    server.push_error(FakeError::new(StatusCode::FORBIDDEN));
+    let result = client.user().account().await;
+    assert!(matches!(result, Err(StabilityAIError::ApiError(e)) if e.name == "permission_denied"));
+
+    let result = client
+        .clone()
+        .with_api_key("sk-other")
+        .user()
+        .balance()
+        .await;
+    assert!(matches!(result, Err(StabilityAIError::ApiError(e)) if e.name == "unauthorized"));
+
+    let request = TextToImageRequestBodyArgs::default()
+        .text_prompts("A lighthouse")
+        .build()
+        .unwrap();
+    let result = client
+        .generate("unknown-engine")
+        .text_to_image(request)
+        .await;
+    assert!(matches!(result, Err(StabilityAIError::ApiError(e)) if e.name == "not_found"));
+
+    assert_eq!(server.requests().len(), 6);
+}
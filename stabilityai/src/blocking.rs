@@ -0,0 +1,433 @@
+//! Blocking API, enabled with the `blocking` cargo feature.
+//!
+//! [Client] wraps the async [crate::Client] and drives its requests on an internal
+//! single threaded Tokio runtime, so it can be used from synchronous code such as
+//! build scripts and small CLIs.
+//!
+//! ```no_run
+//! use stabilityai::blocking::Client;
+//!
+//! let client = Client::new();
+//!
+//! let balance = client.user().balance().unwrap();
+//! println!("{:#?}", balance);
+//! ```
+//!
+//! Generated images can be written without a runtime with
+//! [Artifacts::save_blocking](crate::types::Artifacts::save_blocking).
+//!
+//! The blocking client must not be used from within an async runtime,
+//! doing so panics.
+use std::{fmt::Display, future::Future, sync::Arc};
+
+use crate::{
+    budget::BudgetGuard,
+    cache::ResponseCache,
+    credentials::{ApiKey, CredentialProvider},
+    error::StabilityAIError,
+    key_pool::ApiKeyPool,
+    ledger::LedgerSink,
+    rate_limit::RateLimiter,
+    retry::RetryPolicy,
+    transport::HttpTransport,
+    types::{
+        AccountResponseBody, Artifacts, BalanceResponseBody, BinaryImage, Engine,
+        ImageToImageRequestBody, ImageToImageUpscaleBody, MaskingRequestBody,
+        TextToImageRequestBody,
+    },
+    RequestOptions, RequestPreview, WithMeta,
+};
+
+/// Blocking client, a container of configurations to make API calls.
+#[derive(Debug, Clone)]
+pub struct Client {
+    inner: crate::Client,
+    runtime: Arc<tokio::runtime::Runtime>,
+}
+
+impl Default for Client {
+    /// Create client with default [API_BASE](crate::API_BASE) url and default API key
+    /// from STABILITY_API_KEY env var
+    fn default() -> Self {
+        crate::Client::default().into()
+    }
+}
+
+impl From<crate::Client> for Client {
+    /// Create blocking client with configurations of the async client
+    fn from(inner: crate::Client) -> Self {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("failed to create Tokio runtime for blocking client");
+
+        Self {
+            inner,
+            runtime: Arc::new(runtime),
+        }
+    }
+}
+
+impl Client {
+    /// Create client with default [API_BASE](crate::API_BASE) url and default API key
+    /// from STABILITY_API_KEY env var
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Provide your own [client] to make HTTP requests with.
+    ///
+    /// [client]: reqwest::Client
+    pub fn with_http_client(self, http_client: reqwest::Client) -> Self {
+        self.map(|inner| inner.with_http_client(http_client))
+    }
+
+    /// Provide your own [transport] to send HTTP requests with.
+    ///
+    /// [transport]: HttpTransport
+    pub fn with_transport<T: HttpTransport + 'static>(self, transport: T) -> Self {
+        self.map(|inner| inner.with_transport(transport))
+    }
+
+    /// To use a different API key different from default STABILITY_API_KEY env var
+    pub fn with_api_key<K: Into<ApiKey>>(self, api_key: K) -> Self {
+        self.map(|inner| inner.with_api_key(api_key))
+    }
+
+    /// Ask the [CredentialProvider] for the API key before every attempt
+    pub fn with_credentials<P: CredentialProvider + 'static>(self, credentials: P) -> Self {
+        self.map(|inner| inner.with_credentials(credentials))
+    }
+
+    /// To use a different organization id other than default
+    pub fn with_organization<S: Into<String>>(self, organization: S) -> Self {
+        self.map(|inner| inner.with_organization(organization))
+    }
+
+    /// To use a API base url different from default [API_BASE](crate::API_BASE)
+    pub fn with_api_base<S: Into<String>>(self, api_base: S) -> Self {
+        self.map(|inner| inner.with_api_base(api_base))
+    }
+
+    /// Value of the [CLIENT_ID_HEADER](crate::CLIENT_ID_HEADER) sent with every request
+    pub fn with_client_id<S: Into<String>>(self, client_id: S) -> Self {
+        self.map(|inner| inner.with_client_id(client_id))
+    }
+
+    /// Value of the [CLIENT_VERSION_HEADER](crate::CLIENT_VERSION_HEADER) sent with every request
+    pub fn with_client_version<S: Into<String>>(self, client_version: S) -> Self {
+        self.map(|inner| inner.with_client_version(client_version))
+    }
+
+    /// Exponential backoff for retrying requests which failed transiently.
+    pub fn with_backoff(self, backoff: backoff::ExponentialBackoff) -> Self {
+        self.map(|inner| inner.with_backoff(backoff))
+    }
+
+    /// To decide which failures are retried
+    pub fn with_retry_policy<P: RetryPolicy + 'static>(self, retry_policy: P) -> Self {
+        self.map(|inner| inner.with_retry_policy(retry_policy))
+    }
+
+    /// Limit the rate and concurrency of requests on the client side
+    pub fn with_rate_limit<L: Into<RateLimiter>>(self, rate_limit: L) -> Self {
+        self.map(|inner| inner.with_rate_limit(rate_limit))
+    }
+
+    /// Send requests with keys of the [ApiKeyPool] instead of the single API key
+    pub fn with_api_key_pool(self, key_pool: ApiKeyPool) -> Self {
+        self.map(|inner| inner.with_api_key_pool(key_pool))
+    }
+
+    /// Answer generation requests with a fixed seed from the [ResponseCache]
+    pub fn with_cache(self, cache: ResponseCache) -> Self {
+        self.map(|inner| inner.with_cache(cache))
+    }
+
+    /// Coalesce identical generation requests with a fixed seed made concurrently
+    pub fn with_deduplication(self, enabled: bool) -> Self {
+        self.map(|inner| inner.with_deduplication(enabled))
+    }
+
+    /// Refuse generation requests which would cross the limits of the [BudgetGuard]
+    pub fn with_budget(self, budget: BudgetGuard) -> Self {
+        self.map(|inner| inner.with_budget(budget))
+    }
+
+    /// Record every API call made by this client into the [LedgerSink]
+    pub fn with_ledger<L: LedgerSink + 'static>(self, ledger: L) -> Self {
+        self.map(|inner| inner.with_ledger(ledger))
+    }
+
+    /// The async client used to make API calls
+    pub fn inner(&self) -> &crate::Client {
+        &self.inner
+    }
+
+    fn map<F: FnOnce(crate::Client) -> crate::Client>(mut self, f: F) -> Self {
+        self.inner = f(self.inner);
+        self
+    }
+
+    fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+
+    // API groups
+
+    /// To call [User] group related APIs using this client.
+    pub fn user(&self) -> User<'_> {
+        User {
+            client: self,
+            options: Default::default(),
+        }
+    }
+
+    /// To call [Engines] group related APIs using this client.
+    pub fn engines(&self) -> Engines<'_> {
+        Engines {
+            client: self,
+            options: Default::default(),
+        }
+    }
+
+    /// To call [Generate] group related APIs using this client.
+    pub fn generate<S>(&self, engine_id: S) -> Generate<'_, S>
+    where
+        S: Into<String> + Display,
+    {
+        Generate {
+            client: self,
+            engine_id,
+            options: Default::default(),
+        }
+    }
+}
+
+/// Manage your Stability.ai account, and view account/organization balances
+pub struct User<'c> {
+    client: &'c Client,
+    options: RequestOptions,
+}
+
+impl<'c> User<'c> {
+    /// Timeout, cancellation and headers of the calls made with this instance
+    pub fn with_options(mut self, options: RequestOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    fn user(&self) -> crate::User<'_> {
+        self.client.inner.user().with_options(self.options.clone())
+    }
+
+    /// Get information about the account associated with the provided API key
+    pub fn account(&self) -> Result<AccountResponseBody, StabilityAIError> {
+        self.client.block_on(self.user().account())
+    }
+
+    /// Same as [User::account] with status, headers and latency of the response
+    pub fn account_with_meta(&self) -> Result<WithMeta<AccountResponseBody>, StabilityAIError> {
+        self.client.block_on(self.user().account_with_meta())
+    }
+
+    /// The balance of the account/organization associated with the API key
+    pub fn balance(&self) -> Result<BalanceResponseBody, StabilityAIError> {
+        self.client.block_on(self.user().balance())
+    }
+
+    /// Same as [User::balance] with status, headers and latency of the response
+    pub fn balance_with_meta(&self) -> Result<WithMeta<BalanceResponseBody>, StabilityAIError> {
+        self.client.block_on(self.user().balance_with_meta())
+    }
+}
+
+/// Enumerate available engines
+pub struct Engines<'c> {
+    client: &'c Client,
+    options: RequestOptions,
+}
+
+impl<'c> Engines<'c> {
+    /// Timeout, cancellation and headers of the calls made with this instance
+    pub fn with_options(mut self, options: RequestOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    fn engines(&self) -> crate::Engines<'_> {
+        self.client
+            .inner
+            .engines()
+            .with_options(self.options.clone())
+    }
+
+    /// List all engines available to your organization/user
+    pub fn list(&self) -> Result<Vec<Engine>, StabilityAIError> {
+        self.client.block_on(self.engines().list())
+    }
+
+    /// Same as [Engines::list] with status, headers and latency of the response
+    pub fn list_with_meta(&self) -> Result<WithMeta<Vec<Engine>>, StabilityAIError> {
+        self.client.block_on(self.engines().list_with_meta())
+    }
+}
+
+/// Generate images from text, existing images, or both
+pub struct Generate<'c, E: Display> {
+    client: &'c Client,
+    engine_id: E,
+    options: RequestOptions,
+}
+
+impl<'c, E: Display> Generate<'c, E> {
+    /// Timeout, cancellation and headers of the calls made with this instance
+    pub fn with_options(mut self, options: RequestOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    fn generate(&self) -> crate::Generate<'_, &E> {
+        crate::Generate::new(&self.client.inner, &self.engine_id).with_options(self.options.clone())
+    }
+
+    /// Generate a new image from a text prompt
+    pub fn text_to_image(
+        &self,
+        request: TextToImageRequestBody,
+    ) -> Result<Artifacts, StabilityAIError> {
+        self.client.block_on(self.generate().text_to_image(request))
+    }
+
+    /// Same as [Generate::text_to_image] with status, headers and latency of the response
+    pub fn text_to_image_with_meta(
+        &self,
+        request: TextToImageRequestBody,
+    ) -> Result<WithMeta<Artifacts>, StabilityAIError> {
+        self.client
+            .block_on(self.generate().text_to_image_with_meta(request))
+    }
+
+    /// Modify an image based on a text prompt
+    pub fn image_to_image(
+        &self,
+        request: ImageToImageRequestBody,
+    ) -> Result<Artifacts, StabilityAIError> {
+        self.client
+            .block_on(self.generate().image_to_image(request))
+    }
+
+    /// Same as [Generate::image_to_image] with status, headers and latency of the response
+    pub fn image_to_image_with_meta(
+        &self,
+        request: ImageToImageRequestBody,
+    ) -> Result<WithMeta<Artifacts>, StabilityAIError> {
+        self.client
+            .block_on(self.generate().image_to_image_with_meta(request))
+    }
+
+    /// Create a higher resolution version of an input image,
+    /// see [crate::Generate::image_to_image_upscale].
+    pub fn image_to_image_upscale<R: Into<ImageToImageUpscaleBody>>(
+        &self,
+        request: R,
+    ) -> Result<Artifacts, StabilityAIError> {
+        self.client
+            .block_on(self.generate().image_to_image_upscale(request))
+    }
+
+    /// Same as [Generate::image_to_image_upscale] with status, headers and latency of the response
+    pub fn image_to_image_upscale_with_meta<R: Into<ImageToImageUpscaleBody>>(
+        &self,
+        request: R,
+    ) -> Result<WithMeta<Artifacts>, StabilityAIError> {
+        self.client
+            .block_on(self.generate().image_to_image_upscale_with_meta(request))
+    }
+
+    /// Selectively modify portions of an image using a mask
+    pub fn image_to_image_masking(
+        &self,
+        request: MaskingRequestBody,
+    ) -> Result<Artifacts, StabilityAIError> {
+        self.client
+            .block_on(self.generate().image_to_image_masking(request))
+    }
+
+    /// Same as [Generate::image_to_image_masking] with status, headers and latency of the response
+    pub fn image_to_image_masking_with_meta(
+        &self,
+        request: MaskingRequestBody,
+    ) -> Result<WithMeta<Artifacts>, StabilityAIError> {
+        self.client
+            .block_on(self.generate().image_to_image_masking_with_meta(request))
+    }
+
+    /// Generate a new image from a text prompt and return it as raw PNG bytes.
+    pub fn text_to_image_binary(
+        &self,
+        request: TextToImageRequestBody,
+    ) -> Result<BinaryImage, StabilityAIError> {
+        self.client
+            .block_on(self.generate().text_to_image_binary(request))
+    }
+
+    /// Modify an image based on a text prompt and return it as raw PNG bytes.
+    pub fn image_to_image_binary(
+        &self,
+        request: ImageToImageRequestBody,
+    ) -> Result<BinaryImage, StabilityAIError> {
+        self.client
+            .block_on(self.generate().image_to_image_binary(request))
+    }
+
+    /// Create a higher resolution version of an input image and return it as raw PNG bytes.
+    pub fn image_to_image_upscale_binary<R: Into<ImageToImageUpscaleBody>>(
+        &self,
+        request: R,
+    ) -> Result<BinaryImage, StabilityAIError> {
+        self.client
+            .block_on(self.generate().image_to_image_upscale_binary(request))
+    }
+
+    /// Selectively modify portions of an image using a mask and return it as raw PNG bytes.
+    pub fn image_to_image_masking_binary(
+        &self,
+        request: MaskingRequestBody,
+    ) -> Result<BinaryImage, StabilityAIError> {
+        self.client
+            .block_on(self.generate().image_to_image_masking_binary(request))
+    }
+
+    /// The request [Generate::text_to_image] would send, without sending it
+    pub fn preview_text_to_image(
+        &self,
+        request: TextToImageRequestBody,
+    ) -> Result<RequestPreview, StabilityAIError> {
+        self.generate().preview_text_to_image(request)
+    }
+
+    /// The request [Generate::image_to_image] would send, without sending it
+    pub fn preview_image_to_image(
+        &self,
+        request: ImageToImageRequestBody,
+    ) -> Result<RequestPreview, StabilityAIError> {
+        self.generate().preview_image_to_image(request)
+    }
+
+    /// The request [Generate::image_to_image_upscale] would send, without sending it
+    pub fn preview_image_to_image_upscale<R: Into<ImageToImageUpscaleBody>>(
+        &self,
+        request: R,
+    ) -> Result<RequestPreview, StabilityAIError> {
+        self.generate().preview_image_to_image_upscale(request)
+    }
+
+    /// The request [Generate::image_to_image_masking] would send, without sending it
+    pub fn preview_image_to_image_masking(
+        &self,
+        request: MaskingRequestBody,
+    ) -> Result<RequestPreview, StabilityAIError> {
+        self.generate().preview_image_to_image_masking(request)
+    }
+}
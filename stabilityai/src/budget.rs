@@ -0,0 +1,258 @@
+//! Client side guard against spending more credits than intended.
+//!
+//! A [BudgetGuard] given to [Client::with_budget](crate::Client::with_budget) estimates the
+//! credits of every generation request made with [Generate](crate::Generate), and refuses
+//! it with [StabilityAIError::BudgetExceeded] when it would cross the configured budget,
+//! or leave the account balance below the configured minimum. The balance is lowered by
+//! the estimates of the requests of the guard still in flight.
+//!
+//! ```
+//! use stabilityai::{budget::BudgetGuard, Client};
+//!
+//! let guard = BudgetGuard::new()
+//!     .with_budget(50.0)
+//!     .with_min_balance(10.0)
+//!     .with_balance_check_threshold(2.0);
+//!
+//! let client = Client::new().with_budget(guard.clone());
+//! // ... generate images
+//! println!("spent about {} credits", guard.spent());
+//! ```
+//!
+//! Credits are estimated with the [PricingTable] of the guard. The estimate of a request
+//! is counted when it is sent, and only given back when the request provably did not
+//! reach the server: it failed before being sent, could not connect, or was answered
+//! with a 4xx status. A request which timed out, was cancelled or failed with a server
+//! error stays counted. Requests answered from the [cache](crate::cache) or coalesced
+//! with an identical request in flight are not counted.
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
+
+use crate::{
+    error::StabilityAIError,
+    pricing::{EnginePrice, PricingTable},
+    transport::HttpRequest,
+};
+
+/// Limit of a [BudgetGuard] a request would cross
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BudgetLimit {
+    /// Credits the guard allows to spend, see [BudgetGuard::with_budget]
+    Budget,
+    /// Balance to keep on the account, see [BudgetGuard::with_min_balance]
+    MinBalance,
+}
+
+impl std::fmt::Display for BudgetLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BudgetLimit::Budget => f.write_str("budget"),
+            BudgetLimit::MinBalance => f.write_str("minimum balance"),
+        }
+    }
+}
+
+/// Details of a request refused by a [BudgetGuard]
+#[derive(Debug, Clone, PartialEq)]
+pub struct BudgetExceeded {
+    pub limit: BudgetLimit,
+    /// Estimated credits of the refused request
+    pub estimated: f64,
+    /// Credits which could still be spent under the limit
+    pub available: f64,
+}
+
+/// Budget and minimum balance of the credits spent by a client, see the
+/// [module](self) documentation.
+///
+/// Clones share the credits spent, so that a guard can be inspected while a
+/// client uses it, or shared by several clients.
+#[derive(Debug, Clone)]
+pub struct BudgetGuard {
+    budget: Option<f64>,
+    min_balance: Option<f64>,
+    balance_check_threshold: f64,
+    pricing: PricingTable,
+    spent: Arc<Mutex<f64>>,
+    /// Credits of requests in flight, not yet reflected by the balance of the account
+    outstanding: Arc<Mutex<f64>>,
+}
+
+impl Default for BudgetGuard {
+    fn default() -> Self {
+        Self {
+            budget: None,
+            min_balance: None,
+            balance_check_threshold: 0.0,
+            pricing: PricingTable::default(),
+            spent: Default::default(),
+            outstanding: Default::default(),
+        }
+    }
+}
+
+impl BudgetGuard {
+    /// Guard without limits, which only counts the credits spent
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Maximum credits spent by requests made through this guard
+    pub fn with_budget(mut self, credits: f64) -> Self {
+        self.budget = Some(credits);
+        self
+    }
+
+    /// Check the [balance](crate::User::balance) of the account before sending a
+    /// request, and refuse it when the balance would fall below `credits`
+    pub fn with_min_balance(mut self, credits: f64) -> Self {
+        self.min_balance = Some(credits);
+        self
+    }
+
+    /// Only check the balance for requests estimated at `credits` or more, 0 by default
+    pub fn with_balance_check_threshold(mut self, credits: f64) -> Self {
+        self.balance_check_threshold = credits;
+        self
+    }
+
+    /// Prices to estimate the credits of requests with, [PricingTable::default] by default
+    pub fn with_pricing(mut self, pricing: PricingTable) -> Self {
+        self.pricing = pricing;
+        self
+    }
+
+    /// Estimate every generated image at the same `credits`, whatever its engine
+    pub fn with_cost_per_image(mut self, credits: f64) -> Self {
+        self.pricing = PricingTable::new(EnginePrice::flat(credits));
+        self
+    }
+
+    pub fn pricing(&self) -> &PricingTable {
+        &self.pricing
+    }
+
+    pub fn budget(&self) -> Option<f64> {
+        self.budget
+    }
+
+    pub fn min_balance(&self) -> Option<f64> {
+        self.min_balance
+    }
+
+    /// Estimated credits spent by requests sent so far, including those in flight
+    pub fn spent(&self) -> f64 {
+        *self.spent.lock().unwrap()
+    }
+
+    /// Credits which can still be spent under the budget, `None` without a budget
+    pub fn remaining(&self) -> Option<f64> {
+        self.budget.map(|budget| (budget - self.spent()).max(0.0))
+    }
+
+    /// Forget the credits spent so far
+    pub fn reset(&self) {
+        *self.spent.lock().unwrap() = 0.0;
+    }
+
+    /// Estimated credits of a request, `None` for requests which are not generations
+    pub(crate) fn estimate(&self, request: &HttpRequest) -> Option<f64> {
+        self.pricing.estimate_request(request)
+    }
+
+    /// Whether the balance should be checked before a request of `estimated` credits
+    pub(crate) fn checks_balance(&self, estimated: f64) -> bool {
+        self.min_balance.is_some() && estimated >= self.balance_check_threshold
+    }
+
+    /// Count the estimated credits of a request, unless a limit would be crossed.
+    ///
+    /// The balance is lowered by the credits of the other requests in flight, which it
+    /// may not reflect yet. The credits are given back when the returned reservation is
+    /// dropped, unless an attempt of the request may have reached the server, see
+    /// [Reservation::sending].
+    pub(crate) fn reserve(
+        &self,
+        estimated: f64,
+        balance: Option<f64>,
+    ) -> Result<Reservation, StabilityAIError> {
+        let mut outstanding = self.outstanding.lock().unwrap();
+        if let (Some(balance), Some(min_balance)) = (balance, self.min_balance) {
+            let balance = balance - *outstanding;
+            if balance - estimated < min_balance {
+                return Err(StabilityAIError::BudgetExceeded(BudgetExceeded {
+                    limit: BudgetLimit::MinBalance,
+                    estimated,
+                    available: (balance - min_balance).max(0.0),
+                }));
+            }
+        }
+
+        let mut spent = self.spent.lock().unwrap();
+        if let Some(budget) = self.budget {
+            if *spent + estimated > budget {
+                return Err(StabilityAIError::BudgetExceeded(BudgetExceeded {
+                    limit: BudgetLimit::Budget,
+                    estimated,
+                    available: (budget - *spent).max(0.0),
+                }));
+            }
+        }
+        *spent += estimated;
+        *outstanding += estimated;
+
+        Ok(Reservation {
+            spent: self.spent.clone(),
+            outstanding: self.outstanding.clone(),
+            credits: estimated,
+            sending: AtomicBool::new(false),
+            charged: AtomicBool::new(false),
+        })
+    }
+}
+
+/// Credits counted for a request in flight, given back on drop unless an attempt
+/// of the request may have been processed by the server
+#[derive(Debug)]
+pub(crate) struct Reservation {
+    spent: Arc<Mutex<f64>>,
+    outstanding: Arc<Mutex<f64>>,
+    credits: f64,
+    /// An attempt is being sent, dropping the reservation now keeps the credits
+    sending: AtomicBool,
+    /// An attempt completed which may have cost credits
+    charged: AtomicBool,
+}
+
+impl Reservation {
+    /// Mark an attempt as handed to the transport
+    pub(crate) fn sending(&self) {
+        self.sending.store(true, Ordering::Relaxed);
+    }
+
+    /// Record the outcome of the attempt being sent, `charged` unless it provably
+    /// did not reach the server
+    pub(crate) fn sent(&self, charged: bool) {
+        if charged {
+            self.charged.store(true, Ordering::Relaxed);
+        }
+        self.sending.store(false, Ordering::Relaxed);
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        // The request is over, the balance reflects whatever it cost
+        let mut outstanding = self.outstanding.lock().unwrap();
+        *outstanding = (*outstanding - self.credits).max(0.0);
+        drop(outstanding);
+
+        if self.sending.load(Ordering::Relaxed) || self.charged.load(Ordering::Relaxed) {
+            return;
+        }
+        let mut spent = self.spent.lock().unwrap();
+        *spent = (*spent - self.credits).max(0.0);
+    }
+}
@@ -0,0 +1,304 @@
+//! On-disk cache of generation responses, to avoid paying twice for the same images.
+//!
+//! With a fixed seed, identical generation requests produce identical images. A
+//! [ResponseCache] given to [Client::with_cache](crate::Client::with_cache) stores the
+//! JSON responses of the generation endpoints of [Generate](crate::Generate), keyed by a
+//! SHA-256 hash of the url (engine id and endpoint), the canonical request body and the
+//! contents of uploaded files. Later identical requests are answered from the cache.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use stabilityai::{cache::ResponseCache, Client};
+//!
+//! let cache = ResponseCache::new("./cache")
+//!     .with_ttl(Duration::from_secs(7 * 24 * 3600))
+//!     .with_max_size(1024 * 1024 * 1024);
+//!
+//! let client = Client::new().with_cache(cache);
+//! ```
+//!
+//! Requests without a fixed, non-zero seed are never cached since the API picks a
+//! random seed for them. Responses answered from the cache have no headers and
+//! report zero attempts in [WithMeta](crate::WithMeta).
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use bytes::Bytes;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncReadExt;
+
+use crate::{
+    error::StabilityAIError,
+    transport::{FormPart, HttpRequest, RequestBody},
+};
+
+const ENTRY_EXTENSION: &str = "json";
+
+/// Directory of cached responses, see the [module](self) documentation.
+#[derive(Debug, Clone)]
+pub struct ResponseCache {
+    dir: PathBuf,
+    ttl: Option<Duration>,
+    max_size: Option<u64>,
+}
+
+impl ResponseCache {
+    /// Cache in the given directory, created on first write
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            ttl: None,
+            max_size: None,
+        }
+    }
+
+    /// Ignore and remove responses cached longer ago than `ttl`
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Maximum total size of cached responses in bytes, the oldest responses are
+    /// removed when a new response exceeds it
+    pub fn with_max_size(mut self, max_size: u64) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn ttl(&self) -> Option<Duration> {
+        self.ttl
+    }
+
+    pub fn max_size(&self) -> Option<u64> {
+        self.max_size
+    }
+
+    /// Remove all cached responses
+    pub async fn clear(&self) -> Result<(), StabilityAIError> {
+        for (path, _) in self.entries().await? {
+            remove(&path).await;
+        }
+        Ok(())
+    }
+
+    /// Cached response body, `None` when missing, expired or unreadable
+    pub(crate) async fn get(&self, key: &str) -> Option<Bytes> {
+        let path = self.entry_path(key);
+        let metadata = tokio::fs::metadata(&path).await.ok()?;
+        if self.is_expired(&metadata) {
+            remove(&path).await;
+            return None;
+        }
+
+        match tokio::fs::read(&path).await {
+            Ok(bytes) => Some(bytes.into()),
+            Err(e) => {
+                tracing::warn!("failed to read cached response {}: {e}", path.display());
+                None
+            }
+        }
+    }
+
+    /// Store the response body, then evict expired and oldest responses
+    pub(crate) async fn put(&self, key: &str, body: &[u8]) -> Result<(), StabilityAIError> {
+        let save_error = |e: std::io::Error, path: &Path| {
+            StabilityAIError::FileSaveError(format!("{e}, path: {}", path.display()))
+        };
+
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .map_err(|e| save_error(e, &self.dir))?;
+
+        // Written under a temporary name so that readers never see partial entries
+        let path = self.entry_path(key);
+        let partial = path.with_extension("partial");
+        tokio::fs::write(&partial, body)
+            .await
+            .map_err(|e| save_error(e, &partial))?;
+        tokio::fs::rename(&partial, &path)
+            .await
+            .map_err(|e| save_error(e, &path))?;
+
+        self.evict().await
+    }
+
+    async fn evict(&self) -> Result<(), StabilityAIError> {
+        let mut entries = Vec::new();
+        for (path, metadata) in self.entries().await? {
+            if self.is_expired(&metadata) {
+                remove(&path).await;
+            } else {
+                let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                entries.push((modified, metadata.len(), path));
+            }
+        }
+
+        let Some(max_size) = self.max_size else {
+            return Ok(());
+        };
+        entries.sort();
+        let mut size: u64 = entries.iter().map(|(_, len, _)| len).sum();
+        for (_, len, path) in entries {
+            if size <= max_size {
+                break;
+            }
+            remove(&path).await;
+            size -= len;
+        }
+        Ok(())
+    }
+
+    /// Cached responses with their metadata
+    async fn entries(&self) -> Result<Vec<(PathBuf, std::fs::Metadata)>, StabilityAIError> {
+        let read_error = |e: std::io::Error| {
+            StabilityAIError::FileReadError(format!("{e}, path: {}", self.dir.display()))
+        };
+
+        let mut dir = match tokio::fs::read_dir(&self.dir).await {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(read_error(e)),
+        };
+
+        let mut entries = Vec::new();
+        while let Some(entry) = dir.next_entry().await.map_err(read_error)? {
+            let path = entry.path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some(ENTRY_EXTENSION) {
+                continue;
+            }
+            if let Ok(metadata) = entry.metadata().await {
+                entries.push((path, metadata));
+            }
+        }
+        Ok(entries)
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.{ENTRY_EXTENSION}"))
+    }
+
+    fn is_expired(&self, metadata: &std::fs::Metadata) -> bool {
+        let (Some(ttl), Ok(modified)) = (self.ttl, metadata.modified()) else {
+            return false;
+        };
+        modified.elapsed().map(|age| age > ttl).unwrap_or(false)
+    }
+}
+
+/// Canonical hash of a generation request with a fixed seed, `None` for other requests
+pub(crate) async fn request_key(request: &HttpRequest) -> Result<Option<String>, StabilityAIError> {
+    let mut hasher = Sha256::new();
+    hasher.update(request.method.as_str());
+    hasher.update(b"\n");
+    hasher.update(&request.url);
+    hasher.update(b"\n");
+
+    match &request.body {
+        RequestBody::Empty => return Ok(None),
+        RequestBody::Json(bytes) => {
+            let Ok(value) = serde_json::from_slice::<Value>(bytes) else {
+                return Ok(None);
+            };
+            if !matches!(value.get("seed").and_then(Value::as_u64), Some(seed) if seed > 0) {
+                return Ok(None);
+            }
+            hash_json(&mut hasher, &value);
+        }
+        RequestBody::Multipart(form) => {
+            let seed = form
+                .text_value("seed")
+                .and_then(|seed| seed.parse::<u64>().ok());
+            if !matches!(seed, Some(seed) if seed > 0) {
+                return Ok(None);
+            }
+
+            let mut parts: Vec<_> = form.parts.iter().collect();
+            parts.sort_by(|(a, _), (b, _)| a.cmp(b));
+            for (name, part) in parts {
+                hash_str(&mut hasher, name);
+                match part {
+                    FormPart::Text(value) => {
+                        hasher.update(b"t");
+                        hash_str(&mut hasher, value);
+                    }
+                    FormPart::File(path) => {
+                        hasher.update(b"f");
+                        hash_file(&mut hasher, path).await?;
+                    }
+                }
+            }
+        }
+    }
+
+    let digest = hasher.finalize();
+    Ok(Some(
+        digest.iter().map(|byte| format!("{byte:02x}")).collect(),
+    ))
+}
+
+async fn remove(path: &Path) {
+    if let Err(e) = tokio::fs::remove_file(path).await {
+        tracing::warn!("failed to remove cached response {}: {e}", path.display());
+    }
+}
+
+/// Hash a string with its length, so that consecutive strings cannot be confused
+fn hash_str(hasher: &mut Sha256, value: &str) {
+    hasher.update((value.len() as u64).to_le_bytes());
+    hasher.update(value);
+}
+
+/// Hash a JSON value with object keys in sorted order
+fn hash_json(hasher: &mut Sha256, value: &Value) {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by_key(|(key, _)| *key);
+            hasher.update(b"{");
+            for (key, value) in entries {
+                hash_str(hasher, key);
+                hash_json(hasher, value);
+            }
+            hasher.update(b"}");
+        }
+        Value::Array(values) => {
+            hasher.update(b"[");
+            for value in values {
+                hash_json(hasher, value);
+            }
+            hasher.update(b"]");
+        }
+        Value::String(value) => {
+            hasher.update(b"s");
+            hash_str(hasher, value);
+        }
+        scalar => hash_str(hasher, &scalar.to_string()),
+    }
+}
+
+async fn hash_file(hasher: &mut Sha256, path: &Path) -> Result<(), StabilityAIError> {
+    let read_error = |e: std::io::Error| {
+        StabilityAIError::FileReadError(format!("{e}, path: {}", path.display()))
+    };
+
+    let mut file = tokio::fs::File::open(path).await.map_err(read_error)?;
+    let len = file.metadata().await.map_err(read_error)?.len();
+    hasher.update(len.to_le_bytes());
+
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer).await.map_err(read_error)?;
+        if read == 0 {
+            return Ok(());
+        }
+        hasher.update(&buffer[..read]);
+    }
+}
@@ -0,0 +1,354 @@
+//! Record HTTP interactions into a cassette file and replay them offline.
+//!
+//! [RecordingTransport] sends requests with another transport and records every
+//! request/response pair, with the `Authorization` header and other sensitive headers
+//! redacted. [ReplayTransport] answers requests from a saved [Cassette] without
+//! network access:
+//!
+//! ```no_run
+//! use stabilityai::{
+//!     cassette::{RecordingTransport, ReplayTransport},
+//!     transport::ReqwestTransport,
+//!     Client,
+//! };
+//!
+//! # tokio_test::block_on(async {
+//! // Record once against the API
+//! let recorder = RecordingTransport::new(ReqwestTransport::default());
+//! let client = Client::new().with_transport(recorder.clone());
+//! client.user().balance().await.unwrap();
+//! recorder.save("tests/cassettes/balance.json").unwrap();
+//!
+//! // Replay in tests
+//! let replay = ReplayTransport::from_file("tests/cassettes/balance.json").unwrap();
+//! let client = Client::new().with_transport(replay);
+//! client.user().balance().await.unwrap();
+//! # });
+//! ```
+//!
+//! Requests are matched on method, url and body. Files of multipart forms are
+//! matched by file name, so cassettes can be replayed on other machines.
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use base64::{engine::general_purpose, Engine as _};
+use bytes::Bytes;
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION},
+    StatusCode,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::StabilityAIError,
+    transport::{FormPart, HttpRequest, HttpResponse, HttpTransport, RequestBody},
+};
+
+/// Value recorded in place of sensitive header values
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Recorded interactions, serialized as a JSON file
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Cassette {
+    pub interactions: Vec<Interaction>,
+}
+
+/// A request and the response it received
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub url: String,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default)]
+    pub body: RecordedBody,
+}
+
+/// Body of [RecordedRequest]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordedBody {
+    #[default]
+    Empty,
+    Json(serde_json::Value),
+    Multipart(Vec<RecordedPart>),
+}
+
+/// Field of a recorded `multipart/form-data` body
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordedPart {
+    Text {
+        name: String,
+        value: String,
+    },
+    /// Only the file name is recorded, not the path or contents
+    File {
+        name: String,
+        file_name: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// Body when it is valid UTF-8
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    /// Base64 encoded body when it is binary, e.g. `image/png`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body_base64: Option<String>,
+}
+
+impl Cassette {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, StabilityAIError> {
+        let path = path.as_ref();
+        let contents = std::fs::read(path).map_err(|e| {
+            StabilityAIError::FileReadError(format!("{e}, path: {}", path.display()))
+        })?;
+        serde_json::from_slice(&contents).map_err(|e| {
+            StabilityAIError::FileReadError(format!(
+                "invalid cassette: {e}, path: {}",
+                path.display()
+            ))
+        })
+    }
+
+    /// Write the cassette as pretty printed JSON, creating parent directories
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), StabilityAIError> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| StabilityAIError::FileSaveError(e.to_string()))?;
+        }
+        let contents = serde_json::to_vec_pretty(self)
+            .map_err(|e| StabilityAIError::FileSaveError(e.to_string()))?;
+        std::fs::write(path, contents)
+            .map_err(|e| StabilityAIError::FileSaveError(format!("{e}, path: {}", path.display())))
+    }
+}
+
+impl RecordedRequest {
+    fn new(request: &HttpRequest) -> Self {
+        Self {
+            method: request.method.to_string(),
+            url: request.url.clone(),
+            headers: record_headers(&request.headers),
+            body: RecordedBody::new(&request.body),
+        }
+    }
+
+    /// Whether the recorded request was made for the same method, url and body
+    fn matches(&self, other: &RecordedRequest) -> bool {
+        self.method == other.method && self.url == other.url && self.body == other.body
+    }
+}
+
+impl RecordedBody {
+    pub(crate) fn new(body: &RequestBody) -> Self {
+        match body {
+            RequestBody::Empty => RecordedBody::Empty,
+            RequestBody::Json(bytes) => match serde_json::from_slice(bytes) {
+                Ok(value) => RecordedBody::Json(value),
+                Err(_) => RecordedBody::Json(String::from_utf8_lossy(bytes).into()),
+            },
+            RequestBody::Multipart(form) => RecordedBody::Multipart(
+                form.parts
+                    .iter()
+                    .map(|(name, part)| match part {
+                        FormPart::Text(value) => RecordedPart::Text {
+                            name: name.clone(),
+                            value: value.clone(),
+                        },
+                        FormPart::File(path) => RecordedPart::File {
+                            name: name.clone(),
+                            file_name: path
+                                .file_name()
+                                .map(|file_name| file_name.to_string_lossy().into_owned())
+                                .unwrap_or_default(),
+                        },
+                    })
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl RecordedResponse {
+    fn new(status: StatusCode, headers: &HeaderMap, body: &Bytes) -> Self {
+        let (body, body_base64) = match std::str::from_utf8(body) {
+            Ok(text) => (Some(text.to_string()), None),
+            Err(_) => (None, Some(general_purpose::STANDARD.encode(body))),
+        };
+
+        Self {
+            status: status.as_u16(),
+            headers: record_headers(headers),
+            body,
+            body_base64,
+        }
+    }
+
+    fn to_response(&self) -> Result<HttpResponse, StabilityAIError> {
+        let invalid = |e: String| StabilityAIError::Transport(format!("invalid cassette: {e}"));
+
+        let status = StatusCode::from_u16(self.status).map_err(|e| invalid(e.to_string()))?;
+        let body = match (&self.body, &self.body_base64) {
+            (_, Some(base64)) => general_purpose::STANDARD
+                .decode(base64)
+                .map_err(|e| invalid(e.to_string()))?,
+            (Some(text), None) => text.clone().into_bytes(),
+            (None, None) => Vec::new(),
+        };
+
+        let mut response = HttpResponse::new(status, body);
+        for (name, value) in &self.headers {
+            let name =
+                HeaderName::from_bytes(name.as_bytes()).map_err(|e| invalid(e.to_string()))?;
+            let value = HeaderValue::from_str(value).map_err(|e| invalid(e.to_string()))?;
+            response.headers.append(name, value);
+        }
+        Ok(response)
+    }
+}
+
+/// Header values joined by `, `, sensitive values are redacted
+fn record_headers(headers: &HeaderMap) -> BTreeMap<String, String> {
+    let mut recorded = BTreeMap::<String, String>::new();
+    for (name, value) in headers {
+        let value = if name == AUTHORIZATION || value.is_sensitive() {
+            REDACTED.to_string()
+        } else {
+            String::from_utf8_lossy(value.as_bytes()).into_owned()
+        };
+
+        recorded
+            .entry(name.as_str().to_string())
+            .and_modify(|existing| {
+                existing.push_str(", ");
+                existing.push_str(&value);
+            })
+            .or_insert(value);
+    }
+    recorded
+}
+
+/// Transport recording interactions of another transport, clones share the recording.
+#[derive(Debug, Clone)]
+pub struct RecordingTransport<T> {
+    transport: T,
+    cassette: Arc<Mutex<Cassette>>,
+}
+
+impl<T: HttpTransport> RecordingTransport<T> {
+    pub fn new(transport: T) -> Self {
+        Self {
+            transport,
+            cassette: Default::default(),
+        }
+    }
+
+    /// Interactions recorded so far
+    pub fn cassette(&self) -> Cassette {
+        self.cassette.lock().unwrap().clone()
+    }
+
+    /// Write interactions recorded so far to a cassette file
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), StabilityAIError> {
+        self.cassette().save(path)
+    }
+}
+
+#[async_trait::async_trait]
+impl<T: HttpTransport> HttpTransport for RecordingTransport<T> {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, StabilityAIError> {
+        let recorded_request = RecordedRequest::new(&request);
+
+        let response = self.transport.send(request).await?;
+        let body = response.body.bytes().await?;
+
+        self.cassette
+            .lock()
+            .unwrap()
+            .interactions
+            .push(Interaction {
+                request: recorded_request,
+                response: RecordedResponse::new(response.status, &response.headers, &body),
+            });
+
+        Ok(HttpResponse {
+            status: response.status,
+            headers: response.headers,
+            body: body.into(),
+        })
+    }
+}
+
+/// Transport answering requests from a [Cassette], clones share the replay progress.
+///
+/// Every interaction is replayed once, for the first matching request.
+#[derive(Debug, Clone)]
+pub struct ReplayTransport {
+    interactions: Arc<Mutex<Vec<Option<Interaction>>>>,
+}
+
+impl ReplayTransport {
+    pub fn new(cassette: Cassette) -> Self {
+        Self {
+            interactions: Arc::new(Mutex::new(
+                cassette.interactions.into_iter().map(Some).collect(),
+            )),
+        }
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, StabilityAIError> {
+        Ok(Self::new(Cassette::from_file(path)?))
+    }
+
+    /// Number of recorded interactions not yet replayed
+    pub fn remaining(&self) -> usize {
+        self.interactions
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|interaction| interaction.is_some())
+            .count()
+    }
+}
+
+#[async_trait::async_trait]
+impl HttpTransport for ReplayTransport {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, StabilityAIError> {
+        let recorded_request = RecordedRequest::new(&request);
+
+        let mut interactions = self.interactions.lock().unwrap();
+        let interaction = interactions
+            .iter_mut()
+            .find(|interaction| {
+                interaction
+                    .as_ref()
+                    .map(|interaction| interaction.request.matches(&recorded_request))
+                    .unwrap_or(false)
+            })
+            .and_then(Option::take)
+            .ok_or_else(|| {
+                StabilityAIError::Transport(format!(
+                    "no recorded interaction left for {} {}",
+                    recorded_request.method, recorded_request.url
+                ))
+            })?;
+
+        interaction.response.to_response()
+    }
+}
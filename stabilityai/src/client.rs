@@ -1,9 +1,37 @@
-use reqwest::header::HeaderMap;
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+use bytes::Bytes;
+use reqwest::{
+    header::{HeaderMap, HeaderValue, ACCEPT, AUTHORIZATION},
+    Method, StatusCode,
+};
 use serde::{de::DeserializeOwned, Serialize};
 
 use crate::{
-    error::{map_deserialization_error, ApiError, StabilityAIError},
+    budget::{BudgetGuard, Reservation},
+    cache::{request_key, ResponseCache},
+    credentials::{ApiKey, CredentialProvider, API_KEY_ENV},
+    dedup::InFlight,
+    error::{map_deserialization_error, ApiError, StabilityAIError, UnexpectedResponse},
     generate::Generate,
+    key_pool::ApiKeyPool,
+    ledger::{LedgerEntry, LedgerSink},
+    meta::WithMeta,
+    options::RequestOptions,
+    preview::RequestPreview,
+    pricing::PricingTable,
+    rate_limit::{RateLimiter, RatePermit},
+    retry::{DefaultRetryPolicy, RetryPolicy, ServerBackoff},
+    streaming::{decode_artifacts, ImageSink, StreamedImage},
+    transport::{HttpRequest, HttpTransport, MultipartForm, ReqwestTransport, ResponseBody},
+    types::{Artifacts, BalanceResponseBody},
     user::User,
     Engines,
 };
@@ -11,13 +39,21 @@ use crate::{
 #[derive(Debug, Clone)]
 /// Client is a container of configurations to make API calls.
 pub struct Client {
-    http_client: reqwest::Client,
-    api_key: String,
+    transport: Arc<dyn HttpTransport>,
+    api_key: ApiKey,
+    credentials: Option<Arc<dyn CredentialProvider>>,
     api_base: String,
     organization: String,
     client_id: Option<String>,
     client_version: Option<String>,
     backoff: backoff::ExponentialBackoff,
+    retry_policy: Arc<dyn RetryPolicy>,
+    rate_limiter: Option<RateLimiter>,
+    key_pool: Option<ApiKeyPool>,
+    cache: Option<ResponseCache>,
+    in_flight: Option<InFlight>,
+    budget: Option<BudgetGuard>,
+    ledger: Option<Arc<dyn LedgerSink>>,
 }
 
 /// Default v1 API base url
@@ -28,16 +64,32 @@ pub const ORGANIZATION_HEADER: &str = "Organization";
 pub const CLIENT_ID_HEADER: &str = "Stability-Client-ID";
 /// Name for client version header
 pub const CLIENT_VERSION_HEADER: &str = "Stability-Client-Version";
+/// Name for seed response header, only present for `image/png` responses
+pub const SEED_HEADER: &str = "Seed";
+/// Name for finish reason response header, only present for `image/png` responses
+pub const FINISH_REASON_HEADER: &str = "Finish-Reason";
+
+const IMAGE_PNG: &str = "image/png";
 
 impl Default for Client {
     /// Create client with default [API_BASE] url and default API key from STABILITY_API_KEY env var
     fn default() -> Self {
         Self {
-            http_client: reqwest::Client::new(),
+            transport: Arc::new(ReqwestTransport::default()),
             api_base: API_BASE.to_string(),
-            api_key: std::env::var("STABILITY_API_KEY").unwrap_or_else(|_| "".to_string()),
+            api_key: std::env::var(API_KEY_ENV)
+                .map(ApiKey::from)
+                .unwrap_or_default(),
+            credentials: None,
             organization: Default::default(),
             backoff: Default::default(),
+            retry_policy: Arc::new(DefaultRetryPolicy::default()),
+            rate_limiter: None,
+            key_pool: None,
+            cache: None,
+            in_flight: None,
+            budget: None,
+            ledger: None,
             client_id: None,
             client_version: None,
         }
@@ -54,14 +106,31 @@ impl Client {
     ///
     /// [client]: reqwest::Client
     pub fn with_http_client(mut self, http_client: reqwest::Client) -> Self {
-        self.http_client = http_client;
+        self.transport = Arc::new(ReqwestTransport::new(http_client));
+        self
+    }
+
+    /// Provide your own [transport] to send HTTP requests with,
+    /// for example [InMemoryTransport] in tests.
+    ///
+    /// [transport]: HttpTransport
+    /// [InMemoryTransport]: crate::transport::InMemoryTransport
+    pub fn with_transport<T: HttpTransport + 'static>(mut self, transport: T) -> Self {
+        self.transport = Arc::new(transport);
         self
     }
 
     /// To use a different API key different from default STABILITY_API_KEY env var
-    pub fn with_api_key<S: Into<String>>(mut self, api_key: S) -> Self {
+    pub fn with_api_key<K: Into<ApiKey>>(mut self, api_key: K) -> Self {
         self.api_key = api_key.into();
-        self
+        self.renew_in_flight()
+    }
+
+    /// Ask the [CredentialProvider] for the API key before every attempt,
+    /// instead of using a fixed API key
+    pub fn with_credentials<P: CredentialProvider + 'static>(mut self, credentials: P) -> Self {
+        self.credentials = Some(Arc::new(credentials));
+        self.renew_in_flight()
     }
 
     /// To use a different organization id other than default
@@ -76,17 +145,101 @@ impl Client {
         self
     }
 
-    /// Exponential backoff for retrying rate limited requests.
+    /// Value of the [CLIENT_ID_HEADER] sent with every request
+    pub fn with_client_id<S: Into<String>>(mut self, client_id: S) -> Self {
+        self.client_id = Some(client_id.into());
+        self
+    }
+
+    /// Value of the [CLIENT_VERSION_HEADER] sent with every request
+    pub fn with_client_version<S: Into<String>>(mut self, client_version: S) -> Self {
+        self.client_version = Some(client_version.into());
+        self
+    }
+
+    /// Exponential backoff for retrying requests which failed transiently,
+    /// see [Client::with_retry_policy].
     pub fn with_backoff(mut self, backoff: backoff::ExponentialBackoff) -> Self {
         self.backoff = backoff;
         self
     }
 
+    /// To decide which failures are retried, different from default [DefaultRetryPolicy]
+    pub fn with_retry_policy<P: RetryPolicy + 'static>(mut self, retry_policy: P) -> Self {
+        self.retry_policy = Arc::new(retry_policy);
+        self
+    }
+
+    /// Limit the rate and concurrency of requests on the client side, given either a
+    /// [RateLimit](crate::rate_limit::RateLimit) or a [RateLimiter] shared with other clients.
+    ///
+    /// The limiter is shared by all clones of this client. Every attempt, including
+    /// retries, waits for the limiter before being sent.
+    pub fn with_rate_limit<L: Into<RateLimiter>>(mut self, rate_limit: L) -> Self {
+        self.rate_limiter = Some(rate_limit.into());
+        self
+    }
+
+    /// Send requests with keys of the [ApiKeyPool] instead of the single API key,
+    /// failing over to the next key on 401, 402 and 429 responses.
+    ///
+    /// The pool is shared by all clones of this client.
+    pub fn with_api_key_pool(mut self, key_pool: ApiKeyPool) -> Self {
+        self.key_pool = Some(key_pool);
+        self.renew_in_flight()
+    }
+
+    /// Answer generation requests with a fixed seed from the [ResponseCache], and store
+    /// the responses of the API into it.
+    ///
+    /// Only JSON responses of [Generate](crate::Generate) are cached, not `image/png` ones.
+    pub fn with_cache(mut self, cache: ResponseCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Coalesce identical generation requests with a fixed seed made concurrently by
+    /// this client and its clones: one HTTP call is made, and every caller receives
+    /// a clone of its [Artifacts].
+    ///
+    /// Requests are identical for the same organization, engine, endpoint, request body
+    /// and input file contents. Clones given other credentials with [Client::with_api_key],
+    /// [Client::with_credentials] or [Client::with_api_key_pool] do not share calls.
+    pub fn with_deduplication(mut self, enabled: bool) -> Self {
+        self.in_flight = enabled.then(InFlight::default);
+        self
+    }
+
+    /// Stop sharing calls in flight with clones, which were billed to other credentials
+    fn renew_in_flight(mut self) -> Self {
+        if self.in_flight.is_some() {
+            self.in_flight = Some(InFlight::default());
+        }
+        self
+    }
+
+    /// Refuse generation requests which would cross the budget or minimum balance of
+    /// the [BudgetGuard], checking the balance of the account first when configured.
+    ///
+    /// The credits spent are shared by all clones of this client and of the guard.
+    pub fn with_budget(mut self, budget: BudgetGuard) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Record every API call made by this client and its clones into the [LedgerSink],
+    /// see [ledger](crate::ledger)
+    pub fn with_ledger<L: LedgerSink + 'static>(mut self, ledger: L) -> Self {
+        self.ledger = Some(Arc::new(ledger));
+        self
+    }
+
     pub fn api_base(&self) -> &str {
         &self.api_base
     }
 
-    pub fn api_key(&self) -> &str {
+    /// The fixed API key, redacted when formatted
+    pub fn api_key(&self) -> &ApiKey {
         &self.api_key
     }
 
@@ -120,132 +273,641 @@ impl Client {
     // API groups
 
     /// To call [User] group related APIs using this client.
-    pub fn user(&self) -> User {
+    pub fn user(&self) -> User<'_> {
         User::new(self)
     }
 
     /// To call [Engines] group related APIs using this client.
-    pub fn engines(&self) -> Engines {
+    pub fn engines(&self) -> Engines<'_> {
         Engines::new(self)
     }
 
     /// To call [Generate] group related APIs using this client.
-    pub fn generate<S: Into<String>>(&self, engine_id: S) -> Generate<S>
+    pub fn generate<S>(&self, engine_id: S) -> Generate<'_, S>
     where
-        S: std::fmt::Display,
+        S: Into<String> + std::fmt::Display,
     {
         Generate::new(self, engine_id)
     }
 
+    /// Create a request to {path} with client headers overridden by headers of the options,
+    /// authorization is added when it is sent
+    fn request(
+        &self,
+        method: Method,
+        path: &str,
+        options: &RequestOptions,
+    ) -> Result<HttpRequest, StabilityAIError> {
+        let mut request = HttpRequest::new(method, format!("{}{path}", self.api_base()));
+        request.headers = self.headers();
+        request.headers.extend(options.headers().clone());
+        request.upload_progress = options.upload_progress().cloned();
+
+        if let Some(organization) = options.organization() {
+            let organization = HeaderValue::from_str(organization).map_err(|e| {
+                StabilityAIError::InvalidArgument(format!("invalid organization: {e}"))
+            })?;
+            request.headers.insert(ORGANIZATION_HEADER, organization);
+        }
+
+        Ok(request)
+    }
+
     /// Make a GET request to {path} and deserialize the response body
-    pub(crate) async fn get<O>(&self, path: &str) -> Result<O, StabilityAIError>
+    pub(crate) async fn get<O>(
+        &self,
+        path: &str,
+        options: &RequestOptions,
+    ) -> Result<WithMeta<O>, StabilityAIError>
     where
         O: DeserializeOwned,
     {
-        let request_maker = || async {
-            Ok(self
-                .http_client
-                .get(format!("{}{path}", self.api_base()))
-                .bearer_auth(self.api_key())
-                .headers(self.headers())
-                .build()?)
-        };
+        self.execute(self.request(Method::GET, path, options)?, options)
+            .await
+    }
 
-        self.execute(request_maker).await
+    /// Make a POST request to {path} and deserialize the generated images
+    pub(crate) async fn post<I>(
+        &self,
+        path: &str,
+        request: I,
+        options: &RequestOptions,
+    ) -> Result<WithMeta<Artifacts>, StabilityAIError>
+    where
+        I: Serialize,
+    {
+        let request = self.request(Method::POST, path, options)?.json(&request)?;
+        self.execute_generation(request, options).await
+    }
+
+    /// POST a form at {path} and deserialize the generated images
+    pub(crate) async fn post_form<F>(
+        &self,
+        path: &str,
+        form: F,
+        options: &RequestOptions,
+    ) -> Result<WithMeta<Artifacts>, StabilityAIError>
+    where
+        F: Into<MultipartForm>,
+    {
+        let request = self
+            .request(Method::POST, path, options)?
+            .multipart(form.into());
+        self.execute_generation(request, options).await
+    }
+
+    /// Make a POST request to {path} with `Accept: image/png` and return the raw response
+    pub(crate) async fn post_binary<I>(
+        &self,
+        path: &str,
+        request: I,
+        options: &RequestOptions,
+    ) -> Result<WithMeta<Bytes>, StabilityAIError>
+    where
+        I: Serialize,
+    {
+        let mut request = self.request(Method::POST, path, options)?.json(&request)?;
+        request
+            .headers
+            .insert(ACCEPT, HeaderValue::from_static(IMAGE_PNG));
+
+        self.execute_raw(request, options).await
+    }
+
+    /// POST a form at {path} with `Accept: image/png` and return the raw response
+    pub(crate) async fn post_form_binary<F>(
+        &self,
+        path: &str,
+        form: F,
+        options: &RequestOptions,
+    ) -> Result<WithMeta<Bytes>, StabilityAIError>
+    where
+        F: Into<MultipartForm>,
+    {
+        let mut request = self
+            .request(Method::POST, path, options)?
+            .multipart(form.into());
+        request
+            .headers
+            .insert(ACCEPT, HeaderValue::from_static(IMAGE_PNG));
+
+        self.execute_raw(request, options).await
     }
 
-    /// Make a POST request to {path} and deserialize the response body
-    pub(crate) async fn post<I, O>(&self, path: &str, request: I) -> Result<O, StabilityAIError>
+    /// Make a POST request to {path} and decode the images of the response into the sink
+    pub(crate) async fn post_artifacts<I, S>(
+        &self,
+        path: &str,
+        request: I,
+        options: &RequestOptions,
+        sink: S,
+    ) -> Result<WithMeta<Vec<StreamedImage<S::Output>>>, StabilityAIError>
     where
         I: Serialize,
+        S: ImageSink,
+    {
+        let request = self.request(Method::POST, path, options)?.json(&request)?;
+        self.execute_artifacts(request, options, sink).await
+    }
+
+    /// Make a POST request of a form at {path} and decode the images of the response
+    /// into the sink
+    pub(crate) async fn post_form_artifacts<F, S>(
+        &self,
+        path: &str,
+        form: F,
+        options: &RequestOptions,
+        sink: S,
+    ) -> Result<WithMeta<Vec<StreamedImage<S::Output>>>, StabilityAIError>
+    where
+        F: Into<MultipartForm>,
+        S: ImageSink,
+    {
+        let request = self
+            .request(Method::POST, path, options)?
+            .multipart(form.into());
+        self.execute_artifacts(request, options, sink).await
+    }
+
+    /// The POST request to {path} with a JSON body, without sending it
+    pub(crate) fn preview<I>(
+        &self,
+        path: &str,
+        request: I,
+        options: &RequestOptions,
+    ) -> Result<RequestPreview, StabilityAIError>
+    where
+        I: Serialize,
+    {
+        let request = self.request(Method::POST, path, options)?.json(&request)?;
+        Ok(RequestPreview::new(request))
+    }
+
+    /// The POST request of a form at {path}, without sending it
+    pub(crate) fn preview_form<F>(
+        &self,
+        path: &str,
+        form: F,
+        options: &RequestOptions,
+    ) -> Result<RequestPreview, StabilityAIError>
+    where
+        F: Into<MultipartForm>,
+    {
+        let request = self
+            .request(Method::POST, path, options)?
+            .multipart(form.into());
+        Ok(RequestPreview::new(request))
+    }
+
+    /// Execute a HTTP request and deserialize the JSON response body
+    async fn execute<O>(
+        &self,
+        request: HttpRequest,
+        options: &RequestOptions,
+    ) -> Result<WithMeta<O>, StabilityAIError>
+    where
         O: DeserializeOwned,
     {
-        let request_maker = || async {
-            Ok(self
-                .http_client
-                .post(format!("{}{path}", self.api_base()))
-                .bearer_auth(self.api_key())
-                .headers(self.headers())
-                .json(&request)
-                .build()?)
+        let response = self.execute_raw(request, options).await?;
+
+        let body: O = serde_json::from_slice(response.body.as_ref())
+            .map_err(|e| map_deserialization_error(e, response.body.as_ref()))?;
+        Ok(response.map(|_| body))
+    }
+
+    /// Execute a generation request, awaiting an identical request in flight instead
+    /// when deduplication is enabled
+    async fn execute_generation(
+        &self,
+        request: HttpRequest,
+        options: &RequestOptions,
+    ) -> Result<WithMeta<Artifacts>, StabilityAIError> {
+        let Some(in_flight) = &self.in_flight else {
+            return self.execute_cached(request, options).await;
+        };
+        let Some(key) = request_key(&request).await? else {
+            return self.execute_cached(request, options).await;
         };
 
-        self.execute(request_maker).await
+        // Requests of different organizations are billed separately
+        let organization = request
+            .headers
+            .get(ORGANIZATION_HEADER)
+            .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned())
+            .unwrap_or_default();
+        let key = format!("{organization}/{key}");
+
+        // The shared call runs until its last caller gives up, every caller
+        // waits for it within its own timeout and cancellation
+        let client = self.clone();
+        let shared_options = options.without_limits();
+        let call = async move { client.execute_cached(request, &shared_options).await };
+        // Boxed to keep the future of every caller small
+        options.run(in_flight.run(key, Box::pin(call))).await
     }
 
-    /// POST a form at {path} and deserialize the response body
-    pub(crate) async fn post_form<O, F>(&self, path: &str, form: F) -> Result<O, StabilityAIError>
+    /// Execute a HTTP request through the response cache of the client, if any
+    async fn execute_cached<O>(
+        &self,
+        request: HttpRequest,
+        options: &RequestOptions,
+    ) -> Result<WithMeta<O>, StabilityAIError>
     where
         O: DeserializeOwned,
-        reqwest::multipart::Form: async_convert::TryFrom<F, Error = StabilityAIError>,
-        F: Clone,
     {
-        let request_maker = || async {
-            Ok(self
-                .http_client
-                .post(format!("{}{path}", self.api_base()))
-                .bearer_auth(self.api_key())
-                .headers(self.headers())
-                .multipart(async_convert::TryInto::try_into(form.clone()).await?)
-                .build()?)
+        let started = Instant::now();
+        let (cache, key) = match &self.cache {
+            Some(cache) => match request_key(&request).await? {
+                Some(key) => (cache, key),
+                None => return self.execute(request, options).await,
+            },
+            None => return self.execute(request, options).await,
+        };
+
+        if let Some(bytes) = cache.get(&key).await {
+            match serde_json::from_slice(bytes.as_ref()) {
+                Ok(body) => {
+                    return Ok(WithMeta {
+                        body,
+                        status: StatusCode::OK,
+                        headers: HeaderMap::new(),
+                        latency: started.elapsed(),
+                        attempts: 0,
+                        api_key_name: None,
+                    })
+                }
+                Err(e) => tracing::warn!("Ignoring invalid cached response {key}: {e}"),
+            }
+        }
+
+        let response = self.execute_raw(request, options).await?;
+        let body: O = serde_json::from_slice(response.body.as_ref())
+            .map_err(|e| map_deserialization_error(e, response.body.as_ref()))?;
+        if let Err(e) = cache.put(&key, response.body.as_ref()).await {
+            tracing::warn!("Failed to cache response {key}: {e}");
+        }
+        Ok(response.map(|_| body))
+    }
+
+    /// Execute a HTTP request and retry on transient failures
+    ///
+    /// On success the raw body is returned as is with response metadata.
+    /// The timeout and cancellation of the options apply to all attempts together.
+    async fn execute_raw(
+        &self,
+        request: HttpRequest,
+        options: &RequestOptions,
+    ) -> Result<WithMeta<Bytes>, StabilityAIError> {
+        let started = Instant::now();
+        let entry = self.ledger_entry(&request);
+        let call = self.send(
+            request,
+            options,
+            entry.as_ref(),
+            |body, permit| async move {
+                // Held until the response body is read
+                let _permit = permit;
+                body.bytes().await
+            },
+        );
+        let result = options.run(call).await;
+
+        if let Some(entry) = entry {
+            let entry = entry.into_inner().unwrap();
+            let entry = match &result {
+                Ok(response) => entry
+                    .finish(started.elapsed(), Ok(response.status))
+                    .with_outputs(&response.headers, response.body.as_ref()),
+                Err(e) => entry.finish(started.elapsed(), Err(e)),
+            };
+            self.record(entry).await;
+        }
+        result
+    }
+
+    /// Execute a HTTP request expecting generated images as JSON artifacts, and decode
+    /// every image into the sink as the response body is received
+    pub(crate) async fn execute_artifacts<S: ImageSink>(
+        &self,
+        request: HttpRequest,
+        options: &RequestOptions,
+        sink: S,
+    ) -> Result<WithMeta<Vec<StreamedImage<S::Output>>>, StabilityAIError> {
+        let started = Instant::now();
+        let entry = self.ledger_entry(&request);
+        let call = async {
+            let response = self
+                .send(
+                    request,
+                    options,
+                    entry.as_ref(),
+                    |body, permit| async move { Ok((body, permit)) },
+                )
+                .await?;
+
+            let (body, _permit) = response.body;
+            let images = decode_artifacts(body, sink).await?;
+            Ok(WithMeta {
+                body: images,
+                status: response.status,
+                headers: response.headers,
+                latency: started.elapsed(),
+                attempts: response.attempts,
+                api_key_name: response.api_key_name,
+            })
         };
+        let result = options.run(call).await;
+
+        if let Some(entry) = entry {
+            let mut entry = entry.into_inner().unwrap();
+            if let Ok(response) = &result {
+                for image in &response.body {
+                    entry.seeds.push(image.seed);
+                    entry.finish_reasons.push(image.finish_reason.clone());
+                }
+            }
+            let outcome = result.as_ref().map(|response| response.status);
+            self.record(entry.finish(started.elapsed(), outcome)).await;
+        }
+        result
+    }
 
-        self.execute(request_maker).await
+    /// Ledger entry of a request about to be sent, `None` without a ledger
+    ///
+    /// Every attempt of the request updates the entry with its organization and key.
+    fn ledger_entry(&self, request: &HttpRequest) -> Option<Mutex<LedgerEntry>> {
+        self.ledger.as_ref()?;
+        let estimated_credits = match &self.budget {
+            Some(budget) => budget.estimate(request),
+            None => PricingTable::default().estimate_request(request),
+        };
+        Some(Mutex::new(LedgerEntry::new(
+            request,
+            &self.api_base,
+            estimated_credits,
+        )))
     }
 
-    /// Execute a HTTP request and retry on rate limit
+    /// Record a completed call into the ledger, logging failures of the ledger
+    async fn record(&self, entry: LedgerEntry) {
+        if let Some(ledger) = &self.ledger {
+            if let Err(e) = ledger.record(&entry).await {
+                tracing::warn!("Failed to record API call into the ledger: {e}");
+            }
+        }
+    }
+
+    /// Send a HTTP request within the budget of the client, see [Client::send_with_retry]
+    async fn send<T, F, Fut>(
+        &self,
+        request: HttpRequest,
+        options: &RequestOptions,
+        entry: Option<&Mutex<LedgerEntry>>,
+        read_body: F,
+    ) -> Result<WithMeta<T>, StabilityAIError>
+    where
+        F: Fn(ResponseBody, Option<RatePermit>) -> Fut,
+        Fut: Future<Output = Result<T, StabilityAIError>>,
+    {
+        let reservation = self.reserve_budget(&request).await?;
+        self.send_with_retry(request, options, reservation.as_ref(), entry, read_body)
+            .await
+    }
+
+    /// Send a HTTP request, retrying on transient failures, and read the body of the
+    /// successful response with `read_body`
     ///
-    /// request_maker serves one purpose: to be able to create request again
-    /// to retry API call after getting rate limited. request_maker is async because
-    /// reqwest::multipart::Form is created by async calls to read files for uploads.
-    async fn execute<O, M, Fut>(&self, request_maker: M) -> Result<O, StabilityAIError>
+    /// The request is cloned for every attempt, files of multipart forms
+    /// are read again by the transport for each of them. Network errors while
+    /// reading the body are retried like errors while sending the request.
+    async fn send_with_retry<T, F, Fut>(
+        &self,
+        request: HttpRequest,
+        options: &RequestOptions,
+        reservation: Option<&Reservation>,
+        entry: Option<&Mutex<LedgerEntry>>,
+        read_body: F,
+    ) -> Result<WithMeta<T>, StabilityAIError>
     where
-        O: DeserializeOwned,
-        M: Fn() -> Fut,
-        Fut: core::future::Future<Output = Result<reqwest::Request, StabilityAIError>>,
+        F: Fn(ResponseBody, Option<RatePermit>) -> Fut,
+        Fut: Future<Output = Result<T, StabilityAIError>>,
     {
-        let client = self.http_client.clone();
-
-        backoff::future::retry(self.backoff.clone(), || async {
-            let request = request_maker().await.map_err(backoff::Error::Permanent)?;
-            let response = client
-                .execute(request)
-                .await
-                .map_err(StabilityAIError::Reqwest)
-                .map_err(backoff::Error::Permanent)?;
-
-            let status = response.status();
-            let bytes = response
-                .bytes()
-                .await
-                .map_err(StabilityAIError::Reqwest)
-                .map_err(backoff::Error::Permanent)?;
-
-            // Deserialize response body from either error object or actual response object
-            if !status.is_success() {
-                let api_error: ApiError = serde_json::from_slice(bytes.as_ref())
-                    .map_err(|e| map_deserialization_error(e, bytes.as_ref()))
+        let started = Instant::now();
+        let attempts = AtomicU32::new(0);
+        let retry_policy = self.retry_policy.as_ref();
+        // Delay requested by the server with the last retryable status
+        let server_delay = Mutex::new(None);
+
+        // Network failures are retried according to retry policy
+        let classify = |err: StabilityAIError| {
+            if retry_policy.is_retryable_error(&err) {
+                tracing::warn!("Retrying after network error: {err}");
+                backoff::Error::Transient {
+                    err,
+                    retry_after: None,
+                }
+            } else {
+                backoff::Error::Permanent(err)
+            }
+        };
+
+        let backoff = ServerBackoff::new(self.backoff.clone(), &server_delay);
+        let retry = backoff::future::retry(backoff, || async {
+            // Keys of the pool which failed are replaced without waiting for the
+            // backoff, trying each key at most once per attempt
+            let pool_size = self.key_pool.as_ref().map_or(0, |pool| pool.keys().len());
+            let mut failovers = 0;
+            loop {
+                attempts.fetch_add(1, Ordering::Relaxed);
+                let mut request = request.clone();
+
+                let lease = match &self.key_pool {
+                    Some(key_pool) => Some(
+                        key_pool
+                            .acquire()
+                            .await
+                            .map_err(backoff::Error::Permanent)?,
+                    ),
+                    None => None,
+                };
+                let api_key = match &lease {
+                    Some(lease) => {
+                        // Organization of the options takes precedence over the pooled key
+                        let organization = lease
+                            .key
+                            .organization()
+                            .filter(|_| options.organization().is_none());
+                        if let Some(organization) = organization {
+                            let organization = HeaderValue::from_str(organization)
+                                .map_err(|e| {
+                                    StabilityAIError::InvalidArgument(format!(
+                                        "invalid organization: {e}"
+                                    ))
+                                })
+                                .map_err(backoff::Error::Permanent)?;
+                            request.headers.insert(ORGANIZATION_HEADER, organization);
+                        }
+                        lease.key.api_key().clone()
+                    }
+                    None => match &self.credentials {
+                        Some(credentials) => credentials
+                            .api_key()
+                            .await
+                            .map_err(backoff::Error::Permanent)?,
+                        None => self.api_key.clone(),
+                    },
+                };
+
+                let mut authorization = HeaderValue::from_str(&api_key.bearer())
+                    .map_err(|e| StabilityAIError::InvalidArgument(format!("invalid api key: {e}")))
                     .map_err(backoff::Error::Permanent)?;
+                authorization.set_sensitive(true);
+                request.headers.insert(AUTHORIZATION, authorization);
+
+                if let Some(entry) = entry {
+                    let api_key = match &lease {
+                        Some(lease) => lease.key.name().to_string(),
+                        None => api_key.redacted(),
+                    };
+                    entry.lock().unwrap().attempt(&request, api_key);
+                }
 
-                if status.as_u16() == 429 {
-                    // Rate limited retry...
-                    tracing::warn!("Rate limited: {}", api_error.message);
-                    return Err(backoff::Error::Transient {
-                        err: StabilityAIError::ApiError(api_error),
-                        retry_after: None,
+                let permit = match &self.rate_limiter {
+                    Some(rate_limiter) => Some(rate_limiter.acquire().await),
+                    None => None,
+                };
+
+                if let Some(reservation) = reservation {
+                    reservation.sending();
+                }
+                let response = self.transport.send(request).await;
+                if let Some(reservation) = reservation {
+                    // Rejected requests and requests which were never sent cost nothing
+                    reservation.sent(match &response {
+                        Ok(response) => !response.status.is_client_error(),
+                        Err(StabilityAIError::Reqwest(e)) => !e.is_connect(),
+                        Err(StabilityAIError::FileReadError(_)) => false,
+                        Err(_) => true,
                     });
-                } else {
-                    return Err(backoff::Error::Permanent(StabilityAIError::ApiError(
-                        api_error,
-                    )));
                 }
+                let response = response.map_err(classify)?;
+
+                let status = response.status;
+                let headers = response.headers;
+
+                // Deserialize response body from error object on failure
+                if !status.is_success() {
+                    let bytes = response.body.bytes().await.map_err(classify)?;
+                    let error = match serde_json::from_slice::<ApiError>(bytes.as_ref()) {
+                        Ok(api_error) => StabilityAIError::ApiError(Box::new(ApiError {
+                            status,
+                            headers: headers.clone(),
+                            retryable: retry_policy.is_retryable_status(status),
+                            ..api_error
+                        })),
+                        Err(_) => {
+                            StabilityAIError::UnexpectedResponse(Box::new(UnexpectedResponse::new(
+                                status,
+                                headers.clone(),
+                                bytes.as_ref(),
+                                retry_policy.is_retryable_status(status),
+                            )))
+                        }
+                    };
+
+                    let retry_after = retry_policy.retry_after(&headers);
+
+                    if let (Some(key_pool), Some(lease)) = (&self.key_pool, &lease) {
+                        // Another key of the pool can serve the request right away
+                        if key_pool.report_failure(lease, status, retry_after)
+                            && failovers + 1 < pool_size
+                        {
+                            tracing::warn!(
+                                "Retrying with another API key after status {status}: {error}"
+                            );
+                            failovers += 1;
+                            continue;
+                        }
+                        // No key left to retry with
+                        if key_pool.keys().is_empty() {
+                            return Err(backoff::Error::Permanent(error));
+                        }
+                    }
+
+                    if retry_policy.is_retryable_status(status) {
+                        tracing::warn!("Retrying after status {status}: {error}");
+                        *server_delay.lock().unwrap() = retry_after;
+                        return Err(backoff::Error::Transient {
+                            err: error,
+                            retry_after: None,
+                        });
+                    } else {
+                        return Err(backoff::Error::Permanent(error));
+                    }
+                }
+
+                let body = read_body(response.body, permit).await.map_err(classify)?;
+
+                return Ok(WithMeta {
+                    body,
+                    status,
+                    headers,
+                    latency: started.elapsed(),
+                    attempts: attempts.load(Ordering::Relaxed),
+                    api_key_name: lease.map(|lease| lease.key.name().to_string()),
+                });
             }
+        });
+
+        retry.await
+    }
+
+    /// Count the estimated credits of a generation request with the budget guard,
+    /// checking the balance of the account first when the guard requires it
+    async fn reserve_budget(
+        &self,
+        request: &HttpRequest,
+    ) -> Result<Option<Reservation>, StabilityAIError> {
+        let Some(budget) = &self.budget else {
+            return Ok(None);
+        };
+        let Some(estimated) = budget.estimate(request) else {
+            return Ok(None);
+        };
+
+        let balance = if budget.checks_balance(estimated) {
+            // Balance of the organization the request is billed to, the balance
+            // request itself is not counted
+            let mut balance = self.request(Method::GET, "/user/balance", &RequestOptions::new())?;
+            if let Some(organization) = request.headers.get(ORGANIZATION_HEADER) {
+                balance
+                    .headers
+                    .insert(ORGANIZATION_HEADER, organization.clone());
+            }
+            let started = Instant::now();
+            let entry = self.ledger_entry(&balance);
+            let result = self
+                .send_with_retry(
+                    balance,
+                    &RequestOptions::new(),
+                    None,
+                    entry.as_ref(),
+                    |body, _| body.bytes(),
+                )
+                .await;
+            if let Some(entry) = entry {
+                let entry = entry.into_inner().unwrap();
+                let outcome = result.as_ref().map(|response| response.status);
+                self.record(entry.finish(started.elapsed(), outcome)).await;
+            }
+            let response = result?;
+            let balance: BalanceResponseBody = serde_json::from_slice(response.body.as_ref())
+                .map_err(|e| map_deserialization_error(e, response.body.as_ref()))?;
+            Some(balance.credits)
+        } else {
+            None
+        };
 
-            let response: O = serde_json::from_slice(bytes.as_ref())
-                .map_err(|e| map_deserialization_error(e, bytes.as_ref()))
-                .map_err(backoff::Error::Permanent)?;
-            Ok(response)
-        })
-        .await
+        budget.reserve(estimated, balance).map(Some)
     }
 }
@@ -0,0 +1,253 @@
+//! Client configuration loaded from a file and `STABILITY_*` environment variables.
+//!
+//! A configuration file, in TOML or JSON format depending on its extension, holds
+//! named profiles. Values of the `default` profile apply to every other profile:
+//!
+//! ```toml
+//! [default]
+//! client_id = "my-app"
+//! client_version = "1.2.0"
+//!
+//! [dev]
+//! api_base = "http://localhost:8080/v1"
+//!
+//! [prod]
+//! organization = "org-123"
+//! backoff = { max_elapsed_time_secs = 300 }
+//! rate_limit = { requests = 150, interval_ms = 10000, max_in_flight = 8 }
+//! ```
+//!
+//! [ClientConfig::load] reads the file at `STABILITY_CONFIG_FILE` (if set) using the
+//! profile named by `STABILITY_PROFILE` (or `default`), and then applies the
+//! environment variables `STABILITY_API_KEY`, `STABILITY_API_BASE`,
+//! `STABILITY_ORGANIZATION`, `STABILITY_CLIENT_ID` and `STABILITY_CLIENT_VERSION`.
+//!
+//! ```no_run
+//! use stabilityai::{config::ClientConfig, Client};
+//!
+//! let client: Client = ClientConfig::load().unwrap().into();
+//! ```
+use std::{collections::HashMap, path::Path, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    credentials::{ApiKey, API_KEY_ENV},
+    error::StabilityAIError,
+    rate_limit::RateLimit,
+    Client,
+};
+
+/// Environment variable with the path of the configuration file
+pub const CONFIG_FILE_ENV: &str = "STABILITY_CONFIG_FILE";
+/// Environment variable with the name of the profile to use
+pub const PROFILE_ENV: &str = "STABILITY_PROFILE";
+/// Name of the profile used when none is given, its values apply to every profile
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Configuration of a [Client], every value is optional and falls back
+/// to the default of [Client::new].
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClientConfig {
+    /// Never serialized, to keep the key out of written configuration files
+    #[serde(skip_serializing)]
+    pub api_key: Option<ApiKey>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_base: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub organization: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backoff: Option<BackoffConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<RateLimitConfig>,
+}
+
+/// Exponential backoff settings, missing values use defaults of [backoff::ExponentialBackoff]
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct BackoffConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub initial_interval_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_interval_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub multiplier: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_elapsed_time_secs: Option<u64>,
+}
+
+/// Client side rate limit, see [RateLimit]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub requests: u32,
+    pub interval_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_in_flight: Option<usize>,
+}
+
+impl ClientConfig {
+    /// Load the profile named by `STABILITY_PROFILE` from the file at
+    /// `STABILITY_CONFIG_FILE` and apply `STABILITY_*` environment variables.
+    ///
+    /// Without `STABILITY_CONFIG_FILE` only environment variables are used.
+    pub fn load() -> Result<Self, StabilityAIError> {
+        let profile = std::env::var(PROFILE_ENV).unwrap_or_else(|_| DEFAULT_PROFILE.into());
+        Self::load_profile(&profile)
+    }
+
+    /// Same as [ClientConfig::load] with the given profile
+    pub fn load_profile(profile: &str) -> Result<Self, StabilityAIError> {
+        let config = match std::env::var(CONFIG_FILE_ENV) {
+            Ok(path) => Self::from_file(path, profile)?,
+            Err(_) => Self::default(),
+        };
+
+        Ok(config.merge(Self::from_env()))
+    }
+
+    /// Read a profile from a TOML or JSON configuration file,
+    /// merged on top of the `default` profile.
+    pub fn from_file<P: AsRef<Path>>(path: P, profile: &str) -> Result<Self, StabilityAIError> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).map_err(|e| {
+            StabilityAIError::FileReadError(format!("{e}, path: {}", path.display()))
+        })?;
+
+        let is_json = path
+            .extension()
+            .map(|extension| extension.eq_ignore_ascii_case("json"))
+            .unwrap_or(false);
+
+        let profiles: HashMap<String, ClientConfig> = if is_json {
+            serde_json::from_str(&contents)
+                .map_err(|e| StabilityAIError::InvalidConfig(format!("{}: {e}", path.display())))?
+        } else {
+            toml::from_str(&contents)
+                .map_err(|e| StabilityAIError::InvalidConfig(format!("{}: {e}", path.display())))?
+        };
+
+        Self::select_profile(profiles, profile)
+            .map_err(|e| StabilityAIError::InvalidConfig(format!("{}: {e}", path.display())))
+    }
+
+    fn select_profile(
+        mut profiles: HashMap<String, ClientConfig>,
+        profile: &str,
+    ) -> Result<Self, String> {
+        let default = profiles.remove(DEFAULT_PROFILE);
+        if profile == DEFAULT_PROFILE {
+            return Ok(default.unwrap_or_default());
+        }
+
+        match profiles.remove(profile) {
+            Some(selected) => Ok(default.unwrap_or_default().merge(selected)),
+            None => Err(format!("profile '{profile}' not found")),
+        }
+    }
+
+    /// Configuration from `STABILITY_*` environment variables
+    pub fn from_env() -> Self {
+        Self::from_vars(std::env::vars())
+    }
+
+    /// Configuration from `STABILITY_*` variables of the given key-value pairs
+    pub fn from_vars<I, K, V>(vars: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: Into<String>,
+    {
+        let mut config = Self::default();
+        for (key, value) in vars {
+            let value = Some(value.into());
+            match key.as_ref() {
+                API_KEY_ENV => config.api_key = value.map(ApiKey::from),
+                "STABILITY_API_BASE" => config.api_base = value,
+                "STABILITY_ORGANIZATION" => config.organization = value,
+                "STABILITY_CLIENT_ID" => config.client_id = value,
+                "STABILITY_CLIENT_VERSION" => config.client_version = value,
+                _ => {}
+            }
+        }
+        config
+    }
+
+    /// Values set in `other` take precedence over values of `self`
+    pub fn merge(self, other: ClientConfig) -> Self {
+        Self {
+            api_key: other.api_key.or(self.api_key),
+            api_base: other.api_base.or(self.api_base),
+            organization: other.organization.or(self.organization),
+            client_id: other.client_id.or(self.client_id),
+            client_version: other.client_version.or(self.client_version),
+            backoff: other.backoff.or(self.backoff),
+            rate_limit: other.rate_limit.or(self.rate_limit),
+        }
+    }
+}
+
+impl From<BackoffConfig> for backoff::ExponentialBackoff {
+    fn from(config: BackoffConfig) -> Self {
+        let mut builder = backoff::ExponentialBackoffBuilder::new();
+        if let Some(initial_interval_ms) = config.initial_interval_ms {
+            builder.with_initial_interval(Duration::from_millis(initial_interval_ms));
+        }
+        if let Some(max_interval_ms) = config.max_interval_ms {
+            builder.with_max_interval(Duration::from_millis(max_interval_ms));
+        }
+        if let Some(multiplier) = config.multiplier {
+            builder.with_multiplier(multiplier);
+        }
+        if let Some(max_elapsed_time_secs) = config.max_elapsed_time_secs {
+            builder.with_max_elapsed_time(Some(Duration::from_secs(max_elapsed_time_secs)));
+        }
+        builder.build()
+    }
+}
+
+impl From<RateLimitConfig> for RateLimit {
+    fn from(config: RateLimitConfig) -> Self {
+        let rate_limit = RateLimit::new(config.requests, Duration::from_millis(config.interval_ms));
+        match config.max_in_flight {
+            Some(max_in_flight) => rate_limit.with_max_in_flight(max_in_flight),
+            None => rate_limit,
+        }
+    }
+}
+
+impl From<ClientConfig> for Client {
+    /// Create client with defaults of [Client::new] overridden by the configuration
+    fn from(config: ClientConfig) -> Self {
+        let mut client = Client::new();
+
+        if let Some(api_key) = config.api_key {
+            client = client.with_api_key(api_key);
+        }
+        if let Some(api_base) = config.api_base {
+            client = client.with_api_base(api_base);
+        }
+        if let Some(organization) = config.organization {
+            client = client.with_organization(organization);
+        }
+        if let Some(client_id) = config.client_id {
+            client = client.with_client_id(client_id);
+        }
+        if let Some(client_version) = config.client_version {
+            client = client.with_client_version(client_version);
+        }
+        if let Some(backoff) = config.backoff {
+            client = client.with_backoff(backoff.into());
+        }
+        if let Some(rate_limit) = config.rate_limit {
+            client = client.with_rate_limit(RateLimit::from(rate_limit));
+        }
+
+        client
+    }
+}
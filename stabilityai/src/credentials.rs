@@ -0,0 +1,146 @@
+//! API key handling which keeps the key out of logs and memory once it is not needed.
+//!
+//! [ApiKey] redacts the key in its `Debug` and `Display` output and zeroizes it on drop.
+//! A [CredentialProvider] configured with
+//! [Client::with_credentials](crate::Client::with_credentials) is asked for the key
+//! before every attempt, so keys can be rotated without recreating the client.
+//!
+//! ```no_run
+//! use stabilityai::{credentials::FileCredentials, Client};
+//!
+//! let client = Client::new().with_credentials(FileCredentials::new("/run/secrets/stability"));
+//! ```
+use std::{
+    fmt::{Debug, Display},
+    path::PathBuf,
+};
+
+use serde::Deserialize;
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::error::StabilityAIError;
+
+/// Environment variable with the default API key
+pub const API_KEY_ENV: &str = "STABILITY_API_KEY";
+
+/// Secret API key, redacted when formatted and zeroized on drop
+#[derive(Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct ApiKey(String);
+
+impl ApiKey {
+    pub fn new<S: Into<String>>(api_key: S) -> Self {
+        Self(api_key.into())
+    }
+
+    /// The plain API key, avoid keeping copies of it around
+    pub fn expose_secret(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Last four characters of the key prefixed with `sk-…`, to tell keys apart in logs
+    pub fn redacted(&self) -> String {
+        let mut suffix: Vec<char> = self.0.chars().rev().take(4).collect();
+        suffix.reverse();
+        format!("sk-…{}", suffix.into_iter().collect::<String>())
+    }
+
+    /// Value of the `Authorization` header, zeroized on drop
+    pub(crate) fn bearer(&self) -> Zeroizing<String> {
+        Zeroizing::new(format!("Bearer {}", self.0))
+    }
+}
+
+impl Drop for ApiKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl Debug for ApiKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ApiKey([REDACTED])")
+    }
+}
+
+impl Display for ApiKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("[REDACTED]")
+    }
+}
+
+impl From<String> for ApiKey {
+    fn from(api_key: String) -> Self {
+        Self(api_key)
+    }
+}
+
+impl From<&str> for ApiKey {
+    fn from(api_key: &str) -> Self {
+        Self(api_key.to_string())
+    }
+}
+
+/// Source of the API key, asked before every attempt of a request.
+#[async_trait::async_trait]
+pub trait CredentialProvider: Debug + Send + Sync {
+    async fn api_key(&self) -> Result<ApiKey, StabilityAIError>;
+}
+
+/// Reads the API key from an environment variable on every request
+#[derive(Debug, Clone)]
+pub struct EnvCredentials {
+    var: String,
+}
+
+impl Default for EnvCredentials {
+    /// Read the key from STABILITY_API_KEY env var
+    fn default() -> Self {
+        Self::new(API_KEY_ENV)
+    }
+}
+
+impl EnvCredentials {
+    pub fn new<S: Into<String>>(var: S) -> Self {
+        Self { var: var.into() }
+    }
+}
+
+#[async_trait::async_trait]
+impl CredentialProvider for EnvCredentials {
+    async fn api_key(&self) -> Result<ApiKey, StabilityAIError> {
+        std::env::var(&self.var)
+            .map(ApiKey::from)
+            .map_err(|e| StabilityAIError::InvalidArgument(format!("{e}: {}", self.var)))
+    }
+}
+
+/// Reads the API key from a file on every request, surrounding whitespace is trimmed.
+///
+/// Suited to secrets mounted by an orchestrator, which are rotated in place.
+#[derive(Debug, Clone)]
+pub struct FileCredentials {
+    path: PathBuf,
+}
+
+impl FileCredentials {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+#[async_trait::async_trait]
+impl CredentialProvider for FileCredentials {
+    async fn api_key(&self) -> Result<ApiKey, StabilityAIError> {
+        let contents =
+            Zeroizing::new(tokio::fs::read_to_string(&self.path).await.map_err(|e| {
+                StabilityAIError::FileReadError(format!("{e}, path: {}", self.path.display()))
+            })?);
+
+        Ok(ApiKey::new(contents.trim()))
+    }
+}
@@ -0,0 +1,103 @@
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    future::Future,
+    sync::{Arc, Mutex},
+};
+
+use futures::{
+    future::{BoxFuture, WeakShared},
+    FutureExt,
+};
+
+use crate::{error::StabilityAIError, types::Artifacts, WithMeta};
+
+type SharedResult = Result<WithMeta<Artifacts>, Arc<StabilityAIError>>;
+
+/// Generation calls in flight by request key, clones share the calls.
+///
+/// Every caller of an identical request awaits the same call, which is driven
+/// by whichever of them is polled, so it completes as long as one caller waits.
+/// The call is dropped with its last caller.
+#[derive(Clone, Default)]
+pub(crate) struct InFlight {
+    calls: Arc<Mutex<Calls>>,
+}
+
+#[derive(Default)]
+struct Calls {
+    /// Calls by key with the id they were started with
+    calls: HashMap<String, (u64, WeakShared<BoxFuture<'static, SharedResult>>)>,
+    next_id: u64,
+}
+
+/// Removes the call from the map once it completes or is dropped by every caller
+struct RemoveCall {
+    calls: Arc<Mutex<Calls>>,
+    key: String,
+    id: u64,
+}
+
+impl Drop for RemoveCall {
+    fn drop(&mut self) {
+        let mut calls = self.calls.lock().unwrap();
+        // A newer call with the same key may have replaced this one
+        if calls.calls.get(&self.key).map(|(id, _)| *id) == Some(self.id) {
+            calls.calls.remove(&self.key);
+        }
+    }
+}
+
+impl InFlight {
+    /// Await the call in flight for the key, or make the call if there is none
+    pub(crate) async fn run<F>(
+        &self,
+        key: String,
+        call: F,
+    ) -> Result<WithMeta<Artifacts>, StabilityAIError>
+    where
+        F: Future<Output = Result<WithMeta<Artifacts>, StabilityAIError>> + Send + 'static,
+    {
+        let shared = {
+            let mut calls = self.calls.lock().unwrap();
+            match calls.calls.get(&key).and_then(|(_, call)| call.upgrade()) {
+                Some(shared) => {
+                    tracing::debug!("Awaiting identical request in flight {key}");
+                    shared
+                }
+                None => {
+                    let id = calls.next_id;
+                    calls.next_id += 1;
+                    let remove = RemoveCall {
+                        calls: self.calls.clone(),
+                        key: key.clone(),
+                        id,
+                    };
+                    let shared = async move {
+                        let _remove = remove;
+                        call.await.map_err(Arc::new)
+                    }
+                    .boxed()
+                    .shared();
+                    if let Some(weak) = shared.downgrade() {
+                        calls.calls.insert(key, (id, weak));
+                    }
+                    shared
+                }
+            }
+        };
+
+        // The only caller gets the error itself, other callers share it
+        shared
+            .await
+            .map_err(|e| Arc::try_unwrap(e).unwrap_or_else(StabilityAIError::Coalesced))
+    }
+}
+
+impl Debug for InFlight {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InFlight")
+            .field("calls", &self.calls.lock().unwrap().calls.len())
+            .finish()
+    }
+}
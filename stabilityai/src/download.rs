@@ -1,14 +1,11 @@
 use std::path::{Path, PathBuf};
 
-use base64::{engine::general_purpose, Engine as _};
 use rand::{distributions::Alphanumeric, Rng};
 
 use crate::error::StabilityAIError;
 
-pub(crate) async fn save_b64<P: AsRef<Path>>(
-    b64: &str,
-    dir: P,
-) -> Result<PathBuf, StabilityAIError> {
+/// Path of a PNG file with random name in {dir}
+pub(crate) fn random_png_path<P: AsRef<Path>>(dir: P) -> PathBuf {
     let filename: String = rand::thread_rng()
         .sample_iter(&Alphanumeric)
         .take(10)
@@ -17,16 +14,31 @@ pub(crate) async fn save_b64<P: AsRef<Path>>(
 
     let filename = format!("{filename}.png");
 
-    let path = PathBuf::from(dir.as_ref()).join(filename);
+    PathBuf::from(dir.as_ref()).join(filename)
+}
+
+pub(crate) async fn save_bytes<P: AsRef<Path>>(
+    bytes: &[u8],
+    dir: P,
+) -> Result<PathBuf, StabilityAIError> {
+    let path = random_png_path(dir);
+
+    tokio::fs::write(path.as_path(), bytes)
+        .await
+        .map_err(|e| StabilityAIError::FileSaveError(format!("{e}, path: {}", path.display())))?;
+
+    Ok(path)
+}
+
+#[cfg(feature = "blocking")]
+pub(crate) fn save_bytes_blocking<P: AsRef<Path>>(
+    bytes: &[u8],
+    dir: P,
+) -> Result<PathBuf, StabilityAIError> {
+    let path = random_png_path(dir);
 
-    tokio::fs::write(
-        path.as_path(),
-        general_purpose::STANDARD
-            .decode(b64)
-            .map_err(|e| StabilityAIError::FileSaveError(e.to_string()))?,
-    )
-    .await
-    .map_err(|e| StabilityAIError::FileSaveError(format!("{e}, path: {}", path.display())))?;
+    std::fs::write(path.as_path(), bytes)
+        .map_err(|e| StabilityAIError::FileSaveError(format!("{e}, path: {}", path.display())))?;
 
     Ok(path)
 }
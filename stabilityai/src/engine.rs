@@ -1,17 +1,32 @@
-use crate::{error::StabilityAIError, types::Engine, Client};
+use crate::{error::StabilityAIError, types::Engine, Client, RequestOptions, WithMeta};
 
 /// Enumerate available engines
 pub struct Engines<'c> {
     client: &'c Client,
+    options: RequestOptions,
 }
 
 impl<'c> Engines<'c> {
     pub fn new(client: &'c Client) -> Self {
-        Self { client }
+        Self {
+            client,
+            options: Default::default(),
+        }
+    }
+
+    /// Timeout, cancellation and headers of the calls made with this instance
+    pub fn with_options(mut self, options: RequestOptions) -> Self {
+        self.options = options;
+        self
     }
 
     /// List all engines available to your organization/user
     pub async fn list(&self) -> Result<Vec<Engine>, StabilityAIError> {
-        self.client.get("/engines/list").await
+        Ok(self.list_with_meta().await?.body)
+    }
+
+    /// Same as [Engines::list] with status, headers and latency of the response
+    pub async fn list_with_meta(&self) -> Result<WithMeta<Vec<Engine>>, StabilityAIError> {
+        self.client.get("/engines/list", &self.options).await
     }
 }
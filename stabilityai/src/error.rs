@@ -1,14 +1,30 @@
 //! Errors originating from API calls, parsing responses, and reading-or-writing to the file system.
+use std::sync::Arc;
+
+use reqwest::{
+    header::{HeaderMap, CONTENT_TYPE},
+    StatusCode,
+};
 use serde::Deserialize;
 
+use crate::{budget::BudgetExceeded, retry::is_network_error};
+
 #[derive(Debug, thiserror::Error)]
 pub enum StabilityAIError {
     /// Underlying error from reqwest library after an API call was made
     #[error("http error: {0}")]
     Reqwest(#[from] reqwest::Error),
     /// OpenAI returns error object with details of API call failure
-    #[error("id: {}, name: {}, message: {}", .0.id, .0.name, .0.message)]
-    ApiError(ApiError),
+    #[error("status: {}, id: {}, name: {}, message: {}", .0.status, .0.id, .0.name, .0.message)]
+    ApiError(Box<ApiError>),
+    /// Error response which is not an API error object, e.g. an HTML page of a proxy
+    #[error(
+        "unexpected response, status: {}, content-type: {}, body: {}",
+        .0.status,
+        .0.content_type.as_deref().unwrap_or("none"),
+        .0.body
+    )]
+    UnexpectedResponse(Box<UnexpectedResponse>),
     /// Error when a response cannot be deserialized into a Rust type
     #[error("failed to deserialize api response: {0}")]
     JSONDeserialize(serde_json::Error),
@@ -18,14 +34,72 @@ pub enum StabilityAIError {
     /// Error on the client side when reading file from file system
     #[error("failed to read file: {0}")]
     FileReadError(String),
+    /// Error when the base64 of an [Image](crate::types::Image) cannot be decoded
+    #[error("invalid base64 image: {0}")]
+    InvalidBase64(String),
+    /// Error raised by a custom [transport](crate::transport::HttpTransport)
+    #[error("transport error: {0}")]
+    Transport(String),
+    /// Error when a response header is missing or cannot be parsed
+    #[error("invalid response header: {0}")]
+    InvalidResponseHeader(String),
+    /// Error when a configuration file cannot be parsed or lacks the requested profile
+    #[error("invalid config: {0}")]
+    InvalidConfig(String),
+    /// The call did not complete within the timeout or deadline of its
+    /// [RequestOptions](crate::RequestOptions)
+    #[error("request timed out")]
+    Timeout,
+    /// The call was cancelled with the token of its [RequestOptions](crate::RequestOptions)
+    #[error("request cancelled")]
+    Cancelled,
+    /// Error of an identical in-flight request this call was coalesced with, see
+    /// [Client::with_deduplication](crate::Client::with_deduplication)
+    #[error("{0}")]
+    Coalesced(Arc<StabilityAIError>),
+    /// Request refused before being sent, as it would cross a limit of the
+    /// [BudgetGuard](crate::budget::BudgetGuard) of the client
+    #[error(
+        "budget exceeded: request estimated at {} credits, {} credits available under the {}",
+        .0.estimated,
+        .0.available,
+        .0.limit
+    )]
+    BudgetExceeded(BudgetExceeded),
     /// Error from client side validation
     /// or when builder fails to build request before making API call
     #[error("invalid args: {0}")]
     InvalidArgument(String),
 }
 
+impl StabilityAIError {
+    /// HTTP status of the failed API call, `None` for errors raised before a response
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            StabilityAIError::ApiError(api_error) => Some(api_error.status),
+            StabilityAIError::UnexpectedResponse(response) => Some(response.status),
+            StabilityAIError::Coalesced(error) => error.status(),
+            StabilityAIError::Reqwest(e) => e.status(),
+            _ => None,
+        }
+    }
+
+    /// Whether the failure is transient, so that the same request may succeed later.
+    ///
+    /// API errors are classified by the [RetryPolicy](crate::retry::RetryPolicy) of
+    /// the client, other errors are retryable when they are [network errors](is_network_error).
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            StabilityAIError::ApiError(api_error) => api_error.is_retryable(),
+            StabilityAIError::UnexpectedResponse(response) => response.retryable,
+            StabilityAIError::Coalesced(error) => error.is_retryable(),
+            error => is_network_error(error),
+        }
+    }
+}
+
 /// OpenAI API returns error object on failure
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct ApiError {
     /// A unique identifier for this particular occurrence of the problem.
     pub id: String,
@@ -33,12 +107,196 @@ pub struct ApiError {
     pub name: String,
     /// A human-readable explanation specific to this occurrence of the problem.
     pub message: String,
+    /// HTTP status of the response
+    #[serde(skip)]
+    pub status: StatusCode,
+    /// Headers of the response
+    #[serde(skip)]
+    pub headers: HeaderMap,
+    /// Whether the retry policy of the client considers the status transient
+    #[serde(skip)]
+    pub retryable: bool,
+}
+
+/// Error response whose body cannot be deserialized into an [ApiError]
+#[derive(Debug, Clone)]
+pub struct UnexpectedResponse {
+    pub status: StatusCode,
+    /// Value of the `Content-Type` header
+    pub content_type: Option<String>,
+    pub headers: HeaderMap,
+    /// Body as lossy UTF-8, truncated to [BODY_PREVIEW_LIMIT] characters
+    pub body: String,
+    /// Whether the retry policy of the client considers the status transient
+    pub retryable: bool,
+}
+
+/// Maximum number of characters of a response body kept in errors and logs
+pub const BODY_PREVIEW_LIMIT: usize = 1024;
+
+/// Runs of base64 longer than this are replaced by their length in body previews
+const BASE64_RUN_LIMIT: usize = 64;
+
+/// Class of an [ApiError], derived from its HTTP status
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiErrorKind {
+    /// 400: invalid parameters
+    BadRequest,
+    /// 401: missing or invalid API key
+    Unauthorized,
+    /// 402: not enough credits
+    InsufficientBalance,
+    /// 400 or 403: a prompt or image was flagged by content moderation
+    ContentModeration,
+    /// 403: the key or organization may not use the resource
+    PermissionDenied,
+    /// 404: unknown engine or route
+    NotFound,
+    /// 429: too many requests
+    RateLimited,
+    /// 5xx: failure on the server side
+    ServerError,
+    /// Any other status
+    Other,
+}
+
+impl ApiError {
+    /// Class of the error, from the status and the name of the error
+    pub fn kind(&self) -> ApiErrorKind {
+        match self.status {
+            StatusCode::BAD_REQUEST | StatusCode::FORBIDDEN if self.is_moderation() => {
+                ApiErrorKind::ContentModeration
+            }
+            StatusCode::BAD_REQUEST => ApiErrorKind::BadRequest,
+            StatusCode::UNAUTHORIZED => ApiErrorKind::Unauthorized,
+            StatusCode::PAYMENT_REQUIRED => ApiErrorKind::InsufficientBalance,
+            StatusCode::FORBIDDEN => ApiErrorKind::PermissionDenied,
+            StatusCode::NOT_FOUND => ApiErrorKind::NotFound,
+            StatusCode::TOO_MANY_REQUESTS => ApiErrorKind::RateLimited,
+            status if status.is_server_error() => ApiErrorKind::ServerError,
+            _ => ApiErrorKind::Other,
+        }
+    }
+
+    /// Whether the retry policy of the client considers the status transient
+    pub fn is_retryable(&self) -> bool {
+        self.retryable
+    }
+
+    /// `invalid_prompts` is returned when a prompt contains filtered words
+    fn is_moderation(&self) -> bool {
+        self.name == "invalid_prompts" || self.name.contains("moderation")
+    }
+}
+
+impl UnexpectedResponse {
+    pub(crate) fn new(
+        status: StatusCode,
+        headers: HeaderMap,
+        body: &[u8],
+        retryable: bool,
+    ) -> Self {
+        Self {
+            status,
+            content_type: headers
+                .get(CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+            headers,
+            body: body_preview(body),
+            retryable,
+        }
+    }
 }
 
 pub(crate) fn map_deserialization_error(e: serde_json::Error, bytes: &[u8]) -> StabilityAIError {
-    tracing::error!(
-        "failed deserialization of: {}",
-        String::from_utf8_lossy(bytes)
-    );
+    tracing::error!("failed deserialization of: {}", body_preview(bytes));
     StabilityAIError::JSONDeserialize(e)
 }
+
+/// Body as lossy UTF-8 for errors and logs: base64 data such as generated images is
+/// replaced by its length, and the result is truncated to [BODY_PREVIEW_LIMIT] characters.
+pub(crate) fn body_preview(bytes: &[u8]) -> String {
+    let mut preview = BodyPreview::default();
+
+    // Decode lossy UTF-8 chunk by chunk, so that scanning stops with the preview full
+    let mut rest = bytes;
+    while !rest.is_empty() && preview.chars <= BODY_PREVIEW_LIMIT {
+        let chunk = &rest[..rest.len().min(BODY_PREVIEW_CHUNK)];
+        let consumed = match std::str::from_utf8(chunk) {
+            Ok(text) => {
+                preview.push_str(text);
+                chunk.len()
+            }
+            Err(e) => {
+                let valid = e.valid_up_to();
+                preview.push_str(std::str::from_utf8(&chunk[..valid]).unwrap_or_default());
+                match e.error_len() {
+                    Some(len) => {
+                        preview.push(char::REPLACEMENT_CHARACTER);
+                        valid + len
+                    }
+                    // Character split at the end of the chunk, decoded with the next one
+                    None if chunk.len() < rest.len() => valid,
+                    None => {
+                        preview.push(char::REPLACEMENT_CHARACTER);
+                        chunk.len()
+                    }
+                }
+            }
+        };
+        rest = &rest[consumed..];
+    }
+    preview.end_run();
+
+    match preview.text.char_indices().nth(BODY_PREVIEW_LIMIT) {
+        Some((end, _)) => format!("{}... [{} bytes]", &preview.text[..end], bytes.len()),
+        None => preview.text,
+    }
+}
+
+/// Bytes of the body decoded at once by [body_preview]
+const BODY_PREVIEW_CHUNK: usize = 4096;
+
+/// Preview being built, with the length of the current run of base64 characters
+#[derive(Default)]
+struct BodyPreview {
+    text: String,
+    chars: usize,
+    run: usize,
+}
+
+impl BodyPreview {
+    fn push_str(&mut self, text: &str) {
+        for c in text.chars() {
+            self.push(c);
+        }
+    }
+
+    fn push(&mut self, c: char) {
+        if c.is_ascii_alphanumeric() || matches!(c, '+' | '/' | '=') {
+            self.run += 1;
+            // Short runs are kept as is, longer ones are only counted
+            if self.run <= BASE64_RUN_LIMIT {
+                self.text.push(c);
+                self.chars += 1;
+            } else if self.run == BASE64_RUN_LIMIT + 1 {
+                self.text.truncate(self.text.len() - BASE64_RUN_LIMIT);
+                self.chars -= BASE64_RUN_LIMIT;
+            }
+        } else {
+            self.end_run();
+            self.text.push(c);
+            self.chars += 1;
+        }
+    }
+
+    fn end_run(&mut self) {
+        if self.run > BASE64_RUN_LIMIT {
+            let placeholder = format!("[{} base64 characters]", self.run);
+            self.chars += placeholder.len();
+            self.text.push_str(&placeholder);
+        }
+        self.run = 0;
+    }
+}
@@ -2,22 +2,34 @@ use std::fmt::Display;
 
 use crate::{
     error::StabilityAIError,
+    streaming::{ImageSink, StreamedImage},
     types::{
-        Artifacts, ImageToImageRequestBody, ImageToImageUpscaleBody, MaskingRequestBody,
-        TextToImageRequestBody,
+        Artifacts, BinaryImage, ImageToImageRequestBody, ImageToImageUpscaleBody,
+        MaskingRequestBody, TextToImageRequestBody,
     },
-    Client,
+    Client, RequestOptions, RequestPreview, WithMeta,
 };
 
 /// Generate images from text, existing images, or both
 pub struct Generate<'c, E: Display> {
     client: &'c Client,
     engine_id: E,
+    options: RequestOptions,
 }
 
 impl<'c, E: Display> Generate<'c, E> {
     pub fn new(client: &'c Client, engine_id: E) -> Self {
-        Self { client, engine_id }
+        Self {
+            client,
+            engine_id,
+            options: Default::default(),
+        }
+    }
+
+    /// Timeout, cancellation and headers of the calls made with this instance
+    pub fn with_options(mut self, options: RequestOptions) -> Self {
+        self.options = options;
+        self
     }
 
     /// Generate a new image from a text prompt
@@ -25,10 +37,19 @@ impl<'c, E: Display> Generate<'c, E> {
         &self,
         request: TextToImageRequestBody,
     ) -> Result<Artifacts, StabilityAIError> {
+        Ok(self.text_to_image_with_meta(request).await?.body)
+    }
+
+    /// Same as [Generate::text_to_image] with status, headers and latency of the response
+    pub async fn text_to_image_with_meta(
+        &self,
+        request: TextToImageRequestBody,
+    ) -> Result<WithMeta<Artifacts>, StabilityAIError> {
         self.client
             .post(
                 &format!("/generation/{}/text-to-image", self.engine_id),
                 request,
+                &self.options,
             )
             .await
     }
@@ -38,10 +59,19 @@ impl<'c, E: Display> Generate<'c, E> {
         &self,
         request: ImageToImageRequestBody,
     ) -> Result<Artifacts, StabilityAIError> {
+        Ok(self.image_to_image_with_meta(request).await?.body)
+    }
+
+    /// Same as [Generate::image_to_image] with status, headers and latency of the response
+    pub async fn image_to_image_with_meta(
+        &self,
+        request: ImageToImageRequestBody,
+    ) -> Result<WithMeta<Artifacts>, StabilityAIError> {
         self.client
             .post_form(
                 &format!("/generation/{}/image-to-image", self.engine_id),
                 request,
+                &self.options,
             )
             .await
     }
@@ -67,10 +97,19 @@ impl<'c, E: Display> Generate<'c, E> {
         &self,
         request: R,
     ) -> Result<Artifacts, StabilityAIError> {
+        Ok(self.image_to_image_upscale_with_meta(request).await?.body)
+    }
+
+    /// Same as [Generate::image_to_image_upscale] with status, headers and latency of the response
+    pub async fn image_to_image_upscale_with_meta<R: Into<ImageToImageUpscaleBody>>(
+        &self,
+        request: R,
+    ) -> Result<WithMeta<Artifacts>, StabilityAIError> {
         self.client
             .post_form(
                 &format!("/generation/{}/image-to-image/upscale", self.engine_id),
                 request.into(),
+                &self.options,
             )
             .await
     }
@@ -80,11 +119,220 @@ impl<'c, E: Display> Generate<'c, E> {
         &self,
         request: MaskingRequestBody,
     ) -> Result<Artifacts, StabilityAIError> {
+        Ok(self.image_to_image_masking_with_meta(request).await?.body)
+    }
+
+    /// Same as [Generate::image_to_image_masking] with status, headers and latency of the response
+    pub async fn image_to_image_masking_with_meta(
+        &self,
+        request: MaskingRequestBody,
+    ) -> Result<WithMeta<Artifacts>, StabilityAIError> {
         self.client
             .post_form(
                 &format!("/generation/{}/image-to-image/masking", self.engine_id),
                 request,
+                &self.options,
             )
             .await
     }
+
+    /// Generate a new image from a text prompt and return it as raw PNG bytes.
+    ///
+    /// Only a single image is returned in this mode.
+    pub async fn text_to_image_binary(
+        &self,
+        request: TextToImageRequestBody,
+    ) -> Result<BinaryImage, StabilityAIError> {
+        let response = self
+            .client
+            .post_binary(
+                &format!("/generation/{}/text-to-image", self.engine_id),
+                request,
+                &self.options,
+            )
+            .await?;
+        BinaryImage::from_response(&response.headers, response.body)
+    }
+
+    /// Modify an image based on a text prompt and return it as raw PNG bytes.
+    ///
+    /// Only a single image is returned in this mode.
+    pub async fn image_to_image_binary(
+        &self,
+        request: ImageToImageRequestBody,
+    ) -> Result<BinaryImage, StabilityAIError> {
+        let response = self
+            .client
+            .post_form_binary(
+                &format!("/generation/{}/image-to-image", self.engine_id),
+                request,
+                &self.options,
+            )
+            .await?;
+        BinaryImage::from_response(&response.headers, response.body)
+    }
+
+    /// Create a higher resolution version of an input image and return it as raw PNG bytes.
+    ///
+    /// See [Generate::image_to_image_upscale] for details on the output dimensions.
+    pub async fn image_to_image_upscale_binary<R: Into<ImageToImageUpscaleBody>>(
+        &self,
+        request: R,
+    ) -> Result<BinaryImage, StabilityAIError> {
+        let response = self
+            .client
+            .post_form_binary(
+                &format!("/generation/{}/image-to-image/upscale", self.engine_id),
+                request.into(),
+                &self.options,
+            )
+            .await?;
+        BinaryImage::from_response(&response.headers, response.body)
+    }
+
+    /// Selectively modify portions of an image using a mask and return it as raw PNG bytes.
+    ///
+    /// Only a single image is returned in this mode.
+    pub async fn image_to_image_masking_binary(
+        &self,
+        request: MaskingRequestBody,
+    ) -> Result<BinaryImage, StabilityAIError> {
+        let response = self
+            .client
+            .post_form_binary(
+                &format!("/generation/{}/image-to-image/masking", self.engine_id),
+                request,
+                &self.options,
+            )
+            .await?;
+        BinaryImage::from_response(&response.headers, response.body)
+    }
+
+    /// Generate new images from a text prompt, decoding every image into the sink as the response is received.
+    ///
+    /// See the [streaming](crate::streaming) module.
+    pub async fn text_to_image_streamed<S: ImageSink>(
+        &self,
+        request: TextToImageRequestBody,
+        sink: S,
+    ) -> Result<Vec<StreamedImage<S::Output>>, StabilityAIError> {
+        let response = self
+            .client
+            .post_artifacts(
+                &format!("/generation/{}/text-to-image", self.engine_id),
+                request,
+                &self.options,
+                sink,
+            )
+            .await?;
+        Ok(response.body)
+    }
+
+    /// Modify an image based on a text prompt, decoding every image into the sink as the response is received.
+    ///
+    /// See the [streaming](crate::streaming) module.
+    pub async fn image_to_image_streamed<S: ImageSink>(
+        &self,
+        request: ImageToImageRequestBody,
+        sink: S,
+    ) -> Result<Vec<StreamedImage<S::Output>>, StabilityAIError> {
+        let response = self
+            .client
+            .post_form_artifacts(
+                &format!("/generation/{}/image-to-image", self.engine_id),
+                request,
+                &self.options,
+                sink,
+            )
+            .await?;
+        Ok(response.body)
+    }
+
+    /// Create a higher resolution version of an input image, decoding every image into the sink as the response is received.
+    ///
+    /// See the [streaming](crate::streaming) module.
+    pub async fn image_to_image_upscale_streamed<R: Into<ImageToImageUpscaleBody>, S: ImageSink>(
+        &self,
+        request: R,
+        sink: S,
+    ) -> Result<Vec<StreamedImage<S::Output>>, StabilityAIError> {
+        let response = self
+            .client
+            .post_form_artifacts(
+                &format!("/generation/{}/image-to-image/upscale", self.engine_id),
+                request.into(),
+                &self.options,
+                sink,
+            )
+            .await?;
+        Ok(response.body)
+    }
+
+    /// Selectively modify portions of an image using a mask, decoding every image into the sink as the response is received.
+    ///
+    /// See the [streaming](crate::streaming) module.
+    pub async fn image_to_image_masking_streamed<S: ImageSink>(
+        &self,
+        request: MaskingRequestBody,
+        sink: S,
+    ) -> Result<Vec<StreamedImage<S::Output>>, StabilityAIError> {
+        let response = self
+            .client
+            .post_form_artifacts(
+                &format!("/generation/{}/image-to-image/masking", self.engine_id),
+                request,
+                &self.options,
+                sink,
+            )
+            .await?;
+        Ok(response.body)
+    }
+
+    /// The request [Generate::text_to_image] would send, without sending it
+    pub fn preview_text_to_image(
+        &self,
+        request: TextToImageRequestBody,
+    ) -> Result<RequestPreview, StabilityAIError> {
+        self.client.preview(
+            &format!("/generation/{}/text-to-image", self.engine_id),
+            request,
+            &self.options,
+        )
+    }
+
+    /// The request [Generate::image_to_image] would send, without sending it
+    pub fn preview_image_to_image(
+        &self,
+        request: ImageToImageRequestBody,
+    ) -> Result<RequestPreview, StabilityAIError> {
+        self.client.preview_form(
+            &format!("/generation/{}/image-to-image", self.engine_id),
+            request,
+            &self.options,
+        )
+    }
+
+    /// The request [Generate::image_to_image_upscale] would send, without sending it
+    pub fn preview_image_to_image_upscale<R: Into<ImageToImageUpscaleBody>>(
+        &self,
+        request: R,
+    ) -> Result<RequestPreview, StabilityAIError> {
+        self.client.preview_form(
+            &format!("/generation/{}/image-to-image/upscale", self.engine_id),
+            request.into(),
+            &self.options,
+        )
+    }
+
+    /// The request [Generate::image_to_image_masking] would send, without sending it
+    pub fn preview_image_to_image_masking(
+        &self,
+        request: MaskingRequestBody,
+    ) -> Result<RequestPreview, StabilityAIError> {
+        self.client.preview_form(
+            &format!("/generation/{}/image-to-image/masking", self.engine_id),
+            request,
+            &self.options,
+        )
+    }
 }
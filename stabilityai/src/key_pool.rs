@@ -0,0 +1,254 @@
+//! Pool of API keys to spread requests and credits across keys and organizations.
+//!
+//! An [ApiKeyPool] configured with [Client::with_api_key_pool](crate::Client::with_api_key_pool)
+//! hands out keys in round-robin order. A key which is rate limited (429) or out of
+//! credits (402) is benched for a while, a key which is rejected (401) is dropped from
+//! the pool. The failed request is then retried right away with the next key.
+//!
+//! ```
+//! use stabilityai::{key_pool::{ApiKeyPool, PooledKey}, Client};
+//!
+//! let pool = ApiKeyPool::new([
+//!     PooledKey::new("sk-first").with_name("team-a"),
+//!     PooledKey::new("sk-second").with_organization("org-b"),
+//! ]);
+//!
+//! let client = Client::new().with_api_key_pool(pool);
+//! ```
+//!
+//! The name of the key which served a request is available in
+//! [WithMeta::api_key_name](crate::WithMeta::api_key_name).
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use reqwest::StatusCode;
+
+use crate::{credentials::ApiKey, error::StabilityAIError};
+
+/// An API key of an [ApiKeyPool]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PooledKey {
+    api_key: ApiKey,
+    organization: Option<String>,
+    name: String,
+}
+
+impl PooledKey {
+    /// Key named after its last four characters, e.g. `sk-…wxyz`
+    pub fn new<K: Into<ApiKey>>(api_key: K) -> Self {
+        let api_key = api_key.into();
+
+        Self {
+            name: api_key.redacted(),
+            api_key,
+            organization: None,
+        }
+    }
+
+    /// Organization sent with requests of this key instead of the client organization
+    pub fn with_organization<S: Into<String>>(mut self, organization: S) -> Self {
+        self.organization = Some(organization.into());
+        self
+    }
+
+    /// Name reported for requests served by this key, instead of the redacted key
+    pub fn with_name<S: Into<String>>(mut self, name: S) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    pub fn api_key(&self) -> &ApiKey {
+        &self.api_key
+    }
+
+    pub fn organization(&self) -> Option<&str> {
+        self.organization.as_deref()
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl From<&str> for PooledKey {
+    fn from(api_key: &str) -> Self {
+        Self::new(api_key)
+    }
+}
+
+impl From<String> for PooledKey {
+    fn from(api_key: String) -> Self {
+        Self::new(api_key)
+    }
+}
+
+impl From<ApiKey> for PooledKey {
+    fn from(api_key: ApiKey) -> Self {
+        Self::new(api_key)
+    }
+}
+
+/// Round-robin pool of API keys, clones share the same state.
+#[derive(Debug, Clone)]
+pub struct ApiKeyPool {
+    state: Arc<Mutex<PoolState>>,
+    bench_duration: Duration,
+}
+
+#[derive(Debug)]
+struct PoolState {
+    entries: Vec<Entry>,
+    next: usize,
+}
+
+#[derive(Debug)]
+struct Entry {
+    key: PooledKey,
+    benched_until: Option<Instant>,
+    revoked: bool,
+}
+
+/// A key handed out by the pool for one attempt
+#[derive(Debug, Clone)]
+pub(crate) struct Lease {
+    index: usize,
+    pub(crate) key: PooledKey,
+}
+
+impl ApiKeyPool {
+    /// Pool of the given keys, benched keys are unavailable for one minute by default
+    pub fn new<I, K>(keys: I) -> Self
+    where
+        I: IntoIterator<Item = K>,
+        K: Into<PooledKey>,
+    {
+        let entries = keys
+            .into_iter()
+            .map(|key| Entry {
+                key: key.into(),
+                benched_until: None,
+                revoked: false,
+            })
+            .collect();
+
+        Self {
+            state: Arc::new(Mutex::new(PoolState { entries, next: 0 })),
+            bench_duration: Duration::from_secs(60),
+        }
+    }
+
+    /// How long a rate limited or out of credits key is unavailable,
+    /// a longer `Retry-After` of a rate limited response takes precedence
+    pub fn with_bench_duration(mut self, bench_duration: Duration) -> Self {
+        self.bench_duration = bench_duration;
+        self
+    }
+
+    /// Keys which were not dropped from the pool, including benched ones
+    pub fn keys(&self) -> Vec<PooledKey> {
+        let state = self.state.lock().unwrap();
+        state
+            .entries
+            .iter()
+            .filter(|entry| !entry.revoked)
+            .map(|entry| entry.key.clone())
+            .collect()
+    }
+
+    /// Number of keys neither benched nor dropped
+    pub fn available(&self) -> usize {
+        let state = self.state.lock().unwrap();
+        let now = Instant::now();
+        state
+            .entries
+            .iter()
+            .filter(|entry| entry.is_available(now))
+            .count()
+    }
+
+    /// Next available key in round-robin order, waiting for a benched key
+    /// when all keys are benched
+    pub(crate) async fn acquire(&self) -> Result<Lease, StabilityAIError> {
+        loop {
+            match self.try_acquire()? {
+                Ok(lease) => return Ok(lease),
+                Err(wait) => {
+                    tracing::debug!("All pooled API keys are benched, waiting {wait:?}");
+                    tokio::time::sleep(wait).await;
+                }
+            }
+        }
+    }
+
+    /// Take the next available key, otherwise return the time until a benched key is available
+    fn try_acquire(&self) -> Result<Result<Lease, Duration>, StabilityAIError> {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let len = state.entries.len();
+
+        for offset in 0..len {
+            let index = (state.next + offset) % len;
+            if state.entries[index].is_available(now) {
+                state.next = (index + 1) % len;
+                return Ok(Ok(Lease {
+                    index,
+                    key: state.entries[index].key.clone(),
+                }));
+            }
+        }
+
+        let until = state
+            .entries
+            .iter()
+            .filter(|entry| !entry.revoked)
+            .filter_map(|entry| entry.benched_until)
+            .min()
+            .ok_or_else(|| {
+                StabilityAIError::InvalidArgument("no valid api key left in pool".into())
+            })?;
+
+        Ok(Err(until.saturating_duration_since(now)))
+    }
+
+    /// Bench or drop the leased key depending on the response status.
+    ///
+    /// Returns whether the request should be retried right away with another key.
+    pub(crate) fn report_failure(
+        &self,
+        lease: &Lease,
+        status: StatusCode,
+        retry_after: Option<Duration>,
+    ) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let entry = &mut state.entries[lease.index];
+
+        match status {
+            StatusCode::UNAUTHORIZED => {
+                tracing::warn!("Dropping API key {} from pool", entry.key.name);
+                entry.revoked = true;
+            }
+            StatusCode::TOO_MANY_REQUESTS | StatusCode::PAYMENT_REQUIRED => {
+                let duration = match retry_after {
+                    Some(retry_after) if status == StatusCode::TOO_MANY_REQUESTS => {
+                        retry_after.max(self.bench_duration)
+                    }
+                    _ => self.bench_duration,
+                };
+                tracing::warn!("Benching API key {} for {duration:?}", entry.key.name);
+                entry.benched_until = Some(Instant::now() + duration);
+            }
+            _ => return false,
+        }
+
+        let now = Instant::now();
+        state.entries.iter().any(|entry| entry.is_available(now))
+    }
+}
+
+impl Entry {
+    fn is_available(&self, now: Instant) -> bool {
+        !self.revoked && self.benched_until.map(|until| until <= now).unwrap_or(true)
+    }
+}
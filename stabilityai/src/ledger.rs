@@ -0,0 +1,379 @@
+//! Local ledger of the API calls made by a client, e.g. for chargeback across teams.
+//!
+//! A [LedgerSink] given to [Client::with_ledger](crate::Client::with_ledger) receives a
+//! [LedgerEntry] for every API call once it completes, successful or not, including the
+//! balance checks of a [BudgetGuard](crate::budget::BudgetGuard). [JsonlLedger]
+//! appends them to a JSON Lines file, and [usage_by_day] sums them up:
+//!
+//! ```no_run
+//! use stabilityai::{
+//!     ledger::{usage_by_day, JsonlLedger},
+//!     Client,
+//! };
+//!
+//! # tokio_test::block_on(async {
+//! let ledger = JsonlLedger::new("usage.jsonl");
+//! let client = Client::new().with_ledger(ledger.clone());
+//! // ... generate images
+//!
+//! for (key, usage) in usage_by_day(&ledger.entries().unwrap()) {
+//!     println!("{key:?}: {} images, about {} credits", usage.images, usage.estimated_credits);
+//! }
+//! # });
+//! ```
+//!
+//! Responses answered from the [cache](crate::cache) and callers coalesced with an
+//! identical request in flight are not API calls, so they are not recorded. Credits
+//! are estimated with the [PricingTable](crate::pricing::PricingTable) of the
+//! [BudgetGuard](crate::budget::BudgetGuard) of the client, or the default one.
+use std::{
+    collections::BTreeMap,
+    fmt::Debug,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use reqwest::{
+    header::{HeaderMap, CONTENT_TYPE},
+    StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+
+use crate::{
+    cassette::RecordedBody,
+    client::{FINISH_REASON_HEADER, ORGANIZATION_HEADER, SEED_HEADER},
+    error::StabilityAIError,
+    transport::HttpRequest,
+    types::FinishReason,
+};
+
+const MILLIS_PER_DAY: u64 = 24 * 3600 * 1000;
+
+/// An API call recorded in a ledger
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    /// Milliseconds since the Unix epoch when the call was made
+    pub timestamp_ms: u64,
+    pub method: String,
+    /// Path of the url without the API base, e.g. `/generation/{engine_id}/text-to-image`
+    pub endpoint: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub engine_id: Option<String>,
+    /// Organization the last attempt of the call was billed to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub organization: Option<String>,
+    /// Name of the [PooledKey](crate::key_pool::PooledKey), or the
+    /// [redacted](crate::credentials::ApiKey::redacted) key, of the last attempt
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    /// Request body, with the names of uploaded files instead of their contents
+    #[serde(default)]
+    pub parameters: RecordedBody,
+    /// Status of the response, `None` when no response was received
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Seeds of the generated images
+    #[serde(default)]
+    pub seeds: Vec<i64>,
+    /// Finish reasons of the generated images
+    #[serde(default)]
+    pub finish_reasons: Vec<FinishReason>,
+    pub latency_ms: u64,
+    /// Credits estimated for generation calls
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub estimated_credits: Option<f64>,
+}
+
+impl LedgerEntry {
+    /// Entry of a call about to be made, completed with [LedgerEntry::finish]
+    pub(crate) fn new(
+        request: &HttpRequest,
+        api_base: &str,
+        estimated_credits: Option<f64>,
+    ) -> Self {
+        let endpoint = request
+            .url
+            .strip_prefix(api_base)
+            .unwrap_or(&request.url)
+            .to_string();
+        let engine_id = endpoint
+            .strip_prefix("/generation/")
+            .and_then(|path| path.split('/').next())
+            .map(str::to_string);
+        Self {
+            timestamp_ms: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            method: request.method.to_string(),
+            endpoint,
+            engine_id,
+            organization: organization(request),
+            api_key: None,
+            parameters: RecordedBody::new(&request.body),
+            status: None,
+            error: None,
+            seeds: Vec::new(),
+            finish_reasons: Vec::new(),
+            latency_ms: 0,
+            estimated_credits,
+        }
+    }
+
+    /// Attribute the call to the organization and key of an attempt about to be sent
+    pub(crate) fn attempt(&mut self, request: &HttpRequest, api_key: String) {
+        self.organization = organization(request);
+        self.api_key = Some(api_key);
+    }
+
+    /// Complete the entry with the latency and the status, or error, of the call
+    pub(crate) fn finish(
+        mut self,
+        latency: Duration,
+        outcome: Result<StatusCode, &StabilityAIError>,
+    ) -> Self {
+        self.latency_ms = latency.as_millis() as u64;
+        match outcome {
+            Ok(status) => self.status = Some(status.as_u16()),
+            Err(error) => {
+                self.status = error.status().map(|status| status.as_u16());
+                self.error = Some(error.to_string());
+            }
+        }
+        self
+    }
+
+    /// Record the generated images of a response, from the `Seed` and `Finish-Reason`
+    /// headers of an `image/png` body or from the artifacts of a JSON body
+    pub(crate) fn with_outputs(mut self, headers: &HeaderMap, body: &[u8]) -> Self {
+        let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+        let is_png = header(CONTENT_TYPE.as_str()) == Some("image/png");
+
+        if is_png {
+            self.seeds
+                .extend(header(SEED_HEADER).and_then(|seed| seed.parse::<i64>().ok()));
+            self.finish_reasons
+                .extend(header(FINISH_REASON_HEADER).and_then(|reason| reason.parse().ok()));
+        } else if let Ok(outputs) = serde_json::from_slice::<Outputs>(body) {
+            for output in outputs.artifacts {
+                self.seeds.push(output.seed);
+                self.finish_reasons.push(output.finish_reason);
+            }
+        }
+        self
+    }
+
+    /// Day of the call in UTC, formatted as `YYYY-MM-DD`
+    pub fn day(&self) -> String {
+        let (year, month, day) = civil_from_days((self.timestamp_ms / MILLIS_PER_DAY) as i64);
+        format!("{year:04}-{month:02}-{day:02}")
+    }
+
+    /// Whether the call received a successful response
+    pub fn is_success(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Organization a request is billed to
+fn organization(request: &HttpRequest) -> Option<String> {
+    request
+        .headers
+        .get(ORGANIZATION_HEADER)
+        .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned())
+}
+
+/// Artifacts of a generation response, without their images
+#[derive(Deserialize)]
+struct Outputs {
+    artifacts: Vec<Output>,
+}
+
+#[derive(Deserialize)]
+struct Output {
+    seed: i64,
+    #[serde(rename = "finishReason")]
+    finish_reason: FinishReason,
+}
+
+/// Destination of the [LedgerEntry] of every API call made by a client.
+///
+/// Errors of a sink are logged, they do not fail the API call.
+#[async_trait::async_trait]
+pub trait LedgerSink: Debug + Send + Sync {
+    async fn record(&self, entry: &LedgerEntry) -> Result<(), StabilityAIError>;
+}
+
+/// Ledger appending entries as lines of JSON to a file, created on first write.
+///
+/// Clones append to the same file, one entry at a time.
+#[derive(Debug, Clone)]
+pub struct JsonlLedger {
+    path: PathBuf,
+    lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl JsonlLedger {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            lock: Default::default(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Entries recorded in the file so far, none when the file does not exist
+    pub fn entries(&self) -> Result<Vec<LedgerEntry>, StabilityAIError> {
+        let contents = match std::fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(StabilityAIError::FileReadError(format!(
+                    "{e}, path: {}",
+                    self.path.display()
+                )))
+            }
+        };
+
+        contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                serde_json::from_str(line).map_err(|e| {
+                    StabilityAIError::FileReadError(format!(
+                        "invalid ledger entry: {e}, path: {}, line: {}",
+                        self.path.display(),
+                        index + 1
+                    ))
+                })
+            })
+            .collect()
+    }
+}
+
+#[async_trait::async_trait]
+impl LedgerSink for JsonlLedger {
+    async fn record(&self, entry: &LedgerEntry) -> Result<(), StabilityAIError> {
+        let save_error = |e: std::io::Error| {
+            StabilityAIError::FileSaveError(format!("{e}, path: {}", self.path.display()))
+        };
+
+        let mut line = serde_json::to_vec(entry)
+            .map_err(|e| StabilityAIError::FileSaveError(e.to_string()))?;
+        line.push(b'\n');
+
+        // Lines of concurrent calls must not interleave
+        let _lock = self.lock.lock().await;
+        if let Some(parent) = self
+            .path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(save_error)?;
+        }
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .map_err(save_error)?;
+        file.write_all(&line).await.map_err(save_error)?;
+        file.flush().await.map_err(save_error)
+    }
+}
+
+/// Ledger keeping entries in memory, clones share the same entries
+#[derive(Debug, Clone, Default)]
+pub struct MemoryLedger {
+    entries: Arc<Mutex<Vec<LedgerEntry>>>,
+}
+
+impl MemoryLedger {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Entries recorded so far, in order
+    pub fn entries(&self) -> Vec<LedgerEntry> {
+        self.entries.lock().unwrap().clone()
+    }
+}
+
+#[async_trait::async_trait]
+impl LedgerSink for MemoryLedger {
+    async fn record(&self, entry: &LedgerEntry) -> Result<(), StabilityAIError> {
+        self.entries.lock().unwrap().push(entry.clone());
+        Ok(())
+    }
+}
+
+/// Organization, engine and UTC day which usage is summed up by [usage_by_day]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UsageKey {
+    pub organization: Option<String>,
+    pub engine_id: Option<String>,
+    /// Formatted as `YYYY-MM-DD`
+    pub day: String,
+}
+
+/// Usage summed up over ledger entries
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Usage {
+    pub calls: u64,
+    pub failed_calls: u64,
+    /// Images returned by successful calls
+    pub images: u64,
+    pub estimated_credits: f64,
+}
+
+/// Sum up usage per organization, engine and day.
+///
+/// Credits estimated for failed calls are not counted, as failed calls are not billed.
+pub fn usage_by_day<'a, I>(entries: I) -> BTreeMap<UsageKey, Usage>
+where
+    I: IntoIterator<Item = &'a LedgerEntry>,
+{
+    let mut usage = BTreeMap::<UsageKey, Usage>::new();
+    for entry in entries {
+        let key = UsageKey {
+            organization: entry.organization.clone(),
+            engine_id: entry.engine_id.clone(),
+            day: entry.day(),
+        };
+        let usage = usage.entry(key).or_default();
+        usage.calls += 1;
+        if entry.is_success() {
+            usage.images += entry.seeds.len() as u64;
+            usage.estimated_credits += entry.estimated_credits.unwrap_or_default();
+        } else {
+            usage.failed_calls += 1;
+        }
+    }
+    usage
+}
+
+/// Year, month and day of a number of days since 1970-01-01 in the proleptic
+/// Gregorian calendar, see <http://howardhinnant.github.io/date_algorithms.html>
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
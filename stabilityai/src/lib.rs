@@ -21,6 +21,22 @@
 //!
 //! let client = Client::new()
 //!     .with_http_client(http_client);
+//!
+//! // Use custom transport, e.g. to serve scripted responses in tests
+//! let transport = stabilityai::transport::InMemoryTransport::new();
+//!
+//! let client = Client::new()
+//!     .with_transport(transport.clone());
+//! ```
+//!
+//! ## Loading configuration
+//!
+//! ```no_run
+//! use stabilityai::{config::ClientConfig, Client};
+//!
+//! // Profile named by STABILITY_PROFILE from the file at STABILITY_CONFIG_FILE,
+//! // overridden by STABILITY_* env vars.
+//! let client: Client = ClientConfig::load().unwrap().into();
 //! ```
 //!
 //! ## Making requests
@@ -47,11 +63,31 @@
 //! For full working examples see [examples](https://github.com/64bit/stabilityai/tree/main/examples) directory in the repository.
 //!
 
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod budget;
+pub mod cache;
+pub mod cassette;
 mod client;
+pub mod config;
+pub mod credentials;
+mod dedup;
 mod download;
 mod engine;
 pub mod error;
 mod generate;
+pub mod key_pool;
+pub mod ledger;
+mod meta;
+mod options;
+mod preview;
+pub mod pricing;
+pub mod rate_limit;
+pub mod retry;
+#[cfg(feature = "tower")]
+pub mod service;
+pub mod streaming;
+pub mod transport;
 pub mod types;
 mod user;
 mod util;
@@ -59,9 +95,14 @@ mod util;
 pub use client::Client;
 pub use engine::Engines;
 pub use generate::Generate;
+pub use meta::{WithMeta, REQUEST_ID_HEADERS};
+pub use options::RequestOptions;
+pub use preview::RequestPreview;
 pub use user::User;
 
 pub use client::API_BASE;
 pub use client::CLIENT_ID_HEADER;
 pub use client::CLIENT_VERSION_HEADER;
+pub use client::FINISH_REASON_HEADER;
 pub use client::ORGANIZATION_HEADER;
+pub use client::SEED_HEADER;
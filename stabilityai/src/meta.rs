@@ -0,0 +1,61 @@
+use std::time::Duration;
+
+use reqwest::{
+    header::{HeaderMap, CONTENT_TYPE},
+    StatusCode,
+};
+
+/// Headers commonly used by the API and proxies in front of it to identify a request
+pub const REQUEST_ID_HEADERS: [&str; 4] =
+    ["x-request-id", "request-id", "x-amzn-requestid", "cf-ray"];
+
+/// Response body together with metadata of the HTTP response it was read from.
+///
+/// Returned by the `*_with_meta` variants of [User](crate::User),
+/// [Engines](crate::Engines) and [Generate](crate::Generate) methods.
+#[derive(Debug, Clone)]
+pub struct WithMeta<T> {
+    /// Deserialized response body
+    pub body: T,
+    /// Status of the successful response
+    pub status: StatusCode,
+    /// Headers of the successful response
+    pub headers: HeaderMap,
+    /// Time from the first attempt until the response body was read, including retries
+    pub latency: Duration,
+    /// Number of attempts made, greater than one when the request was retried
+    pub attempts: u32,
+    /// Name of the [pooled key](crate::key_pool::PooledKey) which served the request,
+    /// `None` without an [ApiKeyPool](crate::key_pool::ApiKeyPool)
+    pub api_key_name: Option<String>,
+}
+
+impl<T> WithMeta<T> {
+    pub fn into_body(self) -> T {
+        self.body
+    }
+
+    /// Transform the body keeping response metadata
+    pub fn map<U, F: FnOnce(T) -> U>(self, f: F) -> WithMeta<U> {
+        WithMeta {
+            body: f(self.body),
+            status: self.status,
+            headers: self.headers,
+            latency: self.latency,
+            attempts: self.attempts,
+            api_key_name: self.api_key_name,
+        }
+    }
+
+    /// Value of the first of [REQUEST_ID_HEADERS] present in the response,
+    /// useful to reference a request in support tickets
+    pub fn request_id(&self) -> Option<&str> {
+        REQUEST_ID_HEADERS
+            .iter()
+            .find_map(|name| self.headers.get(*name)?.to_str().ok())
+    }
+
+    pub fn content_type(&self) -> Option<&str> {
+        self.headers.get(CONTENT_TYPE)?.to_str().ok()
+    }
+}
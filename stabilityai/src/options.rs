@@ -0,0 +1,160 @@
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    error::StabilityAIError,
+    transport::{ProgressCallback, UploadProgress},
+};
+
+/// Options of a single API call, given to the `with_options` method of
+/// [User](crate::User), [Engines](crate::Engines) and [Generate](crate::Generate).
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use stabilityai::{Client, RequestOptions};
+///
+/// # tokio_test::block_on(async {
+/// let client = Client::new();
+/// let options = RequestOptions::new()
+///     .with_timeout(Duration::from_secs(5))
+///     .with_organization("tenant-a");
+///
+/// let balance = client.user().with_options(options).balance().await.unwrap();
+/// # });
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct RequestOptions {
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+    cancellation: Option<CancellationToken>,
+    headers: HeaderMap,
+    organization: Option<String>,
+    upload_progress: Option<ProgressCallback>,
+}
+
+impl RequestOptions {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Maximum duration of the call, including retries
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Point in time by which the call must complete, including retries
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Abort the call when the token is cancelled
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Add a header to the request, replacing a client header with the same name
+    pub fn with_header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    /// Add headers to the request, replacing client headers with the same names
+    pub fn with_headers(mut self, headers: HeaderMap) -> Self {
+        self.headers.extend(headers);
+        self
+    }
+
+    /// Organization of the request instead of the client or pooled key organization
+    pub fn with_organization<S: Into<String>>(mut self, organization: S) -> Self {
+        self.organization = Some(organization.into());
+        self
+    }
+
+    /// Report the progress of file uploads of image-to-image requests
+    pub fn with_upload_progress<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(UploadProgress) + Send + Sync + 'static,
+    {
+        self.upload_progress = Some(ProgressCallback::new(callback));
+        self
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    pub fn cancellation(&self) -> Option<&CancellationToken> {
+        self.cancellation.as_ref()
+    }
+
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    pub fn organization(&self) -> Option<&str> {
+        self.organization.as_deref()
+    }
+
+    pub fn upload_progress(&self) -> Option<&ProgressCallback> {
+        self.upload_progress.as_ref()
+    }
+
+    /// Earliest of the deadline and the timeout counted from `started`
+    fn effective_deadline(&self, started: Instant) -> Option<Instant> {
+        let timeout = self.timeout.map(|timeout| started + timeout);
+        match (timeout, self.deadline) {
+            (Some(timeout), Some(deadline)) => Some(timeout.min(deadline)),
+            (timeout, deadline) => timeout.or(deadline),
+        }
+    }
+
+    /// Same options without timeout, deadline and cancellation, for a call shared by
+    /// callers which each bound their own wait with [RequestOptions::run]
+    pub(crate) fn without_limits(&self) -> Self {
+        Self {
+            timeout: None,
+            deadline: None,
+            cancellation: None,
+            ..self.clone()
+        }
+    }
+
+    /// Run the call until it completes, times out or is cancelled
+    pub(crate) async fn run<T, F>(&self, call: F) -> Result<T, StabilityAIError>
+    where
+        F: Future<Output = Result<T, StabilityAIError>>,
+    {
+        let timed = async {
+            match self.effective_deadline(Instant::now()) {
+                Some(deadline) => tokio::time::timeout_at(deadline.into(), call)
+                    .await
+                    .map_err(|_| StabilityAIError::Timeout)?,
+                None => call.await,
+            }
+        };
+
+        match &self.cancellation {
+            Some(token) => {
+                tokio::select! {
+                    biased;
+                    _ = token.cancelled() => Err(StabilityAIError::Cancelled),
+                    result = timed => result,
+                }
+            }
+            None => timed.await,
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use std::fmt::Display;
+
+use reqwest::{
+    header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE},
+    Method,
+};
+
+use crate::{
+    credentials::API_KEY_ENV,
+    transport::{FormPart, HttpRequest, RequestBody},
+};
+
+/// Request a [Client](crate::Client) would send, returned by the `preview_*` methods of
+/// [Generate](crate::Generate) instead of sending it.
+///
+/// The API key is redacted. [RequestPreview::to_curl] renders the request as a `curl`
+/// command, its `Display` implementation does the same.
+///
+/// ```
+/// use stabilityai::{types::TextToImageRequestBodyArgs, Client};
+///
+/// let request = TextToImageRequestBodyArgs::default()
+///     .text_prompts("A lighthouse on a cliff")
+///     .build()
+///     .unwrap();
+///
+/// let preview = Client::new()
+///     .generate("stable-diffusion-v1-6")
+///     .preview_text_to_image(request)
+///     .unwrap();
+///
+/// println!("{preview}");
+/// ```
+#[derive(Debug, Clone)]
+pub struct RequestPreview {
+    pub method: Method,
+    pub url: String,
+    /// Headers of the request, the `Authorization` header is redacted
+    pub headers: HeaderMap,
+    pub body: RequestBody,
+}
+
+impl RequestPreview {
+    pub(crate) fn new(mut request: HttpRequest) -> Self {
+        let mut authorization = HeaderValue::from_static("Bearer [REDACTED]");
+        authorization.set_sensitive(true);
+        request.headers.insert(AUTHORIZATION, authorization);
+
+        if let RequestBody::Json(_) = request.body {
+            request
+                .headers
+                .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        }
+
+        Self {
+            method: request.method,
+            url: request.url,
+            headers: request.headers,
+            body: request.body,
+        }
+    }
+
+    /// Render as a `curl` command reading the API key from STABILITY_API_KEY env var
+    pub fn to_curl(&self) -> String {
+        let mut lines = vec![format!(
+            "curl -f -sS -X {} {}",
+            self.method,
+            shell_quote(&self.url)
+        )];
+
+        for (name, value) in &self.headers {
+            if name == AUTHORIZATION {
+                lines.push(format!("-H \"Authorization: Bearer ${API_KEY_ENV}\""));
+                continue;
+            }
+            let value = String::from_utf8_lossy(value.as_bytes());
+            lines.push(format!("-H {}", shell_quote(&format!("{name}: {value}"))));
+        }
+
+        match &self.body {
+            RequestBody::Empty => {}
+            RequestBody::Json(bytes) => {
+                let json = serde_json::from_slice::<serde_json::Value>(bytes)
+                    .and_then(|value| serde_json::to_string_pretty(&value))
+                    .unwrap_or_else(|_| String::from_utf8_lossy(bytes).into_owned());
+                lines.push(format!("--data-raw {}", shell_quote(&json)));
+            }
+            RequestBody::Multipart(form) => {
+                // Text values starting with @ or < are sent as is with --form-string
+                for (name, part) in &form.parts {
+                    let (option, field) = match part {
+                        FormPart::Text(value) => ("--form-string", format!("{name}={value}")),
+                        FormPart::File(path) => ("-F", format!("{name}=@\"{}\"", path.display())),
+                    };
+                    lines.push(format!("{option} {}", shell_quote(&field)));
+                }
+            }
+        }
+
+        lines.join(" \\\n  ")
+    }
+}
+
+impl Display for RequestPreview {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.to_curl())
+    }
+}
+
+/// Quote for POSIX shells, single quotes are closed, escaped and reopened
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
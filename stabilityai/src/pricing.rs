@@ -0,0 +1,346 @@
+//! Offline estimation of the credits a generation request will cost.
+//!
+//! A [PricingTable] holds the price of an image for each engine, relative to a
+//! number of steps and a size, and estimates the cost of request bodies without
+//! calling the API:
+//!
+//! ```
+//! use stabilityai::{pricing::PricingTable, types::TextToImageRequestBodyArgs};
+//!
+//! let request = TextToImageRequestBodyArgs::default()
+//!     .text_prompts("A lighthouse on a cliff")
+//!     .width(1024_u16)
+//!     .height(1024_u16)
+//!     .steps(30_u32)
+//!     .samples(2)
+//!     .build()?;
+//!
+//! let credits = PricingTable::default().estimate("stable-diffusion-xl-1024-v1-0", &request);
+//! println!("about {credits:.2} credits");
+//! # Ok::<(), stabilityai::error::StabilityAIError>(())
+//! ```
+//!
+//! The default table follows the published prices at the time of writing. Prices
+//! change: update the table with [PricingTable::with_price], or load it from JSON as it
+//! is (de)serializable. The table of a [BudgetGuard](crate::budget::BudgetGuard) is set
+//! with [BudgetGuard::with_pricing](crate::budget::BudgetGuard::with_pricing).
+use std::collections::BTreeMap;
+
+use reqwest::{header::ACCEPT, Method};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    transport::{HttpRequest, MultipartForm, RequestBody},
+    types::{
+        ImageToImageRequestBody, ImageToImageUpscaleBody, LatentUpscalerUpscaleRequestBody,
+        MaskingRequestBody, RealESRGANUpscaleRequestBody, TextToImageRequestBody,
+    },
+};
+
+/// Estimated credits of an image of an engine missing from the pricing table
+pub const DEFAULT_COST_PER_IMAGE: f64 = 0.2;
+
+/// Number of diffusion steps of the API when a request has none
+pub const DEFAULT_STEPS: u32 = 50;
+
+/// Price of an image generated by an engine.
+///
+/// The price scales linearly with the number of steps and of pixels when the
+/// reference steps and size are set, and is flat otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EnginePrice {
+    /// Credits of an image with the reference steps and size
+    pub credits: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reference_steps: Option<u32>,
+    /// Number of pixels of the reference size
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reference_pixels: Option<u32>,
+}
+
+impl EnginePrice {
+    /// Same price for every image, whatever its steps and size
+    pub fn flat(credits: f64) -> Self {
+        Self {
+            credits,
+            reference_steps: None,
+            reference_pixels: None,
+        }
+    }
+
+    /// Scale the price with the number of diffusion steps
+    pub fn with_reference_steps(mut self, steps: u32) -> Self {
+        self.reference_steps = Some(steps);
+        self
+    }
+
+    /// Scale the price with the number of pixels of the image
+    pub fn with_reference_size(mut self, width: u32, height: u32) -> Self {
+        self.reference_pixels = Some(width * height);
+        self
+    }
+
+    /// Estimated credits of a single image
+    ///
+    /// Missing steps default to [DEFAULT_STEPS], a missing size to the reference size
+    /// and a single side to a square image.
+    pub fn image_cost(&self, params: &CostParams) -> f64 {
+        let mut credits = self.credits;
+        if let Some(reference_steps) = self.reference_steps.filter(|steps| *steps > 0) {
+            let steps = params.steps.unwrap_or(DEFAULT_STEPS);
+            credits *= steps as f64 / reference_steps as f64;
+        }
+        if let Some(reference_pixels) = self.reference_pixels.filter(|pixels| *pixels > 0) {
+            let pixels = match (params.width, params.height) {
+                (Some(width), Some(height)) => width as f64 * height as f64,
+                (Some(side), None) | (None, Some(side)) => side as f64 * side as f64,
+                (None, None) => reference_pixels as f64,
+            };
+            credits *= pixels / reference_pixels as f64;
+        }
+        credits
+    }
+}
+
+/// Parameters of a request which its cost depends on, `None` when left to the API
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CostParams {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub steps: Option<u32>,
+    pub samples: Option<u32>,
+}
+
+impl CostParams {
+    /// Parameters of a JSON request body
+    fn from_json(value: &Value) -> Self {
+        let field = |name: &str| {
+            value
+                .get(name)
+                .and_then(Value::as_u64)
+                .and_then(|value| u32::try_from(value).ok())
+        };
+        Self {
+            width: field("width"),
+            height: field("height"),
+            steps: field("steps"),
+            samples: field("samples"),
+        }
+    }
+
+    /// Parameters of a multipart request body
+    fn from_form(form: &MultipartForm) -> Self {
+        let field = |name: &str| form.text_value(name).and_then(|value| value.parse().ok());
+        Self {
+            width: field("width"),
+            height: field("height"),
+            steps: field("steps"),
+            samples: field("samples"),
+        }
+    }
+}
+
+/// Request bodies which cost can be estimated by a [PricingTable]
+pub trait EstimateCost {
+    fn cost_params(&self) -> CostParams;
+}
+
+impl EstimateCost for CostParams {
+    fn cost_params(&self) -> CostParams {
+        *self
+    }
+}
+
+impl EstimateCost for TextToImageRequestBody {
+    fn cost_params(&self) -> CostParams {
+        CostParams {
+            width: self.width.map(u32::from),
+            height: self.height.map(u32::from),
+            steps: self.steps,
+            samples: self.samples.map(u32::from),
+        }
+    }
+}
+
+/// The size of the generated images is the size of the init image, which is not read
+impl EstimateCost for ImageToImageRequestBody {
+    fn cost_params(&self) -> CostParams {
+        CostParams {
+            steps: self.steps,
+            samples: self.samples.map(u32::from),
+            ..Default::default()
+        }
+    }
+}
+
+/// The size of the generated images is the size of the init image, which is not read
+impl EstimateCost for MaskingRequestBody {
+    fn cost_params(&self) -> CostParams {
+        CostParams {
+            steps: self.steps,
+            samples: self.samples.map(u32::from),
+            ..Default::default()
+        }
+    }
+}
+
+impl EstimateCost for LatentUpscalerUpscaleRequestBody {
+    fn cost_params(&self) -> CostParams {
+        CostParams {
+            width: self.width.map(u32::from),
+            height: self.height.map(u32::from),
+            steps: self.steps,
+            samples: None,
+        }
+    }
+}
+
+impl EstimateCost for RealESRGANUpscaleRequestBody {
+    fn cost_params(&self) -> CostParams {
+        CostParams {
+            width: self.width.map(u32::from),
+            height: self.height.map(u32::from),
+            ..Default::default()
+        }
+    }
+}
+
+impl EstimateCost for ImageToImageUpscaleBody {
+    fn cost_params(&self) -> CostParams {
+        match self {
+            ImageToImageUpscaleBody::LatentUpscalerUpscaleRequestBody(body) => body.cost_params(),
+            ImageToImageUpscaleBody::RealESRGANUpscaleRequestBody(body) => body.cost_params(),
+        }
+    }
+}
+
+/// Prices of images by engine id, see the [module](self) documentation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PricingTable {
+    #[serde(default)]
+    engines: BTreeMap<String, EnginePrice>,
+    /// Price of engines missing from the table
+    #[serde(default = "default_fallback")]
+    fallback: EnginePrice,
+}
+
+fn default_fallback() -> EnginePrice {
+    EnginePrice::flat(DEFAULT_COST_PER_IMAGE)
+}
+
+impl Default for PricingTable {
+    fn default() -> Self {
+        Self::new(default_fallback())
+            .with_price(
+                "stable-diffusion-v1-6",
+                EnginePrice::flat(0.2)
+                    .with_reference_steps(30)
+                    .with_reference_size(512, 512),
+            )
+            .with_price(
+                "stable-diffusion-512-v2-1",
+                EnginePrice::flat(0.2)
+                    .with_reference_steps(30)
+                    .with_reference_size(512, 512),
+            )
+            .with_price(
+                "stable-diffusion-768-v2-1",
+                EnginePrice::flat(0.45)
+                    .with_reference_steps(30)
+                    .with_reference_size(768, 768),
+            )
+            .with_price(
+                "stable-diffusion-xl-beta-v2-2-2",
+                EnginePrice::flat(0.2)
+                    .with_reference_steps(30)
+                    .with_reference_size(512, 512),
+            )
+            .with_price(
+                "stable-diffusion-xl-1024-v0-9",
+                EnginePrice::flat(0.2).with_reference_steps(30),
+            )
+            .with_price(
+                "stable-diffusion-xl-1024-v1-0",
+                EnginePrice::flat(0.2).with_reference_steps(30),
+            )
+            .with_price("esrgan-v1-x2plus", EnginePrice::flat(0.2))
+            .with_price(
+                "stable-diffusion-x4-latent-upscaler",
+                EnginePrice::flat(12.0),
+            )
+    }
+}
+
+impl PricingTable {
+    /// Table without engines, where every engine has the `fallback` price
+    pub fn new(fallback: EnginePrice) -> Self {
+        Self {
+            engines: BTreeMap::new(),
+            fallback,
+        }
+    }
+
+    /// Set the price of an engine
+    pub fn with_price<S: Into<String>>(mut self, engine_id: S, price: EnginePrice) -> Self {
+        self.set_price(engine_id, price);
+        self
+    }
+
+    /// Set the price of engines missing from the table
+    pub fn with_fallback(mut self, price: EnginePrice) -> Self {
+        self.fallback = price;
+        self
+    }
+
+    /// Set the price of an engine
+    pub fn set_price<S: Into<String>>(&mut self, engine_id: S, price: EnginePrice) {
+        self.engines.insert(engine_id.into(), price);
+    }
+
+    /// Price of an engine, the fallback price for engines missing from the table
+    pub fn price(&self, engine_id: &str) -> &EnginePrice {
+        self.engines.get(engine_id).unwrap_or(&self.fallback)
+    }
+
+    /// Prices of the engines of the table
+    pub fn engines(&self) -> impl Iterator<Item = (&str, &EnginePrice)> {
+        self.engines
+            .iter()
+            .map(|(engine_id, price)| (engine_id.as_str(), price))
+    }
+
+    /// Estimated credits of a request body sent to an engine, for all its samples
+    pub fn estimate<B: EstimateCost + ?Sized>(&self, engine_id: &str, body: &B) -> f64 {
+        let params = body.cost_params();
+        let samples = params.samples.unwrap_or(1);
+        self.price(engine_id).image_cost(&params) * samples as f64
+    }
+
+    /// Estimated credits of a request, `None` for requests which are not generations
+    pub(crate) fn estimate_request(&self, request: &HttpRequest) -> Option<f64> {
+        if request.method != Method::POST {
+            return None;
+        }
+        let (_, path) = request.url.split_once("/generation/")?;
+        let engine_id = path.split('/').next()?;
+
+        let mut params = match &request.body {
+            RequestBody::Json(bytes) => serde_json::from_slice::<Value>(bytes)
+                .map(|value| CostParams::from_json(&value))
+                .unwrap_or_default(),
+            RequestBody::Multipart(form) => CostParams::from_form(form),
+            RequestBody::Empty => CostParams::default(),
+        };
+        // A single image is returned as `image/png`, whatever the number of samples
+        let binary = request
+            .headers
+            .get(ACCEPT)
+            .is_some_and(|accept| accept.as_bytes() == b"image/png");
+        if binary {
+            params.samples = Some(1);
+        }
+
+        Some(self.estimate(engine_id, &params))
+    }
+}
@@ -0,0 +1,145 @@
+//! Client side rate limiting to stay below the API rate limit proactively.
+//!
+//! A [RateLimiter] combines a token bucket, allowing a number of requests per interval,
+//! with an optional limit on the number of requests in flight. It is shared by all
+//! clones of the [Client](crate::Client) it is configured on.
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Configuration of a [RateLimiter]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimit {
+    requests: u32,
+    interval: Duration,
+    max_in_flight: Option<usize>,
+}
+
+impl Default for RateLimit {
+    /// The documented limit of the API: 150 requests every 10 seconds
+    fn default() -> Self {
+        Self::new(150, Duration::from_secs(10))
+    }
+}
+
+impl RateLimit {
+    /// Allow at most `requests` every `interval`, bursts up to `requests` are allowed
+    pub fn new(requests: u32, interval: Duration) -> Self {
+        Self {
+            requests: requests.max(1),
+            interval,
+            max_in_flight: None,
+        }
+    }
+
+    /// Limit the number of requests waiting for a response at the same time
+    pub fn with_max_in_flight(mut self, max_in_flight: usize) -> Self {
+        self.max_in_flight = Some(max_in_flight.max(1));
+        self
+    }
+
+    pub fn requests(&self) -> u32 {
+        self.requests
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    pub fn max_in_flight(&self) -> Option<usize> {
+        self.max_in_flight
+    }
+}
+
+/// Token bucket and in-flight limiter, clones share the same state.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    limit: RateLimit,
+    bucket: Arc<Mutex<Bucket>>,
+    in_flight: Option<Arc<Semaphore>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+/// Permission to send a request, the in-flight slot is released on drop.
+#[derive(Debug)]
+pub struct RatePermit {
+    _in_flight: Option<OwnedSemaphorePermit>,
+}
+
+impl From<RateLimit> for RateLimiter {
+    fn from(limit: RateLimit) -> Self {
+        Self::new(limit)
+    }
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            bucket: Arc::new(Mutex::new(Bucket {
+                tokens: limit.requests as f64,
+                refilled_at: Instant::now(),
+            })),
+            in_flight: limit.max_in_flight.map(|n| Arc::new(Semaphore::new(n))),
+            limit,
+        }
+    }
+
+    pub fn limit(&self) -> &RateLimit {
+        &self.limit
+    }
+
+    /// Wait until a request can be sent without exceeding the limit
+    pub async fn acquire(&self) -> RatePermit {
+        // Wait for an in-flight slot first so that tokens are not spent while queued
+        let in_flight = match &self.in_flight {
+            Some(semaphore) => Some(
+                semaphore
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("rate limiter semaphore is never closed"),
+            ),
+            None => None,
+        };
+
+        while let Some(wait) = self.try_take_token() {
+            tracing::debug!("Rate limited on client side for {wait:?}");
+            tokio::time::sleep(wait).await;
+        }
+
+        RatePermit {
+            _in_flight: in_flight,
+        }
+    }
+
+    /// Take a token if one is available, otherwise return the time until the next one
+    fn try_take_token(&self) -> Option<Duration> {
+        let mut bucket = self.bucket.lock().unwrap();
+        let capacity = self.limit.requests as f64;
+        let per_token = self.limit.interval.as_secs_f64() / capacity;
+
+        let now = Instant::now();
+        if per_token > 0.0 {
+            let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed / per_token).min(capacity);
+        } else {
+            bucket.tokens = capacity;
+        }
+        bucket.refilled_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            None
+        } else {
+            Some(Duration::from_secs_f64((1.0 - bucket.tokens) * per_token))
+        }
+    }
+}
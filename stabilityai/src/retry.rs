@@ -0,0 +1,206 @@
+//! Classification of failed API calls into transient (retried) and permanent failures.
+//!
+//! Retries are scheduled by the [backoff::ExponentialBackoff] configured with
+//! [Client::with_backoff](crate::Client::with_backoff), which policies can lengthen
+//! with the delay requested by the server in the `Retry-After` header. The maximum
+//! elapsed time of the backoff applies either way.
+use std::{
+    fmt::Debug,
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use backoff::{backoff::Backoff, ExponentialBackoff};
+
+use reqwest::{
+    header::{HeaderMap, RETRY_AFTER},
+    StatusCode,
+};
+
+use crate::error::StabilityAIError;
+
+/// Decides which failed API calls are retried.
+pub trait RetryPolicy: Debug + Send + Sync {
+    /// Whether a response with the given non-success status should be retried
+    fn is_retryable_status(&self, status: StatusCode) -> bool;
+
+    /// Whether an error raised before a response was received should be retried,
+    /// for example a connection reset or a timeout
+    fn is_retryable_error(&self, error: &StabilityAIError) -> bool;
+
+    /// Delay requested by the server before the next attempt.
+    ///
+    /// Defaults to the value of the `Retry-After` header, see [parse_retry_after].
+    fn retry_after(&self, headers: &HeaderMap) -> Option<Duration> {
+        parse_retry_after(headers)
+    }
+}
+
+/// Retries rate limits, server errors from gateways and network failures.
+#[derive(Debug, Clone)]
+pub struct DefaultRetryPolicy {
+    statuses: Vec<StatusCode>,
+    retry_network_errors: bool,
+    honor_retry_after: bool,
+}
+
+impl Default for DefaultRetryPolicy {
+    /// Retry 429, 500, 502, 503 and 504 responses, interrupted connections and timeouts,
+    /// honoring the `Retry-After` header.
+    fn default() -> Self {
+        Self {
+            statuses: vec![
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+            retry_network_errors: true,
+            honor_retry_after: true,
+        }
+    }
+}
+
+impl DefaultRetryPolicy {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Only retry rate limited (429) requests.
+    pub fn rate_limit_only() -> Self {
+        Self {
+            statuses: vec![StatusCode::TOO_MANY_REQUESTS],
+            retry_network_errors: false,
+            honor_retry_after: true,
+        }
+    }
+
+    /// Replace the list of HTTP status codes which are retried
+    pub fn with_statuses<I: IntoIterator<Item = StatusCode>>(mut self, statuses: I) -> Self {
+        self.statuses = statuses.into_iter().collect();
+        self
+    }
+
+    /// Whether to retry interrupted connections and timeouts
+    pub fn with_network_errors(mut self, retry: bool) -> Self {
+        self.retry_network_errors = retry;
+        self
+    }
+
+    /// Whether to wait for the delay in the `Retry-After` header when it is longer than the backoff interval
+    pub fn with_retry_after(mut self, honor: bool) -> Self {
+        self.honor_retry_after = honor;
+        self
+    }
+}
+
+impl RetryPolicy for DefaultRetryPolicy {
+    fn is_retryable_status(&self, status: StatusCode) -> bool {
+        self.statuses.contains(&status)
+    }
+
+    fn is_retryable_error(&self, error: &StabilityAIError) -> bool {
+        self.retry_network_errors && is_network_error(error)
+    }
+
+    fn retry_after(&self, headers: &HeaderMap) -> Option<Duration> {
+        if self.honor_retry_after {
+            parse_retry_after(headers)
+        } else {
+            None
+        }
+    }
+}
+
+/// Backoff of one call which waits at least the delay requested by the server.
+///
+/// The `Retry-After` delay of the last failure only lengthens the interval of the
+/// wrapped backoff. Retries stop when the delay would end after its maximum
+/// elapsed time, rather than retrying earlier than the server asked.
+pub(crate) struct ServerBackoff<'a> {
+    backoff: ExponentialBackoff,
+    retry_after: &'a Mutex<Option<Duration>>,
+}
+
+impl<'a> ServerBackoff<'a> {
+    pub(crate) fn new(
+        backoff: ExponentialBackoff,
+        retry_after: &'a Mutex<Option<Duration>>,
+    ) -> Self {
+        Self {
+            backoff,
+            retry_after,
+        }
+    }
+}
+
+impl Backoff for ServerBackoff<'_> {
+    fn reset(&mut self) {
+        self.backoff.reset();
+    }
+
+    fn next_backoff(&mut self) -> Option<Duration> {
+        let retry_after = self.retry_after.lock().unwrap().take();
+        let interval = self.backoff.next_backoff()?;
+        let Some(retry_after) = retry_after.filter(|delay| *delay > interval) else {
+            return Some(interval);
+        };
+
+        match self.backoff.max_elapsed_time {
+            Some(max_elapsed_time)
+                if self.backoff.get_elapsed_time() + retry_after > max_elapsed_time =>
+            {
+                None
+            }
+            _ => Some(retry_after),
+        }
+    }
+}
+
+/// Whether the error is a timeout or a connection interrupted by the peer.
+///
+/// Failures to resolve or connect to the host are not considered transient.
+pub fn is_network_error(error: &StabilityAIError) -> bool {
+    match error {
+        StabilityAIError::Reqwest(e) => {
+            if e.is_timeout() {
+                return true;
+            }
+
+            // Walk the source chain looking for an interrupted connection
+            let mut source = std::error::Error::source(e);
+            while let Some(inner) = source {
+                if let Some(io_error) = inner.downcast_ref::<std::io::Error>() {
+                    return matches!(
+                        io_error.kind(),
+                        std::io::ErrorKind::ConnectionReset
+                            | std::io::ErrorKind::ConnectionAborted
+                            | std::io::ErrorKind::BrokenPipe
+                            | std::io::ErrorKind::UnexpectedEof
+                            | std::io::ErrorKind::TimedOut
+                    );
+                }
+                source = inner.source();
+            }
+
+            false
+        }
+        _ => false,
+    }
+}
+
+/// Parse `Retry-After` header given either as delay in seconds or as HTTP date.
+pub fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(
+        date.duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    )
+}
@@ -0,0 +1,132 @@
+//! [tower] integration, enabled with the `tower` cargo feature.
+//!
+//! [TransportService] exposes any [HttpTransport] as a [Service], so standard tower
+//! layers (timeout, concurrency limit, rate limit, tracing ...) can be stacked on top.
+//! The resulting service is then used by the client with [Client::with_service]:
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use stabilityai::{service::TransportService, transport::ReqwestTransport, Client};
+//! use tower::ServiceBuilder;
+//!
+//! let service = ServiceBuilder::new()
+//!     .timeout(Duration::from_secs(120))
+//!     .concurrency_limit(4)
+//!     .service(TransportService::new(ReqwestTransport::default()));
+//!
+//! let client = Client::new().with_service(service);
+//! ```
+//!
+//! Layers wrap every attempt, including the retries of [Client::with_retry_policy].
+use std::{
+    fmt::Debug,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use tokio::sync::Mutex;
+use tower::{BoxError, Service};
+
+use crate::{
+    error::StabilityAIError,
+    transport::{HttpRequest, HttpResponse, HttpTransport},
+    Client,
+};
+
+/// [Service] sending requests with an [HttpTransport], the innermost service of a tower stack.
+#[derive(Debug, Clone)]
+pub struct TransportService {
+    transport: Arc<dyn HttpTransport>,
+}
+
+impl TransportService {
+    pub fn new<T: HttpTransport + 'static>(transport: T) -> Self {
+        Self {
+            transport: Arc::new(transport),
+        }
+    }
+}
+
+impl Service<HttpRequest> for TransportService {
+    type Response = HttpResponse;
+    type Error = StabilityAIError;
+    type Future = Pin<Box<dyn Future<Output = Result<HttpResponse, StabilityAIError>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: HttpRequest) -> Self::Future {
+        let transport = self.transport.clone();
+        Box::pin(async move { transport.send(request).await })
+    }
+}
+
+/// [HttpTransport] calling a [Service] for every request.
+///
+/// A single instance of the service is shared by all requests, so layers such as
+/// rate limits keep their state. The service is locked only until it is ready
+/// to accept a request, responses are awaited concurrently.
+pub struct ServiceTransport<S> {
+    service: Mutex<S>,
+}
+
+impl<S> ServiceTransport<S> {
+    pub fn new(service: S) -> Self {
+        Self {
+            service: Mutex::new(service),
+        }
+    }
+}
+
+impl<S> Debug for ServiceTransport<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ServiceTransport { .. }")
+    }
+}
+
+#[async_trait::async_trait]
+impl<S> HttpTransport for ServiceTransport<S>
+where
+    S: Service<HttpRequest, Response = HttpResponse> + Send + 'static,
+    S::Error: Into<BoxError>,
+    S::Future: Send,
+{
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, StabilityAIError> {
+        let response = {
+            let mut service = self.service.lock().await;
+
+            futures::future::poll_fn(|cx| service.poll_ready(cx))
+                .await
+                .map_err(into_stability_error)?;
+
+            service.call(request)
+        };
+
+        response.await.map_err(into_stability_error)
+    }
+}
+
+/// Keep errors of the client as is, and wrap errors raised by layers
+fn into_stability_error<E: Into<BoxError>>(error: E) -> StabilityAIError {
+    match error.into().downcast::<StabilityAIError>() {
+        Ok(error) => *error,
+        Err(error) => StabilityAIError::Transport(error.to_string()),
+    }
+}
+
+impl Client {
+    /// Send requests through a [tower] [Service], typically a stack of layers
+    /// on top of [TransportService].
+    pub fn with_service<S>(self, service: S) -> Self
+    where
+        S: Service<HttpRequest, Response = HttpResponse> + Send + 'static,
+        S::Error: Into<BoxError>,
+        S::Future: Send,
+    {
+        self.with_transport(ServiceTransport::new(service))
+    }
+}
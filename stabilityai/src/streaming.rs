@@ -0,0 +1,559 @@
+//! Decode generated images while the response is received.
+//!
+//! The `*_streamed` methods of [Generate](crate::Generate) parse the `artifacts`
+//! array of the JSON response incrementally and base64-decode every image straight
+//! into an [ImageSink], instead of buffering the response, the base64 strings and
+//! the decoded images. This keeps memory use low for responses with many samples.
+//!
+//! ```no_run
+//! use stabilityai::{streaming::DirSink, types::TextToImageRequestBodyArgs, Client};
+//!
+//! # tokio_test::block_on(async {
+//! let request = TextToImageRequestBodyArgs::default()
+//!     .text_prompts("A lighthouse on a cliff")
+//!     .samples(10)
+//!     .build()
+//!     .unwrap();
+//!
+//! let images = Client::new()
+//!     .generate("stable-diffusion-xl-1024-v1-0")
+//!     .text_to_image_streamed(request, DirSink::new("./data"))
+//!     .await
+//!     .unwrap();
+//!
+//! for image in images {
+//!     println!("{} seed: {}", image.output.display(), image.seed);
+//! }
+//! # });
+//! ```
+//!
+//! Any closure returning an [AsyncWrite] for the index of an image is a sink too,
+//! e.g. `|_| Vec::new()` decodes every image into memory.
+use std::path::{Path, PathBuf};
+
+use base64::{engine::general_purpose, Engine as _};
+use futures::TryStreamExt;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::{
+    download::random_png_path, error::StabilityAIError, transport::ResponseBody,
+    types::FinishReason,
+};
+
+/// Base64 characters decoded at once, a multiple of 4
+const DECODE_CHUNK: usize = 64 * 1024;
+
+/// Destination of the images decoded from a streamed response.
+#[async_trait::async_trait]
+pub trait ImageSink: Send {
+    type Writer: AsyncWrite + Unpin + Send;
+    /// Returned for every image once it is written
+    type Output: Send;
+
+    /// Writer of the image at `index` in the `artifacts` array
+    async fn create(&mut self, index: usize) -> Result<Self::Writer, StabilityAIError>;
+
+    /// Complete the image at `index` after all of its bytes were written
+    async fn finish(
+        &mut self,
+        index: usize,
+        writer: Self::Writer,
+    ) -> Result<Self::Output, StabilityAIError>;
+
+    /// Discard the partially written image at `index` when the response cannot be decoded.
+    ///
+    /// The writer is dropped by default.
+    async fn abort(&mut self, _index: usize, _writer: Self::Writer) {}
+
+    /// Discard the image at `index` which was finished before the response failed to
+    /// decode, as the images are returned all or none.
+    ///
+    /// The output is dropped by default.
+    async fn discard(&mut self, _index: usize, _output: Self::Output) {}
+}
+
+/// Image decoded into an [ImageSink]
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamedImage<O> {
+    /// Output of [ImageSink::finish], e.g. the path of the file written by [DirSink]
+    pub output: O,
+    pub finish_reason: FinishReason,
+    pub seed: i64,
+}
+
+/// Sink writing every image to a PNG file with a random name in a directory.
+///
+/// The directory is created if it does not exist. When the response cannot be
+/// decoded completely, the files of all its images are removed.
+#[derive(Debug, Clone)]
+pub struct DirSink {
+    dir: PathBuf,
+    paths: Vec<PathBuf>,
+    dir_created: bool,
+}
+
+impl DirSink {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            paths: Vec::new(),
+            dir_created: false,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl ImageSink for DirSink {
+    type Writer = tokio::fs::File;
+    type Output = PathBuf;
+
+    async fn create(&mut self, index: usize) -> Result<Self::Writer, StabilityAIError> {
+        if !self.dir_created {
+            tokio::fs::create_dir_all(&self.dir).await.map_err(|e| {
+                StabilityAIError::FileSaveError(format!("{e}, path: {}", self.dir.display()))
+            })?;
+            self.dir_created = true;
+        }
+        let path = random_png_path(&self.dir);
+        let file = tokio::fs::File::create(&path).await.map_err(|e| {
+            StabilityAIError::FileSaveError(format!("{e}, path: {}", path.display()))
+        })?;
+        self.paths.resize(index + 1, PathBuf::new());
+        self.paths[index] = path;
+        Ok(file)
+    }
+
+    async fn finish(
+        &mut self,
+        index: usize,
+        mut writer: Self::Writer,
+    ) -> Result<Self::Output, StabilityAIError> {
+        let path = std::mem::take(&mut self.paths[index]);
+        if let Err(e) = writer.flush().await {
+            drop(writer);
+            remove_image_file(&path).await;
+            return Err(StabilityAIError::FileSaveError(format!(
+                "{e}, path: {}",
+                path.display()
+            )));
+        }
+        Ok(path)
+    }
+
+    async fn abort(&mut self, index: usize, writer: Self::Writer) {
+        drop(writer);
+        remove_image_file(&std::mem::take(&mut self.paths[index])).await;
+    }
+
+    async fn discard(&mut self, _index: usize, output: Self::Output) {
+        remove_image_file(&output).await;
+    }
+}
+
+async fn remove_image_file(path: &Path) {
+    if let Err(e) = tokio::fs::remove_file(path).await {
+        tracing::warn!("Failed to remove image {}: {e}", path.display());
+    }
+}
+
+#[async_trait::async_trait]
+impl<F, W> ImageSink for F
+where
+    F: FnMut(usize) -> W + Send,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    type Writer = W;
+    type Output = W;
+
+    async fn create(&mut self, index: usize) -> Result<Self::Writer, StabilityAIError> {
+        Ok(self(index))
+    }
+
+    async fn finish(
+        &mut self,
+        _index: usize,
+        mut writer: Self::Writer,
+    ) -> Result<Self::Output, StabilityAIError> {
+        writer
+            .flush()
+            .await
+            .map_err(|e| StabilityAIError::FileSaveError(e.to_string()))?;
+        Ok(writer)
+    }
+}
+
+/// JSON object or array the scanner is in
+#[derive(Debug)]
+struct Frame {
+    is_object: bool,
+    /// Last key read in an object
+    key: Vec<u8>,
+    /// Whether the next string of an object is a key
+    expecting_key: bool,
+}
+
+/// What the characters of the current string or scalar are read into
+#[derive(Debug, PartialEq)]
+enum Capture {
+    None,
+    Key,
+    Value,
+    Base64,
+}
+
+/// Fields of the artifact being read
+#[derive(Debug)]
+struct Artifact<W> {
+    index: usize,
+    writer: Option<W>,
+    written: bool,
+    finish_reason: Option<FinishReason>,
+    seed: Option<i64>,
+}
+
+/// Incremental parser of `{"artifacts": [{"base64": ..., "finishReason": ..., "seed": ...}]}`
+struct Decoder<S: ImageSink> {
+    sink: S,
+    stack: Vec<Frame>,
+    in_string: bool,
+    escape: bool,
+    /// Value and number of hex digits read of a `\uXXXX` escape
+    unicode_escape: Option<(u32, u8)>,
+    capture: Capture,
+    buffer: Vec<u8>,
+    artifact: Option<Artifact<S::Writer>>,
+    images: Vec<StreamedImage<S::Output>>,
+}
+
+fn invalid(message: String) -> StabilityAIError {
+    StabilityAIError::JSONDeserialize(<serde_json::Error as serde::de::Error>::custom(message))
+}
+
+fn write_error(e: std::io::Error) -> StabilityAIError {
+    StabilityAIError::FileSaveError(e.to_string())
+}
+
+impl<S: ImageSink> Decoder<S> {
+    fn new(sink: S) -> Self {
+        Self {
+            sink,
+            stack: Vec::new(),
+            in_string: false,
+            escape: false,
+            unicode_escape: None,
+            capture: Capture::None,
+            buffer: Vec::new(),
+            artifact: None,
+            images: Vec::new(),
+        }
+    }
+
+    /// Whether the scanner is directly in an object of the root `artifacts` array
+    fn in_artifact(&self) -> bool {
+        matches!(
+            self.stack.as_slice(),
+            [root, array, artifact]
+                if root.is_object && root.key == b"artifacts" && !array.is_object && artifact.is_object
+        )
+    }
+
+    async fn feed(&mut self, mut chunk: &[u8]) -> Result<(), StabilityAIError> {
+        while let Some((&byte, rest)) = chunk.split_first() {
+            // Copy base64 up to the end of the string or an escape at once
+            if self.capture == Capture::Base64 && !self.escape && self.unicode_escape.is_none() {
+                let len = chunk
+                    .iter()
+                    .position(|byte| matches!(byte, b'"' | b'\\'))
+                    .unwrap_or(chunk.len());
+                if len > 0 {
+                    self.buffer.extend_from_slice(&chunk[..len]);
+                    if self.buffer.len() >= DECODE_CHUNK {
+                        self.write_base64(false).await?;
+                    }
+                    chunk = &chunk[len..];
+                    continue;
+                }
+            }
+
+            if self.in_string {
+                self.string_byte(byte).await?;
+            } else {
+                self.structural_byte(byte).await?;
+            }
+            chunk = rest;
+        }
+        Ok(())
+    }
+
+    async fn string_byte(&mut self, byte: u8) -> Result<(), StabilityAIError> {
+        if let Some((value, digits)) = self.unicode_escape {
+            let digit = (byte as char)
+                .to_digit(16)
+                .ok_or_else(|| invalid("invalid unicode escape in base64".into()))?;
+            let value = value * 16 + digit;
+            if digits < 3 {
+                self.unicode_escape = Some((value, digits + 1));
+            } else {
+                self.unicode_escape = None;
+                match char::from_u32(value) {
+                    Some(c) if is_base64_char(c) => self.base64_byte(c as u8).await?,
+                    _ => {
+                        return Err(invalid(format!(
+                            "escaped character \\u{value:04x} is not base64"
+                        )))
+                    }
+                }
+            }
+            return Ok(());
+        }
+
+        if self.escape {
+            self.escape = false;
+            match self.capture {
+                // `/` and `\uXXXX` escapes of base64 characters are decoded
+                Capture::Base64 if byte == b'/' => self.base64_byte(byte).await?,
+                Capture::Base64 if byte == b'u' => self.unicode_escape = Some((0, 0)),
+                Capture::Base64 => return Err(invalid("invalid escape in base64".into())),
+                Capture::None => {}
+                _ => self.buffer.extend_from_slice(&[b'\\', byte]),
+            }
+            return Ok(());
+        }
+
+        match byte {
+            b'\\' => self.escape = true,
+            b'"' => {
+                self.in_string = false;
+                self.end_string().await?;
+            }
+            _ => match self.capture {
+                Capture::None => {}
+                Capture::Base64 => self.base64_byte(byte).await?,
+                _ => self.buffer.push(byte),
+            },
+        }
+        Ok(())
+    }
+
+    async fn base64_byte(&mut self, byte: u8) -> Result<(), StabilityAIError> {
+        self.buffer.push(byte);
+        if self.buffer.len() >= DECODE_CHUNK {
+            self.write_base64(false).await?;
+        }
+        Ok(())
+    }
+
+    async fn structural_byte(&mut self, byte: u8) -> Result<(), StabilityAIError> {
+        match byte {
+            b'"' => {
+                self.in_string = true;
+                self.start_string().await?;
+            }
+            b'{' | b'[' => {
+                self.end_scalar()?;
+                self.stack.push(Frame {
+                    is_object: byte == b'{',
+                    key: Vec::new(),
+                    expecting_key: byte == b'{',
+                });
+                if self.in_artifact() {
+                    self.artifact = Some(Artifact {
+                        index: self.images.len(),
+                        writer: None,
+                        written: false,
+                        finish_reason: None,
+                        seed: None,
+                    });
+                }
+            }
+            b'}' | b']' => {
+                self.end_scalar()?;
+                if self.in_artifact() {
+                    self.end_artifact().await?;
+                }
+                self.stack
+                    .pop()
+                    .ok_or_else(|| invalid("unbalanced JSON".into()))?;
+            }
+            b':' => {
+                if let Some(frame) = self.stack.last_mut() {
+                    frame.expecting_key = false;
+                }
+            }
+            b',' => {
+                self.end_scalar()?;
+                if let Some(frame) = self.stack.last_mut() {
+                    frame.expecting_key = frame.is_object;
+                }
+            }
+            byte if byte.is_ascii_whitespace() => self.end_scalar()?,
+            byte => {
+                if self.capture == Capture::None && self.in_artifact() {
+                    self.capture = Capture::Value;
+                }
+                if self.capture == Capture::Value {
+                    self.buffer.push(byte);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn start_string(&mut self) -> Result<(), StabilityAIError> {
+        self.buffer.clear();
+        let expecting_key = self
+            .stack
+            .last()
+            .map(|frame| frame.is_object && frame.expecting_key)
+            .unwrap_or(false);
+
+        self.capture = if expecting_key {
+            Capture::Key
+        } else if !self.in_artifact() {
+            Capture::None
+        } else if self.stack.last().map(|frame| frame.key.as_slice()) == Some(b"base64") {
+            let artifact = self.artifact.as_mut().expect("artifact is open");
+            artifact.writer = Some(self.sink.create(artifact.index).await?);
+            Capture::Base64
+        } else {
+            self.buffer.push(b'"');
+            Capture::Value
+        };
+        Ok(())
+    }
+
+    async fn end_string(&mut self) -> Result<(), StabilityAIError> {
+        match self.capture {
+            Capture::Key => {
+                let key = std::mem::take(&mut self.buffer);
+                if let Some(frame) = self.stack.last_mut() {
+                    frame.key = key;
+                }
+                self.capture = Capture::None;
+            }
+            Capture::Base64 => {
+                self.write_base64(true).await?;
+                self.capture = Capture::None;
+            }
+            Capture::Value => {
+                self.buffer.push(b'"');
+                self.end_scalar()?;
+            }
+            Capture::None => {}
+        }
+        Ok(())
+    }
+
+    /// Set the field of the artifact from the scalar or string read
+    fn end_scalar(&mut self) -> Result<(), StabilityAIError> {
+        if self.capture != Capture::Value {
+            return Ok(());
+        }
+        self.capture = Capture::None;
+
+        let value = std::mem::take(&mut self.buffer);
+        let key = self.stack.last().map(|frame| frame.key.as_slice());
+        let artifact = self.artifact.as_mut().expect("artifact is open");
+        match key {
+            Some(b"finishReason") => {
+                artifact.finish_reason = Some(
+                    serde_json::from_slice(&value).map_err(StabilityAIError::JSONDeserialize)?,
+                )
+            }
+            Some(b"seed") => {
+                artifact.seed = Some(
+                    serde_json::from_slice(&value).map_err(StabilityAIError::JSONDeserialize)?,
+                )
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Decode the buffered base64 into the writer, all of it at the end of the string
+    async fn write_base64(&mut self, end: bool) -> Result<(), StabilityAIError> {
+        let len = if end {
+            self.buffer.len()
+        } else {
+            self.buffer.len() - self.buffer.len() % 4
+        };
+        let bytes = general_purpose::STANDARD
+            .decode(&self.buffer[..len])
+            .map_err(|e| invalid(format!("invalid base64: {e}")))?;
+        self.buffer.drain(..len);
+
+        let artifact = self.artifact.as_mut().expect("artifact is open");
+        let writer = artifact.writer.as_mut().expect("writer is created");
+        writer.write_all(&bytes).await.map_err(write_error)?;
+        artifact.written |= end;
+        Ok(())
+    }
+
+    async fn end_artifact(&mut self) -> Result<(), StabilityAIError> {
+        // The artifact stays open on a missing field, for its image to be aborted
+        let artifact = self.artifact.as_mut().expect("artifact is open");
+        let missing = |field: &str| invalid(format!("missing field `{field}` in artifact"));
+
+        if !artifact.written {
+            return Err(missing("base64"));
+        }
+        let finish_reason = artifact
+            .finish_reason
+            .take()
+            .ok_or_else(|| missing("finishReason"))?;
+        let seed = artifact.seed.ok_or_else(|| missing("seed"))?;
+
+        let artifact = self.artifact.take().expect("artifact is open");
+        let writer = artifact.writer.expect("writer is created");
+        let output = self.sink.finish(artifact.index, writer).await?;
+        self.images.push(StreamedImage {
+            output,
+            finish_reason,
+            seed,
+        });
+        Ok(())
+    }
+
+    async fn decode(&mut self, body: ResponseBody) -> Result<(), StabilityAIError> {
+        let mut stream = body.into_stream();
+        while let Some(chunk) = stream.try_next().await? {
+            self.feed(&chunk).await?;
+        }
+        if self.in_string || !self.stack.is_empty() {
+            return Err(invalid("response body ended before the end of JSON".into()));
+        }
+        Ok(())
+    }
+
+    /// Let the sink discard the image being written and the images already finished
+    async fn abort(&mut self) {
+        if let Some(Artifact {
+            index,
+            writer: Some(writer),
+            ..
+        }) = self.artifact.take()
+        {
+            self.sink.abort(index, writer).await;
+        }
+        for (index, image) in std::mem::take(&mut self.images).into_iter().enumerate() {
+            self.sink.discard(index, image.output).await;
+        }
+    }
+}
+
+fn is_base64_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '+' | '/' | '=')
+}
+
+/// Decode the images of a JSON artifacts response body into the sink
+pub(crate) async fn decode_artifacts<S: ImageSink>(
+    body: ResponseBody,
+    sink: S,
+) -> Result<Vec<StreamedImage<S::Output>>, StabilityAIError> {
+    let mut decoder = Decoder::new(sink);
+    if let Err(e) = decoder.decode(body).await {
+        decoder.abort().await;
+        return Err(e);
+    }
+    Ok(decoder.images)
+}
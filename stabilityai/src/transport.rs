@@ -0,0 +1,427 @@
+//! HTTP transport used by [Client](crate::Client) to send requests.
+//!
+//! [ReqwestTransport] is used by default. [InMemoryTransport] serves scripted
+//! responses and records outgoing requests, which is useful to test code calling
+//! the API without a network connection.
+use std::{
+    collections::VecDeque,
+    fmt::Debug,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use bytes::{Bytes, BytesMut};
+use futures::{stream::BoxStream, Stream, StreamExt, TryStreamExt};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE},
+    Method, StatusCode,
+};
+use serde::Serialize;
+
+use crate::{error::StabilityAIError, util::create_file_part};
+
+/// Sends HTTP requests on behalf of [Client](crate::Client).
+#[async_trait::async_trait]
+pub trait HttpTransport: Debug + Send + Sync {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, StabilityAIError>;
+}
+
+/// HTTP request made by the client.
+#[derive(Debug, Clone)]
+pub struct HttpRequest {
+    pub method: Method,
+    pub url: String,
+    pub headers: HeaderMap,
+    pub body: RequestBody,
+    /// Called as the files of a multipart body are uploaded
+    pub upload_progress: Option<ProgressCallback>,
+}
+
+impl HttpRequest {
+    pub fn new<S: Into<String>>(method: Method, url: S) -> Self {
+        Self {
+            method,
+            url: url.into(),
+            headers: HeaderMap::new(),
+            body: RequestBody::Empty,
+            upload_progress: None,
+        }
+    }
+
+    /// Serialize request as JSON body
+    pub fn json<I: Serialize>(mut self, request: &I) -> Result<Self, StabilityAIError> {
+        let bytes = serde_json::to_vec(request).map_err(|e| {
+            StabilityAIError::InvalidArgument(format!("failed to serialize request: {e}"))
+        })?;
+        self.body = RequestBody::Json(bytes.into());
+        Ok(self)
+    }
+
+    pub fn multipart(mut self, form: MultipartForm) -> Self {
+        self.body = RequestBody::Multipart(form);
+        self
+    }
+}
+
+/// Body of [HttpRequest]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum RequestBody {
+    #[default]
+    Empty,
+    /// Serialized JSON document
+    Json(Bytes),
+    /// `multipart/form-data` fields, files are read when the request is sent
+    Multipart(MultipartForm),
+}
+
+/// Fields of a `multipart/form-data` request in the order they are sent.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MultipartForm {
+    pub parts: Vec<(String, FormPart)>,
+}
+
+/// A single field of [MultipartForm]
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormPart {
+    Text(String),
+    /// File uploaded from the file system
+    File(PathBuf),
+}
+
+impl MultipartForm {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Add a text field
+    pub fn text<N: Into<String>, V: Into<String>>(mut self, name: N, value: V) -> Self {
+        self.parts.push((name.into(), FormPart::Text(value.into())));
+        self
+    }
+
+    /// Add a file field, the file is read when the request is sent
+    pub fn file<N: Into<String>, P: AsRef<Path>>(mut self, name: N, path: P) -> Self {
+        self.parts
+            .push((name.into(), FormPart::File(PathBuf::from(path.as_ref()))));
+        self
+    }
+
+    /// Value of the first text field with given name
+    pub fn text_value(&self, name: &str) -> Option<&str> {
+        self.parts.iter().find_map(|(n, part)| match part {
+            FormPart::Text(value) if n == name => Some(value.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Path of the first file field with given name
+    pub fn file_path(&self, name: &str) -> Option<&Path> {
+        self.parts.iter().find_map(|(n, part)| match part {
+            FormPart::File(path) if n == name => Some(path.as_path()),
+            _ => None,
+        })
+    }
+
+    /// Create the [reqwest] form, opening every file for streaming upload
+    pub(crate) async fn into_reqwest_form(
+        self,
+        progress: Option<ProgressCallback>,
+    ) -> Result<reqwest::multipart::Form, StabilityAIError> {
+        let tracker = match progress {
+            Some(callback) => Some(ProgressTracker::new(callback, self.files_len().await?)),
+            None => None,
+        };
+
+        let mut multipart = reqwest::multipart::Form::new();
+        for (name, part) in self.parts {
+            multipart = match part {
+                FormPart::Text(value) => multipart.text(name, value),
+                FormPart::File(path) => {
+                    multipart.part(name, create_file_part(path, tracker.clone()).await?)
+                }
+            };
+        }
+        Ok(multipart)
+    }
+
+    /// Total size of the files of the form
+    async fn files_len(&self) -> Result<u64, StabilityAIError> {
+        let mut total = 0;
+        for (_, part) in &self.parts {
+            if let FormPart::File(path) = part {
+                let metadata = tokio::fs::metadata(path).await.map_err(|e| {
+                    StabilityAIError::FileReadError(format!("{e}, path: {}", path.display()))
+                })?;
+                total += metadata.len();
+            }
+        }
+        Ok(total)
+    }
+}
+
+/// Progress of the upload of the files of a multipart request.
+///
+/// Progress counts the file bytes read by the request body as the connection pulls it,
+/// not the bytes written to the socket: the last chunks may still be buffered by the
+/// connection when `sent` reaches `total`. Text fields are small and not counted.
+/// A retried request reports its progress from zero again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UploadProgress {
+    /// File bytes read for the upload so far
+    pub sent: u64,
+    /// Size of all files of the request
+    pub total: u64,
+}
+
+/// Callback receiving the [UploadProgress] of a request, see
+/// [RequestOptions::with_upload_progress](crate::RequestOptions::with_upload_progress).
+#[derive(Clone)]
+pub struct ProgressCallback(Arc<dyn Fn(UploadProgress) + Send + Sync>);
+
+impl ProgressCallback {
+    pub fn new<F>(callback: F) -> Self
+    where
+        F: Fn(UploadProgress) + Send + Sync + 'static,
+    {
+        Self(Arc::new(callback))
+    }
+
+    pub fn report(&self, progress: UploadProgress) {
+        (self.0)(progress)
+    }
+}
+
+impl Debug for ProgressCallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ProgressCallback")
+    }
+}
+
+/// Bytes read from all file streams of a form, clones share the count
+#[derive(Debug, Clone)]
+pub(crate) struct ProgressTracker {
+    callback: ProgressCallback,
+    sent: Arc<AtomicU64>,
+    total: u64,
+}
+
+impl ProgressTracker {
+    fn new(callback: ProgressCallback, total: u64) -> Self {
+        Self {
+            callback,
+            sent: Default::default(),
+            total,
+        }
+    }
+
+    pub(crate) fn advance(&self, len: usize) {
+        let sent = self.sent.fetch_add(len as u64, Ordering::Relaxed) + len as u64;
+        self.callback.report(UploadProgress {
+            sent,
+            total: self.total,
+        });
+    }
+}
+
+/// HTTP response received by the client.
+#[derive(Debug)]
+pub struct HttpResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: ResponseBody,
+}
+
+impl HttpResponse {
+    pub fn new<B: Into<ResponseBody>>(status: StatusCode, body: B) -> Self {
+        Self {
+            status,
+            headers: HeaderMap::new(),
+            body: body.into(),
+        }
+    }
+
+    /// Response with given status and `application/json` body
+    pub fn json<O: Serialize>(status: StatusCode, body: &O) -> Result<Self, StabilityAIError> {
+        let bytes = serde_json::to_vec(body).map_err(|e| {
+            StabilityAIError::InvalidArgument(format!("failed to serialize response: {e}"))
+        })?;
+        Ok(Self::new(status, bytes).with_header(CONTENT_TYPE.as_str(), "application/json"))
+    }
+
+    /// Add a response header, panics on invalid header name or value
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.insert(
+            HeaderName::from_bytes(name.as_bytes()).expect("invalid header name"),
+            HeaderValue::from_str(value).expect("invalid header value"),
+        );
+        self
+    }
+}
+
+/// Body of [HttpResponse] as a stream of chunks.
+pub struct ResponseBody(BoxStream<'static, Result<Bytes, StabilityAIError>>);
+
+impl ResponseBody {
+    pub fn from_stream<S>(stream: S) -> Self
+    where
+        S: Stream<Item = Result<Bytes, StabilityAIError>> + Send + 'static,
+    {
+        Self(stream.boxed())
+    }
+
+    /// Read the whole body into memory
+    pub async fn bytes(self) -> Result<Bytes, StabilityAIError> {
+        let mut stream = self.0;
+        let mut buffer = BytesMut::new();
+        while let Some(chunk) = stream.try_next().await? {
+            if buffer.is_empty() {
+                buffer = BytesMut::from(chunk.as_ref());
+            } else {
+                buffer.extend_from_slice(&chunk);
+            }
+        }
+        Ok(buffer.freeze())
+    }
+
+    pub fn into_stream(self) -> BoxStream<'static, Result<Bytes, StabilityAIError>> {
+        self.0
+    }
+}
+
+impl Debug for ResponseBody {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ResponseBody { .. }")
+    }
+}
+
+impl From<Bytes> for ResponseBody {
+    fn from(value: Bytes) -> Self {
+        Self::from_stream(futures::stream::once(async move { Ok(value) }))
+    }
+}
+
+impl From<Vec<u8>> for ResponseBody {
+    fn from(value: Vec<u8>) -> Self {
+        Bytes::from(value).into()
+    }
+}
+
+impl From<String> for ResponseBody {
+    fn from(value: String) -> Self {
+        Bytes::from(value).into()
+    }
+}
+
+impl From<&'static str> for ResponseBody {
+    fn from(value: &'static str) -> Self {
+        Bytes::from_static(value.as_bytes()).into()
+    }
+}
+
+impl From<&'static [u8]> for ResponseBody {
+    fn from(value: &'static [u8]) -> Self {
+        Bytes::from_static(value).into()
+    }
+}
+
+/// Default transport sending requests with [reqwest::Client].
+#[derive(Debug, Clone, Default)]
+pub struct ReqwestTransport {
+    http_client: reqwest::Client,
+}
+
+impl ReqwestTransport {
+    pub fn new(http_client: reqwest::Client) -> Self {
+        Self { http_client }
+    }
+}
+
+#[async_trait::async_trait]
+impl HttpTransport for ReqwestTransport {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, StabilityAIError> {
+        let mut builder = self
+            .http_client
+            .request(request.method, request.url)
+            .headers(request.headers);
+
+        builder = match request.body {
+            RequestBody::Empty => builder,
+            RequestBody::Json(bytes) => builder
+                .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+                .body(bytes),
+            RequestBody::Multipart(form) => {
+                builder.multipart(form.into_reqwest_form(request.upload_progress).await?)
+            }
+        };
+
+        let response = builder.send().await?;
+
+        Ok(HttpResponse {
+            status: response.status(),
+            headers: response.headers().clone(),
+            body: ResponseBody::from_stream(
+                response.bytes_stream().map_err(StabilityAIError::Reqwest),
+            ),
+        })
+    }
+}
+
+/// Transport replying with scripted responses in order and recording every request.
+///
+/// Clones share the same responses and recorded requests, so a clone can be kept
+/// to inspect the requests after passing the transport to
+/// [Client::with_transport](crate::Client::with_transport).
+///
+/// Files of multipart requests are not read, so upload progress is never reported.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryTransport {
+    inner: Arc<Mutex<InMemoryState>>,
+}
+
+#[derive(Debug, Default)]
+struct InMemoryState {
+    responses: VecDeque<HttpResponse>,
+    requests: Vec<HttpRequest>,
+}
+
+impl InMemoryTransport {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Queue a response to be returned for the next request without a response yet
+    pub fn push_response(&self, response: HttpResponse) {
+        self.inner.lock().unwrap().responses.push_back(response);
+    }
+
+    /// Queue a response, for chaining at construction time
+    pub fn with_response(self, response: HttpResponse) -> Self {
+        self.push_response(response);
+        self
+    }
+
+    /// Requests received so far, in order
+    pub fn requests(&self) -> Vec<HttpRequest> {
+        self.inner.lock().unwrap().requests.clone()
+    }
+
+    /// Number of scripted responses not yet returned
+    pub fn remaining_responses(&self) -> usize {
+        self.inner.lock().unwrap().responses.len()
+    }
+}
+
+#[async_trait::async_trait]
+impl HttpTransport for InMemoryTransport {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, StabilityAIError> {
+        let mut state = self.inner.lock().unwrap();
+        let url = request.url.clone();
+        state.requests.push(request);
+        state.responses.pop_front().ok_or_else(|| {
+            StabilityAIError::Transport(format!("no scripted response left for {url}"))
+        })
+    }
+}
@@ -0,0 +1,150 @@
+//! Client side validation of image dimensions, following the rules of the API for
+//! each engine family.
+use crate::error::StabilityAIError;
+
+use super::{TextToImageRequestBody, TextToImageRequestBodyArgs};
+
+/// Dimensions of SDXL v0.9 and v1.0 engines, as `(width, height)`
+pub const SDXL_DIMENSIONS: [(u16, u16); 9] = [
+    (1024, 1024),
+    (1152, 896),
+    (1216, 832),
+    (1344, 768),
+    (1536, 640),
+    (640, 1536),
+    (768, 1344),
+    (832, 1216),
+    (896, 1152),
+];
+
+/// Width or height used by the API when the request has none
+const DEFAULT_SIDE: u16 = 512;
+const MIN_SIDE: u16 = 128;
+const MAX_PIXELS: u32 = 1_048_576;
+
+/// Dimension rules of an engine family
+enum Rules {
+    /// Bounds on the number of pixels, for 512 and 768 engines
+    Pixels {
+        min: u32,
+        engines: &'static str,
+    },
+    SdxlBeta,
+    /// Fixed list of dimensions of SDXL v0.9 and v1.0
+    Sdxl,
+    /// Engines without known rules besides the multiples of 64
+    Other,
+}
+
+impl Rules {
+    fn of(engine_id: &str) -> Self {
+        if engine_id.contains("xl-beta") {
+            Rules::SdxlBeta
+        } else if engine_id.contains("xl-1024-v0-9") || engine_id.contains("xl-1024-v1-0") {
+            Rules::Sdxl
+        } else if engine_id.contains("768") {
+            Rules::Pixels {
+                min: 589_824,
+                engines: "768 engines",
+            }
+        } else if engine_id.contains("512") {
+            Rules::Pixels {
+                min: 262_144,
+                engines: "512 engines",
+            }
+        } else {
+            Rules::Other
+        }
+    }
+}
+
+/// Check `width` and `height` of a generation against the rules of the engine, as
+/// described on [TextToImageRequestBody::height] and [TextToImageRequestBody::width].
+///
+/// A missing dimension is the default of the API, 512. Engines which rules are unknown,
+/// e.g. newer engines, are only checked for multiples of 64 of at least 128.
+pub fn validate_dimensions(
+    engine_id: &str,
+    width: Option<u16>,
+    height: Option<u16>,
+) -> Result<(), StabilityAIError> {
+    let invalid = |message: String| {
+        Err(StabilityAIError::InvalidArgument(format!(
+            "invalid dimensions for engine {engine_id}: {message}"
+        )))
+    };
+
+    for (name, side) in [("width", width), ("height", height)] {
+        let Some(side) = side else { continue };
+        if side % 64 != 0 {
+            return invalid(format!("{name} {side} is not a multiple of 64"));
+        }
+        if side < MIN_SIDE {
+            return invalid(format!("{name} {side} is less than {MIN_SIDE}"));
+        }
+    }
+
+    let (width, height) = (
+        width.unwrap_or(DEFAULT_SIDE),
+        height.unwrap_or(DEFAULT_SIDE),
+    );
+    match Rules::of(engine_id) {
+        Rules::Pixels { min, engines } => {
+            let pixels = width as u32 * height as u32;
+            if !(min..=MAX_PIXELS).contains(&pixels) {
+                return invalid(format!(
+                    "{width}x{height} is {pixels} pixels, {engines} require between {min} and {MAX_PIXELS} pixels"
+                ));
+            }
+        }
+        Rules::SdxlBeta => {
+            for (name, side, other_name, other) in [
+                ("width", width, "height", height),
+                ("height", height, "width", width),
+            ] {
+                if side > 896 {
+                    return invalid(format!("{name} {side} is greater than 896 for SDXL Beta"));
+                }
+                if other > 512 && side > 512 {
+                    return invalid(format!(
+                        "{name} {side} is greater than 512 while {other_name} {other} is greater than 512 for SDXL Beta"
+                    ));
+                }
+            }
+        }
+        Rules::Sdxl => {
+            if !SDXL_DIMENSIONS.contains(&(width, height)) {
+                let dimensions: Vec<_> = SDXL_DIMENSIONS
+                    .iter()
+                    .map(|(w, h)| format!("{w}x{h}"))
+                    .collect();
+                return invalid(format!(
+                    "{width}x{height} is not one of the SDXL dimensions {}",
+                    dimensions.join(", ")
+                ));
+            }
+        }
+        Rules::Other => {}
+    }
+    Ok(())
+}
+
+impl TextToImageRequestBody {
+    /// Check the dimensions of the request against the rules of the engine, see
+    /// [validate_dimensions]
+    pub fn validate_dimensions(&self, engine_id: &str) -> Result<(), StabilityAIError> {
+        validate_dimensions(engine_id, self.width, self.height)
+    }
+}
+
+impl TextToImageRequestBodyArgs {
+    /// Build the request and check its dimensions against the rules of the engine
+    pub fn build_for_engine(
+        &self,
+        engine_id: &str,
+    ) -> Result<TextToImageRequestBody, StabilityAIError> {
+        let request = self.build()?;
+        request.validate_dimensions(engine_id)?;
+        Ok(request)
+    }
+}
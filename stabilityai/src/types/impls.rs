@@ -1,14 +1,26 @@
 use std::{
-    fmt::Display,
+    fmt::{Debug, Display},
     path::{Path, PathBuf},
+    str::FromStr,
+    sync::OnceLock,
 };
 
-use crate::{download::save_b64, error::StabilityAIError, util::create_file_part};
+use base64::{engine::general_purpose, Engine as _};
+use bytes::Bytes;
+use reqwest::header::HeaderMap;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::{
+    client::{FINISH_REASON_HEADER, SEED_HEADER},
+    download::save_bytes,
+    error::StabilityAIError,
+    transport::MultipartForm,
+};
 
 use super::{
-    Artifacts, ClipGuidancePreset, Image, ImageToImageRequestBody, ImageToImageUpscaleBody,
-    InitImage, InputImage, LatentUpscalerUpscaleRequestBody, MaskImage, MaskSource,
-    MaskingRequestBody, RealESRGANUpscaleRequestBody, Sampler, StylePreset,
+    Artifacts, BinaryImage, ClipGuidancePreset, FinishReason, Image, ImageToImageRequestBody,
+    ImageToImageUpscaleBody, InitImage, InputImage, LatentUpscalerUpscaleRequestBody, MaskImage,
+    MaskSource, MaskingRequestBody, RealESRGANUpscaleRequestBody, Sampler, StylePreset,
 };
 
 use super::{TextPrompt, TextPrompts};
@@ -205,34 +217,224 @@ impl Display for MaskSource {
     }
 }
 
+impl Display for FinishReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::ContentFiltered => "CONTENT_FILTERED",
+                Self::Error => "ERROR",
+                Self::Success => "SUCCESS",
+            }
+        )
+    }
+}
+
+impl FromStr for FinishReason {
+    type Err = StabilityAIError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "CONTENT_FILTERED" => Ok(Self::ContentFiltered),
+            "ERROR" => Ok(Self::Error),
+            "SUCCESS" => Ok(Self::Success),
+            _ => Err(StabilityAIError::InvalidResponseHeader(format!(
+                "unknown finish reason: {s}"
+            ))),
+        }
+    }
+}
+
+/// Fail with a descriptive error when an image cannot be saved because of its finish reason
+fn check_finish_reason(finish_reason: &FinishReason) -> Result<(), StabilityAIError> {
+    match finish_reason {
+        FinishReason::ContentFiltered => Err(StabilityAIError::FileSaveError(
+            "FinishReason::CONTENT_FILTERED: Your request activated the API's safety
+                filters and could not be processed. Please modify the prompt and try again."
+                .into(),
+        )),
+        FinishReason::Error => Err(StabilityAIError::FileSaveError(
+            "FinishReason::ERROR".into(),
+        )),
+        FinishReason::Success => Ok(()),
+    }
+}
+
+/// Bytes of [Image] decoded on first access, ignored when comparing images
+#[derive(Clone, Default)]
+pub(crate) struct DecodedBytes(OnceLock<Bytes>);
+
+impl Debug for DecodedBytes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0.get() {
+            Some(bytes) => write!(f, "DecodedBytes({} bytes)", bytes.len()),
+            None => f.write_str("DecodedBytes(not decoded)"),
+        }
+    }
+}
+
+impl PartialEq for DecodedBytes {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
 impl Image {
+    pub fn from_base64<S: Into<String>>(base64: S, finish_reason: FinishReason, seed: i64) -> Self {
+        Self {
+            base64: base64.into(),
+            finish_reason,
+            seed,
+            decoded: Default::default(),
+        }
+    }
+
+    /// Image of the given PNG bytes, encoded as base64 for serialization
+    pub fn from_bytes<B: Into<Bytes>>(bytes: B, finish_reason: FinishReason, seed: i64) -> Self {
+        let bytes = bytes.into();
+        let image = Self::from_base64(
+            general_purpose::STANDARD.encode(&bytes),
+            finish_reason,
+            seed,
+        );
+        image.decoded.0.get_or_init(|| bytes);
+        image
+    }
+
+    /// Decoded bytes of the image, decoded on first call and cached.
+    ///
+    /// The cache is not invalidated when the `base64` field is modified afterwards.
+    pub fn bytes(&self) -> Result<Bytes, StabilityAIError> {
+        if let Some(bytes) = self.decoded.0.get() {
+            return Ok(bytes.clone());
+        }
+
+        let bytes = Bytes::from(
+            general_purpose::STANDARD
+                .decode(&self.base64)
+                .map_err(|e| StabilityAIError::InvalidBase64(e.to_string()))?,
+        );
+        Ok(self.decoded.0.get_or_init(|| bytes).clone())
+    }
+
+    /// Write the decoded bytes of the image
+    pub async fn write_to<W: AsyncWrite + Unpin>(
+        &self,
+        mut writer: W,
+    ) -> Result<(), StabilityAIError> {
+        let bytes = self.bytes()?;
+        let write = async {
+            writer.write_all(&bytes).await?;
+            writer.flush().await
+        };
+        write
+            .await
+            .map_err(|e| StabilityAIError::FileSaveError(e.to_string()))
+    }
+
     pub async fn save<P: AsRef<Path>>(&self, dir: P) -> Result<PathBuf, StabilityAIError> {
-        match self.finish_reason {
-            super::FinishReason::ContentFiltered => Err(StabilityAIError::FileSaveError(
-                "FinishReason::CONTENT_FILTERED: Your request activated the API's safety
-                filters and could not be processed. Please modify the prompt and try again."
-                    .into(),
-            )),
-            super::FinishReason::Error => Err(StabilityAIError::FileSaveError(
-                "FinishReason::ERROR".into(),
-            )),
-            super::FinishReason::Success => save_b64(&self.base64, dir).await,
+        check_finish_reason(&self.finish_reason)?;
+        save_bytes(&self.bytes()?, dir).await
+    }
+
+    /// Save the image with blocking file system calls, without a Tokio runtime.
+    #[cfg(feature = "blocking")]
+    pub fn save_blocking<P: AsRef<Path>>(&self, dir: P) -> Result<PathBuf, StabilityAIError> {
+        check_finish_reason(&self.finish_reason)?;
+        crate::download::save_bytes_blocking(&self.bytes()?, dir)
+    }
+}
+
+impl BinaryImage {
+    /// Create image from the headers and body of an `image/png` response
+    pub(crate) fn from_response(
+        headers: &HeaderMap,
+        bytes: Bytes,
+    ) -> Result<Self, StabilityAIError> {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .ok_or_else(|| {
+                    StabilityAIError::InvalidResponseHeader(format!("missing {name} header"))
+                })?
+                .to_str()
+                .map_err(|e| StabilityAIError::InvalidResponseHeader(format!("{name}: {e}")))
+        };
+
+        let seed = header(SEED_HEADER)?
+            .parse::<i64>()
+            .map_err(|e| StabilityAIError::InvalidResponseHeader(format!("{SEED_HEADER}: {e}")))?;
+        let finish_reason = header(FINISH_REASON_HEADER)?.parse()?;
+
+        Ok(Self {
+            bytes,
+            finish_reason,
+            seed,
+        })
+    }
+
+    /// Save the image as a PNG file with a random name in the given directory.
+    pub async fn save<P: AsRef<Path>>(&self, dir: P) -> Result<PathBuf, StabilityAIError> {
+        check_finish_reason(&self.finish_reason)?;
+        save_bytes(&self.bytes, dir).await
+    }
+
+    /// Save the image with blocking file system calls, without a Tokio runtime.
+    #[cfg(feature = "blocking")]
+    pub fn save_blocking<P: AsRef<Path>>(&self, dir: P) -> Result<PathBuf, StabilityAIError> {
+        check_finish_reason(&self.finish_reason)?;
+        crate::download::save_bytes_blocking(&self.bytes, dir)
+    }
+}
+
+/// Create {dir} and its parents if it doesn't exist
+fn create_dir<P: AsRef<Path>>(dir: P) -> Result<(), StabilityAIError> {
+    let exists = match Path::try_exists(dir.as_ref()) {
+        Ok(exists) => exists,
+        Err(e) => return Err(StabilityAIError::FileSaveError(e.to_string())),
+    };
+
+    if !exists {
+        std::fs::create_dir_all(dir.as_ref())
+            .map_err(|e| StabilityAIError::FileSaveError(e.to_string()))?;
+    }
+
+    Ok(())
+}
+
+/// Combine errors of saving individual images into a single error
+fn collect_saved<I>(results: I) -> Result<Vec<PathBuf>, StabilityAIError>
+where
+    I: IntoIterator<Item = Result<PathBuf, StabilityAIError>>,
+{
+    let mut errors = vec![];
+    let mut paths = vec![];
+
+    for result in results {
+        match result {
+            Ok(path) => paths.push(path),
+            Err(e) => errors.push(e),
         }
     }
+
+    if errors.is_empty() {
+        Ok(paths)
+    } else {
+        Err(StabilityAIError::FileSaveError(
+            errors
+                .into_iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<String>>()
+                .join("; "),
+        ))
+    }
 }
 
 impl Artifacts {
     /// Save each image in a dedicated Tokio task and return paths to saved files.
     pub async fn save<P: AsRef<Path>>(&self, dir: P) -> Result<Vec<PathBuf>, StabilityAIError> {
-        let exists = match Path::try_exists(dir.as_ref()) {
-            Ok(exists) => exists,
-            Err(e) => return Err(StabilityAIError::FileSaveError(e.to_string())),
-        };
-
-        if !exists {
-            std::fs::create_dir_all(dir.as_ref())
-                .map_err(|e| StabilityAIError::FileSaveError(e.to_string()))?;
-        }
+        create_dir(dir.as_ref())?;
 
         let mut handles = vec![];
         for image in &self.artifacts {
@@ -242,30 +444,24 @@ impl Artifacts {
         }
 
         let results = futures::future::join_all(handles).await;
-        let mut errors = vec![];
-        let mut paths = vec![];
-
-        for result in results {
-            match result {
-                Ok(inner) => match inner {
-                    Ok(path) => paths.push(path),
-                    Err(e) => errors.push(e),
-                },
-                Err(e) => errors.push(StabilityAIError::FileSaveError(e.to_string())),
-            }
-        }
 
-        if errors.is_empty() {
-            Ok(paths)
-        } else {
-            Err(StabilityAIError::FileSaveError(
-                errors
-                    .into_iter()
-                    .map(|e| e.to_string())
-                    .collect::<Vec<String>>()
-                    .join("; "),
-            ))
-        }
+        collect_saved(results.into_iter().map(|result| match result {
+            Ok(inner) => inner,
+            Err(e) => Err(StabilityAIError::FileSaveError(e.to_string())),
+        }))
+    }
+
+    /// Save each image one after the other with blocking file system calls
+    /// and return paths to saved files.
+    #[cfg(feature = "blocking")]
+    pub fn save_blocking<P: AsRef<Path>>(&self, dir: P) -> Result<Vec<PathBuf>, StabilityAIError> {
+        create_dir(dir.as_ref())?;
+
+        collect_saved(
+            self.artifacts
+                .iter()
+                .map(|image| image.save_blocking(dir.as_ref())),
+        )
     }
 }
 
@@ -287,12 +483,9 @@ impl From<RealESRGANUpscaleRequestBody> for ImageToImageUpscaleBody {
     }
 }
 
-// start: types to multipart from
+// start: types to multipart form
 
-fn from_for_text_prompts(
-    mut form: reqwest::multipart::Form,
-    text_prompts: TextPrompts,
-) -> reqwest::multipart::Form {
+fn from_for_text_prompts(mut form: MultipartForm, text_prompts: TextPrompts) -> MultipartForm {
     for (idx, text_prompt) in text_prompts.text_prompts.into_iter().enumerate() {
         if text_prompt.text.is_empty() {
             continue;
@@ -305,14 +498,9 @@ fn from_for_text_prompts(
     form
 }
 
-#[async_convert::async_trait]
-impl async_convert::TryFrom<ImageToImageRequestBody> for reqwest::multipart::Form {
-    type Error = StabilityAIError;
-
-    async fn try_from(request: ImageToImageRequestBody) -> Result<Self, Self::Error> {
-        let init_image_part = create_file_part(&request.init_image.path).await?;
-
-        let mut form = reqwest::multipart::Form::new().part("init_image", init_image_part);
+impl From<ImageToImageRequestBody> for MultipartForm {
+    fn from(request: ImageToImageRequestBody) -> Self {
+        let mut form = MultipartForm::new().file("init_image", &request.init_image.path);
 
         form = from_for_text_prompts(form, request.text_prompts);
 
@@ -360,18 +548,13 @@ impl async_convert::TryFrom<ImageToImageRequestBody> for reqwest::multipart::For
             form = form.text("extras", extras.to_string());
         }
 
-        Ok(form)
+        form
     }
 }
 
-#[async_convert::async_trait]
-impl async_convert::TryFrom<LatentUpscalerUpscaleRequestBody> for reqwest::multipart::Form {
-    type Error = StabilityAIError;
-
-    async fn try_from(request: LatentUpscalerUpscaleRequestBody) -> Result<Self, Self::Error> {
-        let image = create_file_part(&request.image.path).await?;
-
-        let mut form = reqwest::multipart::Form::new().part("image", image);
+impl From<LatentUpscalerUpscaleRequestBody> for MultipartForm {
+    fn from(request: LatentUpscalerUpscaleRequestBody) -> Self {
+        let mut form = MultipartForm::new().file("image", &request.image.path);
 
         if let Some(width) = request.width {
             form = form.text("width", width.to_string());
@@ -397,18 +580,13 @@ impl async_convert::TryFrom<LatentUpscalerUpscaleRequestBody> for reqwest::multi
             form = form.text("cfg_scale", cfg_scale.to_string());
         }
 
-        Ok(form)
+        form
     }
 }
 
-#[async_convert::async_trait]
-impl async_convert::TryFrom<RealESRGANUpscaleRequestBody> for reqwest::multipart::Form {
-    type Error = StabilityAIError;
-
-    async fn try_from(request: RealESRGANUpscaleRequestBody) -> Result<Self, Self::Error> {
-        let image = create_file_part(&request.image.path).await?;
-
-        let mut form = reqwest::multipart::Form::new().part("image", image);
+impl From<RealESRGANUpscaleRequestBody> for MultipartForm {
+    fn from(request: RealESRGANUpscaleRequestBody) -> Self {
+        let mut form = MultipartForm::new().file("image", &request.image.path);
 
         if let Some(width) = request.width {
             form = form.text("width", width.to_string());
@@ -418,42 +596,29 @@ impl async_convert::TryFrom<RealESRGANUpscaleRequestBody> for reqwest::multipart
             form = form.text("height", height.to_string());
         }
 
-        Ok(form)
+        form
     }
 }
 
-#[async_convert::async_trait]
-impl async_convert::TryFrom<ImageToImageUpscaleBody> for reqwest::multipart::Form {
-    type Error = StabilityAIError;
-
-    async fn try_from(request: ImageToImageUpscaleBody) -> Result<Self, Self::Error> {
+impl From<ImageToImageUpscaleBody> for MultipartForm {
+    fn from(request: ImageToImageUpscaleBody) -> Self {
         match request {
-            ImageToImageUpscaleBody::LatentUpscalerUpscaleRequestBody(body) => {
-                async_convert::TryFrom::try_from(body).await
-            }
-            ImageToImageUpscaleBody::RealESRGANUpscaleRequestBody(body) => {
-                async_convert::TryFrom::try_from(body).await
-            }
+            ImageToImageUpscaleBody::LatentUpscalerUpscaleRequestBody(body) => body.into(),
+            ImageToImageUpscaleBody::RealESRGANUpscaleRequestBody(body) => body.into(),
         }
     }
 }
 
-#[async_convert::async_trait]
-impl async_convert::TryFrom<MaskingRequestBody> for reqwest::multipart::Form {
-    type Error = StabilityAIError;
-
-    async fn try_from(request: MaskingRequestBody) -> Result<Self, Self::Error> {
-        let init_image = create_file_part(&request.init_image.path).await?;
-
-        let mut form = reqwest::multipart::Form::new().part("init_image", init_image);
+impl From<MaskingRequestBody> for MultipartForm {
+    fn from(request: MaskingRequestBody) -> Self {
+        let mut form = MultipartForm::new().file("init_image", &request.init_image.path);
 
         form = from_for_text_prompts(form, request.text_prompts);
 
         form = form.text("mask_source", request.mask_source.to_string());
 
         if let Some(mask_image) = request.mask_image {
-            let mask_image_part = create_file_part(mask_image.path).await?;
-            form = form.part("mask_image", mask_image_part);
+            form = form.file("mask_image", mask_image.path);
         }
 
         if let Some(cfg_scale) = request.cfg_scale {
@@ -488,8 +653,29 @@ impl async_convert::TryFrom<MaskingRequestBody> for reqwest::multipart::Form {
             form = form.text("extras", extras.to_string());
         }
 
-        Ok(form)
+        form
     }
 }
 
+/// Build a [reqwest::multipart::Form] reading the files of the request,
+/// for sending requests with a [reqwest::Client] of your own
+macro_rules! impl_try_from_for_reqwest_form {
+    ($from_typ:ty) => {
+        #[async_convert::async_trait]
+        impl async_convert::TryFrom<$from_typ> for reqwest::multipart::Form {
+            type Error = StabilityAIError;
+
+            async fn try_from(request: $from_typ) -> Result<Self, Self::Error> {
+                MultipartForm::from(request).into_reqwest_form(None).await
+            }
+        }
+    };
+}
+
+impl_try_from_for_reqwest_form!(ImageToImageRequestBody);
+impl_try_from_for_reqwest_form!(LatentUpscalerUpscaleRequestBody);
+impl_try_from_for_reqwest_form!(RealESRGANUpscaleRequestBody);
+impl_try_from_for_reqwest_form!(ImageToImageUpscaleBody);
+impl_try_from_for_reqwest_form!(MaskingRequestBody);
+
 // end: types to multipart form
@@ -1,8 +1,10 @@
 //! Types used in API requests and responses.
 //! These types are created from component schemas in the [OpenAPI spec](https://platform.stability.ai/docs/api-reference)
+mod dimensions;
 mod impls;
 mod spec_types;
 use derive_builder::UninitializedFieldError;
+pub use dimensions::{validate_dimensions, SDXL_DIMENSIONS};
 pub use spec_types::*;
 
 use crate::error::StabilityAIError;
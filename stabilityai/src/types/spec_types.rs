@@ -1,11 +1,14 @@
 use std::{path::PathBuf, sync::Arc};
 
+use bytes::Bytes;
 use derive_builder::Builder;
 
 use serde::{Deserialize, Serialize};
 
 use crate::error::StabilityAIError;
 
+use super::impls::DecodedBytes;
+
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 pub struct OrganizationMembership {
     pub id: String,
@@ -197,11 +200,11 @@ pub struct TextToImageRequestBody {
     /// - For 768 engines: 589,824 ≤ `height * width` ≤ 1,048,576
     ///
     /// - For SDXL Beta: can be as low as 128 and as high as 896 as long as `width`
-    /// is not greater than 512. If `width` is greater than 512 then this can
-    /// be _at most_ 512.
+    ///   is not greater than 512. If `width` is greater than 512 then this can
+    ///   be _at most_ 512.
     ///
     /// - For SDXL v0.9: valid dimensions are 1024x1024, 1152x896, 1216x832,
-    /// 1344x768, 1536x640, 640x1536, 768x1344, 832x1216, or 896x1152
+    ///   1344x768, 1536x640, 640x1536, 768x1344, 832x1216, or 896x1152
     ///
     /// - For SDXL v1.0: valid dimensions are the same as SDXL v0.9
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -215,10 +218,10 @@ pub struct TextToImageRequestBody {
     /// - For 768 engines: 589,824 ≤ `height * width` ≤ 1,048,576
     ///
     /// - For SDXL Beta: can be as low as 128 and as high as 896 as long as `height`
-    /// is not greater than 512. If `height` is greater than 512 then this can be _at most_ 512.
+    ///   is not greater than 512. If `height` is greater than 512 then this can be _at most_ 512.
     ///
     /// - For SDXL v0.9: valid dimensions are 1024x1024, 1152x896, 1216x832, 1344x768, 1536x640,
-    /// 640x1536, 768x1344, 832x1216, or 896x1152
+    ///   640x1536, 768x1344, 832x1216, or 896x1152
     ///
     /// - For SDXL v1.0: valid dimensions are the same as SDXL v0.9
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -279,12 +282,28 @@ pub struct Artifacts {
     pub artifacts: Vec<Arc<Image>>,
 }
 
+/// Image of a JSON generation response, built with [Image::from_base64] or [Image::from_bytes]
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 pub struct Image {
+    /// The generated image encoded as base64, see [Image::bytes] for the decoded bytes
     pub base64: String,
     #[serde(rename = "finishReason")]
     pub finish_reason: FinishReason,
     pub seed: i64,
+    #[serde(skip)]
+    pub(crate) decoded: DecodedBytes,
+}
+
+/// Image returned as raw PNG bytes when the `Accept` header is set to `image/png`.
+///
+/// Seed and finish reason are parsed from the `Seed` and `Finish-Reason` response headers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BinaryImage {
+    /// The bytes of the generated PNG image
+    pub bytes: Bytes,
+    pub finish_reason: FinishReason,
+    /// The seed used to generate the image
+    pub seed: i64,
 }
 
 #[derive(Debug, Deserialize, Default, Serialize, Clone, PartialEq)]
@@ -463,16 +482,16 @@ pub struct MaskingRequestBody {
     /// mask from:
     ///
     /// - `MASK_IMAGE_WHITE` will use the white pixels of the
-    /// mask_image as the mask, where white pixels are completely replaced
-    /// and black pixels are unchanged
+    ///   mask_image as the mask, where white pixels are completely replaced
+    ///   and black pixels are unchanged
     ///
     /// - `MASK_IMAGE_BLACK` will use the
-    /// black pixels of the mask_image as the mask, where black pixels are
-    /// completely replaced and white pixels are unchanged
+    ///   black pixels of the mask_image as the mask, where black pixels are
+    ///   completely replaced and white pixels are unchanged
     ///
     /// - `INIT_IMAGE_ALPHA` will use the alpha channel of the init_image
-    /// as the mask, where fully transparent pixels are completely replaced
-    /// and fully opaque pixels are unchanged
+    ///   as the mask, where fully transparent pixels are completely replaced
+    ///   and fully opaque pixels are unchanged
     pub mask_source: MaskSource,
 
     /// Optional grayscale mask that allows for influence over which pixels
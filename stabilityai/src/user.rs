@@ -1,26 +1,50 @@
 use crate::{
     error::StabilityAIError,
     types::{AccountResponseBody, BalanceResponseBody},
-    Client,
+    Client, RequestOptions, WithMeta,
 };
 
 /// Manage your Stability.ai account, and view account/organization balances
 pub struct User<'c> {
     client: &'c Client,
+    options: RequestOptions,
 }
 
 impl<'c> User<'c> {
     pub fn new(client: &'c Client) -> Self {
-        Self { client }
+        Self {
+            client,
+            options: Default::default(),
+        }
+    }
+
+    /// Timeout, cancellation and headers of the calls made with this instance
+    pub fn with_options(mut self, options: RequestOptions) -> Self {
+        self.options = options;
+        self
     }
 
     /// Get information about the account associated with the provided API key
     pub async fn account(&self) -> Result<AccountResponseBody, StabilityAIError> {
-        self.client.get("/user/account").await
+        Ok(self.account_with_meta().await?.body)
+    }
+
+    /// Same as [User::account] with status, headers and latency of the response
+    pub async fn account_with_meta(
+        &self,
+    ) -> Result<WithMeta<AccountResponseBody>, StabilityAIError> {
+        self.client.get("/user/account", &self.options).await
     }
 
     /// The balance of the account/organization associated with the API key
     pub async fn balance(&self) -> Result<BalanceResponseBody, StabilityAIError> {
-        self.client.get("/user/balance").await
+        Ok(self.balance_with_meta().await?.body)
+    }
+
+    /// Same as [User::balance] with status, headers and latency of the response
+    pub async fn balance_with_meta(
+        &self,
+    ) -> Result<WithMeta<BalanceResponseBody>, StabilityAIError> {
+        self.client.get("/user/balance", &self.options).await
     }
 }
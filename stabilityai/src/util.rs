@@ -1,15 +1,24 @@
 use std::path::Path;
 
+use futures::StreamExt;
 use reqwest::Body;
 use tokio_util::codec::{BytesCodec, FramedRead};
 
-use crate::error::StabilityAIError;
+use crate::{error::StabilityAIError, transport::ProgressTracker};
 
-pub(crate) async fn file_stream_body<P: AsRef<Path>>(path: P) -> Result<Body, StabilityAIError> {
+/// Stream the file, reporting the bytes read to the optional tracker
+pub(crate) async fn file_stream_body<P: AsRef<Path>>(
+    path: P,
+    tracker: Option<ProgressTracker>,
+) -> Result<Body, StabilityAIError> {
     let file = tokio::fs::File::open(path.as_ref())
         .await
         .map_err(|e| StabilityAIError::FileReadError(e.to_string()))?;
-    let stream = FramedRead::new(file, BytesCodec::new());
+    let stream = FramedRead::new(file, BytesCodec::new()).inspect(move |chunk| {
+        if let (Some(tracker), Ok(chunk)) = (&tracker, chunk) {
+            tracker.advance(chunk.len());
+        }
+    });
     let body = Body::wrap_stream(stream);
     Ok(body)
 }
@@ -17,6 +26,7 @@ pub(crate) async fn file_stream_body<P: AsRef<Path>>(path: P) -> Result<Body, St
 /// Creates the part for the given image file for multipart upload.
 pub(crate) async fn create_file_part<P: AsRef<Path>>(
     path: P,
+    tracker: Option<ProgressTracker>,
 ) -> Result<reqwest::multipart::Part, StabilityAIError> {
     let file_name = path
         .as_ref()
@@ -31,10 +41,11 @@ pub(crate) async fn create_file_part<P: AsRef<Path>>(
         .unwrap()
         .to_string();
 
-    let file_part = reqwest::multipart::Part::stream(file_stream_body(path.as_ref()).await?)
-        .file_name(file_name)
-        .mime_str("application/octet-stream")
-        .unwrap();
+    let file_part =
+        reqwest::multipart::Part::stream(file_stream_body(path.as_ref(), tracker).await?)
+            .file_name(file_name)
+            .mime_str("application/octet-stream")
+            .unwrap();
 
     Ok(file_part)
 }
@@ -0,0 +1,104 @@
+//! Generations can be returned as raw PNG bytes with metadata in the response headers.
+
+use std::time::Duration;
+
+use reqwest::{header::ACCEPT, StatusCode};
+use stabilityai::{
+    error::StabilityAIError,
+    transport::{HttpResponse, InMemoryTransport, RequestBody, ResponseBody},
+    types::{FinishReason, RealESRGANUpscaleRequestBodyArgs, TextToImageRequestBodyArgs},
+    Client, FINISH_REASON_HEADER, SEED_HEADER,
+};
+
+const PNG: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+fn png(seed: &str, finish_reason: &str) -> HttpResponse {
+    HttpResponse::new(StatusCode::OK, ResponseBody::from(PNG))
+        .with_header("content-type", "image/png")
+        .with_header(SEED_HEADER, seed)
+        .with_header(FINISH_REASON_HEADER, finish_reason)
+}
+
+fn client(transport: &InMemoryTransport) -> Client {
+    let backoff = backoff::ExponentialBackoffBuilder::new()
+        .with_max_elapsed_time(Some(Duration::ZERO))
+        .build();
+
+    Client::new()
+        .with_api_key("sk-test")
+        .with_backoff(backoff)
+        .with_transport(transport.clone())
+}
+
+#[tokio::test]
+async fn parse_image_from_headers() {
+    let transport = InMemoryTransport::new()
+        .with_response(png("1234", "SUCCESS"))
+        .with_response(png("5678", "CONTENT_FILTERED"));
+    let client = client(&transport);
+    let generate = client.generate("stable-diffusion-v1-6");
+
+    let request = TextToImageRequestBodyArgs::default()
+        .text_prompts("A lighthouse")
+        .build()
+        .unwrap();
+    let image = generate.text_to_image_binary(request).await.unwrap();
+    assert_eq!(image.bytes.as_ref(), PNG);
+    assert_eq!(image.seed, 1234);
+    assert_eq!(image.finish_reason, FinishReason::Success);
+
+    let upscale = RealESRGANUpscaleRequestBodyArgs::default()
+        .image("small.png")
+        .width(2048_u16)
+        .build()
+        .unwrap();
+    let image = generate
+        .image_to_image_upscale_binary(upscale)
+        .await
+        .unwrap();
+    assert_eq!(image.seed, 5678);
+    assert_eq!(image.finish_reason, FinishReason::ContentFiltered);
+    // Filtered images are not saved
+    assert!(matches!(
+        image.save(std::env::temp_dir()).await,
+        Err(StabilityAIError::FileSaveError(_))
+    ));
+
+    let requests = transport.requests();
+    assert!(requests[0].url.ends_with("/text-to-image"));
+    assert!(matches!(requests[0].body, RequestBody::Json(_)));
+    assert!(requests[1].url.ends_with("/image-to-image/upscale"));
+    assert!(matches!(requests[1].body, RequestBody::Multipart(_)));
+    for request in requests {
+        assert_eq!(request.headers[ACCEPT], "image/png");
+    }
+}
+
+#[tokio::test]
+async fn reject_invalid_headers() {
+    let missing_seed = HttpResponse::new(StatusCode::OK, ResponseBody::from(PNG))
+        .with_header(FINISH_REASON_HEADER, "SUCCESS");
+    let transport = InMemoryTransport::new()
+        .with_response(missing_seed)
+        .with_response(png("not a seed", "SUCCESS"))
+        .with_response(png("1", "DONE"));
+    let client = client(&transport);
+    let generate = client.generate("stable-diffusion-v1-6");
+    let request = TextToImageRequestBodyArgs::default()
+        .text_prompts("A lighthouse")
+        .build()
+        .unwrap();
+
+    for expected in [
+        "missing Seed header",
+        "Seed: ",
+        "unknown finish reason: DONE",
+    ] {
+        match generate.text_to_image_binary(request.clone()).await {
+            Err(StabilityAIError::InvalidResponseHeader(message)) => {
+                assert!(message.contains(expected), "{message}")
+            }
+            result => panic!("unexpected result: {result:?}"),
+        }
+    }
+}
@@ -0,0 +1,49 @@
+#![cfg(feature = "blocking")]
+//! Blocking client makes requests and saves images without an async runtime.
+
+use base64::{engine::general_purpose, Engine as _};
+use reqwest::StatusCode;
+use serde_json::json;
+use stabilityai::{
+    blocking::Client,
+    transport::{HttpResponse, InMemoryTransport},
+    types::TextToImageRequestBodyArgs,
+};
+
+#[test]
+fn text_to_image_and_save() {
+    let png = b"\x89PNG\r\n\x1a\n";
+    let artifacts = json!({"artifacts": [
+        {"base64": general_purpose::STANDARD.encode(png), "finishReason": "SUCCESS", "seed": 1},
+        {"base64": general_purpose::STANDARD.encode(png), "finishReason": "SUCCESS", "seed": 2},
+    ]});
+    let transport = InMemoryTransport::new()
+        .with_response(HttpResponse::json(StatusCode::OK, &json!({"credits": 9.0})).unwrap())
+        .with_response(HttpResponse::json(StatusCode::OK, &artifacts).unwrap());
+
+    let client = Client::new().with_transport(transport.clone());
+
+    assert_eq!(client.user().balance().unwrap().credits, 9.0);
+
+    let request = TextToImageRequestBodyArgs::default()
+        .text_prompts("A lighthouse")
+        .build()
+        .unwrap();
+    let artifacts = client
+        .generate("stable-diffusion-v1-6")
+        .text_to_image(request)
+        .unwrap();
+
+    let dir = std::env::temp_dir().join(format!("stabilityai-blocking-{}", std::process::id()));
+    let paths = artifacts.save_blocking(&dir).unwrap();
+    let written: Vec<_> = paths
+        .iter()
+        .map(|path| std::fs::read(path).unwrap())
+        .collect();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(written, vec![png.to_vec(); 2]);
+
+    assert!(transport.requests()[1]
+        .url
+        .ends_with("/generation/stable-diffusion-v1-6/text-to-image"));
+}
@@ -0,0 +1,201 @@
+//! Generation requests are refused once they would cross the budget of the client.
+
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use serde_json::json;
+use stabilityai::{
+    budget::{BudgetGuard, BudgetLimit},
+    error::StabilityAIError,
+    transport::{HttpRequest, HttpResponse, HttpTransport, InMemoryTransport},
+    types::{TextToImageRequestBody, TextToImageRequestBodyArgs},
+    Client, RequestOptions,
+};
+
+/// Transport which never answers
+#[derive(Debug)]
+struct HangingTransport;
+
+#[async_trait::async_trait]
+impl HttpTransport for HangingTransport {
+    async fn send(&self, _request: HttpRequest) -> Result<HttpResponse, StabilityAIError> {
+        futures::future::pending().await
+    }
+}
+
+/// Transport answering balance requests, and never answering generation requests
+#[derive(Debug)]
+struct BalanceTransport(f64);
+
+#[async_trait::async_trait]
+impl HttpTransport for BalanceTransport {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, StabilityAIError> {
+        if request.url.ends_with("/user/balance") {
+            return Ok(balance(self.0));
+        }
+        futures::future::pending().await
+    }
+}
+
+fn artifacts() -> HttpResponse {
+    let body =
+        json!({"artifacts": [{"base64": "iVBORw0KGgo=", "finishReason": "SUCCESS", "seed": 1}]});
+    HttpResponse::json(StatusCode::OK, &body).unwrap()
+}
+
+fn balance(credits: f64) -> HttpResponse {
+    HttpResponse::json(StatusCode::OK, &json!({ "credits": credits })).unwrap()
+}
+
+fn request(samples: u8) -> TextToImageRequestBody {
+    TextToImageRequestBodyArgs::default()
+        .text_prompts("A lighthouse")
+        .samples(samples)
+        .build()
+        .unwrap()
+}
+
+fn client(transport: &InMemoryTransport, guard: BudgetGuard) -> Client {
+    let backoff = backoff::ExponentialBackoffBuilder::new()
+        .with_max_elapsed_time(Some(Duration::ZERO))
+        .build();
+
+    Client::new()
+        .with_api_key("sk-test")
+        .with_backoff(backoff)
+        .with_transport(transport.clone())
+        .with_budget(guard)
+}
+
+#[tokio::test]
+async fn refuse_requests_over_budget() {
+    let error = json!({"id": "1", "name": "bad_request", "message": "invalid steps"});
+    let transport = InMemoryTransport::new()
+        .with_response(artifacts())
+        .with_response(HttpResponse::json(StatusCode::BAD_REQUEST, &error).unwrap())
+        .with_response(artifacts());
+    let guard = BudgetGuard::new()
+        .with_budget(1.0)
+        .with_cost_per_image(0.25);
+    let client = client(&transport, guard.clone());
+    let generate = client.generate("stable-diffusion-v1-6");
+
+    generate.text_to_image(request(3)).await.unwrap();
+    assert_eq!(guard.spent(), 0.75);
+
+    // Requests rejected by the API are not counted
+    generate.text_to_image(request(1)).await.unwrap_err();
+    assert_eq!(guard.spent(), 0.75);
+
+    let error = generate.text_to_image(request(2)).await.unwrap_err();
+    match error {
+        StabilityAIError::BudgetExceeded(exceeded) => {
+            assert_eq!(exceeded.limit, BudgetLimit::Budget);
+            assert_eq!(exceeded.estimated, 0.5);
+            assert_eq!(exceeded.available, 0.25);
+        }
+        error => panic!("unexpected error: {error}"),
+    }
+    assert_eq!(transport.requests().len(), 2);
+
+    generate.text_to_image(request(1)).await.unwrap();
+    assert_eq!(guard.remaining(), Some(0.0));
+}
+
+#[tokio::test]
+async fn check_balance_before_expensive_requests() {
+    let transport = InMemoryTransport::new()
+        .with_response(artifacts())
+        .with_response(balance(10.5))
+        .with_response(artifacts())
+        .with_response(balance(10.3));
+    let guard = BudgetGuard::new()
+        .with_min_balance(10.0)
+        .with_balance_check_threshold(0.4)
+        .with_cost_per_image(0.2);
+    let client = client(&transport, guard.clone());
+    let generate = client.generate("stable-diffusion-v1-6");
+
+    // Cheap requests are sent without checking the balance
+    generate.text_to_image(request(1)).await.unwrap();
+    generate.text_to_image(request(2)).await.unwrap();
+
+    let error = generate.text_to_image(request(2)).await.unwrap_err();
+    assert!(matches!(
+        error,
+        StabilityAIError::BudgetExceeded(exceeded) if exceeded.limit == BudgetLimit::MinBalance
+    ));
+
+    let paths: Vec<_> = transport
+        .requests()
+        .iter()
+        .map(|request| request.url.rsplit('/').next().unwrap().to_string())
+        .collect();
+    assert_eq!(
+        paths,
+        ["text-to-image", "balance", "text-to-image", "balance"]
+    );
+    assert!((guard.spent() - 0.6).abs() < 1e-9);
+}
+
+#[tokio::test]
+async fn count_requests_in_flight_against_balance() {
+    let guard = BudgetGuard::new()
+        .with_min_balance(10.0)
+        .with_cost_per_image(0.2);
+    let client =
+        client(&InMemoryTransport::new(), guard.clone()).with_transport(BalanceTransport(10.5));
+
+    // The balance does not reflect the first request until it completes
+    let first = tokio::spawn({
+        let client = client.clone();
+        async move {
+            client
+                .generate("stable-diffusion-v1-6")
+                .text_to_image(request(2))
+                .await
+        }
+    });
+    while guard.spent() == 0.0 {
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+
+    let error = client
+        .generate("stable-diffusion-v1-6")
+        .text_to_image(request(2))
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        StabilityAIError::BudgetExceeded(exceeded)
+            if exceeded.limit == BudgetLimit::MinBalance && (exceeded.available - 0.1).abs() < 1e-9
+    ));
+    first.abort();
+}
+
+#[tokio::test]
+async fn count_requests_which_may_have_been_processed() {
+    let error = json!({"id": "1", "name": "server_error", "message": "try again"});
+    let transport = InMemoryTransport::new()
+        .with_response(HttpResponse::json(StatusCode::INTERNAL_SERVER_ERROR, &error).unwrap());
+    let guard = BudgetGuard::new().with_cost_per_image(0.25);
+
+    let client = client(&transport, guard.clone());
+    client
+        .generate("stable-diffusion-v1-6")
+        .text_to_image(request(1))
+        .await
+        .unwrap_err();
+    assert_eq!(guard.spent(), 0.25);
+
+    // The request timed out after it was sent
+    let client = client.with_transport(HangingTransport);
+    let error = client
+        .generate("stable-diffusion-v1-6")
+        .with_options(RequestOptions::new().with_timeout(Duration::from_millis(10)))
+        .text_to_image(request(1))
+        .await
+        .unwrap_err();
+    assert!(matches!(error, StabilityAIError::Timeout));
+    assert_eq!(guard.spent(), 0.5);
+}
@@ -0,0 +1,154 @@
+//! Generation responses of requests with a fixed seed are answered from the cache.
+
+use std::path::PathBuf;
+
+use reqwest::StatusCode;
+use serde_json::json;
+use stabilityai::{
+    cache::ResponseCache,
+    transport::{HttpResponse, InMemoryTransport},
+    types::{ImageToImageRequestBodyArgs, TextToImageRequestBody, TextToImageRequestBodyArgs},
+    Client,
+};
+
+/// Empty cache directory for a test
+fn cache_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir()
+        .join("stabilityai-cache-tests")
+        .join(name);
+    std::fs::remove_dir_all(&dir).ok();
+    dir
+}
+
+fn artifacts(seed: u32) -> HttpResponse {
+    let body =
+        json!({"artifacts": [{"base64": "iVBORw0KGgo=", "finishReason": "SUCCESS", "seed": seed}]});
+    HttpResponse::json(StatusCode::OK, &body).unwrap()
+}
+
+fn text_to_image(prompt: &str, seed: Option<u32>) -> TextToImageRequestBody {
+    let mut args = TextToImageRequestBodyArgs::default();
+    args.text_prompts(prompt);
+    if let Some(seed) = seed {
+        args.seed(seed);
+    }
+    args.build().unwrap()
+}
+
+fn client(transport: &InMemoryTransport, cache: ResponseCache) -> Client {
+    Client::new()
+        .with_api_key("sk-test")
+        .with_transport(transport.clone())
+        .with_cache(cache)
+}
+
+#[tokio::test]
+async fn cache_requests_with_fixed_seed() {
+    let transport = InMemoryTransport::new()
+        .with_response(artifacts(7))
+        .with_response(artifacts(7))
+        .with_response(artifacts(8))
+        .with_response(artifacts(9));
+    let client = client(&transport, ResponseCache::new(cache_dir("fixed-seed")));
+    let generate = client.generate("stable-diffusion-v1-6");
+
+    let first = generate
+        .text_to_image_with_meta(text_to_image("A lighthouse", Some(7)))
+        .await
+        .unwrap();
+    let cached = generate
+        .text_to_image_with_meta(text_to_image("A lighthouse", Some(7)))
+        .await
+        .unwrap();
+    assert_eq!(first.attempts, 1);
+    assert_eq!(cached.attempts, 0);
+    assert_eq!(cached.body, first.body);
+    assert_eq!(transport.requests().len(), 1);
+
+    // Other prompts and other engines are other requests
+    generate
+        .text_to_image(text_to_image("A lake", Some(7)))
+        .await
+        .unwrap();
+    client
+        .generate("stable-diffusion-xl-1024-v1-0")
+        .text_to_image(text_to_image("A lighthouse", Some(7)))
+        .await
+        .unwrap();
+    assert_eq!(transport.requests().len(), 3);
+
+    // Without a seed the API picks a random one
+    generate
+        .text_to_image(text_to_image("A lighthouse", None))
+        .await
+        .unwrap();
+    assert_eq!(transport.requests().len(), 4);
+}
+
+#[tokio::test]
+async fn key_covers_uploaded_files() {
+    let dir = cache_dir("files");
+    let init_image = dir.with_extension("png");
+    std::fs::write(&init_image, b"first image").unwrap();
+
+    let transport = InMemoryTransport::new()
+        .with_response(artifacts(1))
+        .with_response(artifacts(2));
+    let client = client(&transport, ResponseCache::new(&dir));
+    let request = || {
+        ImageToImageRequestBodyArgs::default()
+            .text_prompts("A crab")
+            .init_image(&init_image)
+            .seed(3_u32)
+            .build()
+            .unwrap()
+    };
+
+    let generate = client.generate("stable-diffusion-v1-6");
+    generate.image_to_image(request()).await.unwrap();
+    generate.image_to_image(request()).await.unwrap();
+    assert_eq!(transport.requests().len(), 1);
+
+    std::fs::write(&init_image, b"second image").unwrap();
+    let artifacts = generate.image_to_image(request()).await.unwrap();
+    assert_eq!(artifacts.artifacts[0].seed, 2);
+    assert_eq!(transport.requests().len(), 2);
+}
+
+#[tokio::test]
+async fn evict_oldest_responses() {
+    let dir = cache_dir("evict");
+    let entry_size = serde_json::to_vec(
+        &json!({"artifacts": [{"base64": "iVBORw0KGgo=", "finishReason": "SUCCESS", "seed": 1}]}),
+    )
+    .unwrap()
+    .len() as u64;
+
+    let transport = InMemoryTransport::new()
+        .with_response(artifacts(1))
+        .with_response(artifacts(2))
+        .with_response(artifacts(1));
+    let client = client(
+        &transport,
+        ResponseCache::new(&dir).with_max_size(entry_size),
+    );
+    let generate = client.generate("stable-diffusion-v1-6");
+
+    generate
+        .text_to_image(text_to_image("A lighthouse", Some(1)))
+        .await
+        .unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    generate
+        .text_to_image(text_to_image("A lighthouse", Some(2)))
+        .await
+        .unwrap();
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+    // The first response was evicted, the second one is still cached
+    generate
+        .text_to_image(text_to_image("A lighthouse", Some(1)))
+        .await
+        .unwrap();
+    assert_eq!(transport.requests().len(), 3);
+}
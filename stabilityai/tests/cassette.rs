@@ -0,0 +1,116 @@
+//! Interactions recorded into a cassette are replayed for matching requests.
+
+use reqwest::StatusCode;
+use serde_json::json;
+use stabilityai::{
+    cassette::{Cassette, RecordedBody, RecordedPart, RecordingTransport, ReplayTransport},
+    error::StabilityAIError,
+    transport::{HttpResponse, InMemoryTransport},
+    types::{MaskSource, MaskingRequestBodyArgs, TextToImageRequestBodyArgs},
+    Client,
+};
+
+fn artifacts(seed: u32) -> HttpResponse {
+    let artifacts =
+        json!({"artifacts": [{"base64": "aGVsbG8=", "finishReason": "SUCCESS", "seed": seed}]});
+    HttpResponse::json(StatusCode::OK, &artifacts).unwrap()
+}
+
+async fn record() -> Cassette {
+    let transport = InMemoryTransport::new()
+        .with_response(artifacts(1))
+        .with_response(artifacts(2))
+        .with_response(
+            HttpResponse::new(StatusCode::OK, &b"\x89PNG\r\n"[..])
+                .with_header("content-type", "image/png")
+                .with_header("seed", "3")
+                .with_header("finish-reason", "SUCCESS"),
+        );
+    let recorder = RecordingTransport::new(transport);
+    let client = Client::new()
+        .with_api_key("sk-secret")
+        .with_transport(recorder.clone());
+
+    let generate = client.generate("stable-diffusion-v1-6");
+    generate.text_to_image(text_to_image()).await.unwrap();
+    generate.image_to_image_masking(masking()).await.unwrap();
+    generate
+        .text_to_image_binary(text_to_image())
+        .await
+        .unwrap();
+
+    recorder.cassette()
+}
+
+fn text_to_image() -> stabilityai::types::TextToImageRequestBody {
+    TextToImageRequestBodyArgs::default()
+        .text_prompts("A lighthouse")
+        .build()
+        .unwrap()
+}
+
+fn masking() -> stabilityai::types::MaskingRequestBody {
+    MaskingRequestBodyArgs::default()
+        .text_prompts("A lake")
+        .init_image("/home/recorder/images/init.png")
+        .mask_image("/home/recorder/images/mask.png")
+        .mask_source(MaskSource::MaskImageWhite)
+        .build()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn recording_redacts_api_key() {
+    let cassette = record().await;
+    assert_eq!(cassette.interactions.len(), 3);
+
+    let request = &cassette.interactions[0].request;
+    assert_eq!(request.headers["authorization"], "[REDACTED]");
+    assert_eq!(
+        request.body,
+        RecordedBody::Json(json!({"text_prompts": [{"text": "A lighthouse"}]}))
+    );
+
+    let RecordedBody::Multipart(parts) = &cassette.interactions[1].request.body else {
+        panic!("expected multipart body");
+    };
+    assert!(parts.contains(&RecordedPart::File {
+        name: "init_image".into(),
+        file_name: "init.png".into(),
+    }));
+
+    let response = &cassette.interactions[2].response;
+    assert_eq!(response.body, None);
+    assert!(response.body_base64.is_some());
+}
+
+#[tokio::test]
+async fn replay_from_file() {
+    let path = std::env::temp_dir().join("stabilityai-cassette-test.json");
+    record().await.save(&path).unwrap();
+
+    let replay = ReplayTransport::from_file(&path).unwrap();
+    let client = Client::new().with_transport(replay.clone());
+    let generate = client.generate("stable-diffusion-v1-6");
+
+    // Files are matched by name, not by path
+    let mut request = masking();
+    request.init_image = "./images/init.png".into();
+    request.mask_image = Some("./images/mask.png".into());
+    let artifacts = generate.image_to_image_masking(request).await.unwrap();
+    assert_eq!(artifacts.artifacts[0].seed, 2);
+
+    let artifacts = generate.text_to_image(text_to_image()).await.unwrap();
+    assert_eq!(artifacts.artifacts[0].seed, 1);
+
+    let image = generate
+        .text_to_image_binary(text_to_image())
+        .await
+        .unwrap();
+    assert_eq!(image.bytes.as_ref(), b"\x89PNG\r\n");
+    assert_eq!(image.seed, 3);
+    assert_eq!(replay.remaining(), 0);
+
+    let result = generate.text_to_image(text_to_image()).await;
+    assert!(matches!(result, Err(StabilityAIError::Transport(_))));
+}
@@ -0,0 +1,120 @@
+//! Profiles of configuration files are merged with the default profile and environment variables.
+
+use std::path::PathBuf;
+
+use reqwest::StatusCode;
+use serde_json::json;
+use stabilityai::{
+    config::ClientConfig,
+    error::StabilityAIError,
+    transport::{HttpResponse, InMemoryTransport},
+    Client,
+};
+
+const CONFIG: &str = r#"
+[default]
+client_id = "my-app"
+client_version = "1.2.0"
+
+[dev]
+api_base = "http://localhost:8080/v1"
+
+[prod]
+organization = "org-123"
+backoff = { max_elapsed_time_secs = 300 }
+rate_limit = { requests = 150, interval_ms = 10000, max_in_flight = 8 }
+"#;
+
+fn write_config(name: &str, contents: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join("stabilityai-config-tests");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn profile_inherits_default() {
+    let path = write_config("inherit.toml", CONFIG);
+
+    let dev = ClientConfig::from_file(&path, "dev").unwrap();
+    assert_eq!(dev.api_base.as_deref(), Some("http://localhost:8080/v1"));
+    assert_eq!(dev.client_id.as_deref(), Some("my-app"));
+    assert_eq!(dev.client_version.as_deref(), Some("1.2.0"));
+    assert_eq!(dev.organization, None);
+
+    let prod = ClientConfig::from_file(&path, "prod").unwrap();
+    assert_eq!(prod.organization.as_deref(), Some("org-123"));
+    assert_eq!(prod.rate_limit.unwrap().max_in_flight, Some(8));
+}
+
+#[test]
+fn json_file() {
+    let config = json!({"default": {"api_key": "sk-json", "client_id": "json-app"}});
+    let path = write_config("profiles.json", &config.to_string());
+
+    let config = ClientConfig::from_file(path, "default").unwrap();
+    assert_eq!(config.api_key.unwrap().expose_secret(), "sk-json");
+    assert_eq!(config.client_id.as_deref(), Some("json-app"));
+}
+
+#[test]
+fn missing_profile() {
+    let path = write_config("missing.toml", CONFIG);
+
+    let result = ClientConfig::from_file(path, "staging");
+    assert!(matches!(result, Err(StabilityAIError::InvalidConfig(_))));
+}
+
+#[test]
+fn unknown_field() {
+    let path = write_config(
+        "unknown.toml",
+        "[default]\napi_url = \"http://localhost\"\n",
+    );
+
+    let result = ClientConfig::from_file(path, "default");
+    assert!(matches!(result, Err(StabilityAIError::InvalidConfig(_))));
+}
+
+#[test]
+fn env_vars_override_file() {
+    let path = write_config("env.toml", CONFIG);
+    let file = ClientConfig::from_file(path, "dev").unwrap();
+
+    let env = ClientConfig::from_vars([
+        ("STABILITY_API_KEY", "sk-env"),
+        ("STABILITY_CLIENT_VERSION", "2.0.0"),
+        ("HOME", "/root"),
+    ]);
+
+    let config = file.merge(env);
+    assert_eq!(config.api_key.unwrap().expose_secret(), "sk-env");
+    assert_eq!(config.client_id.as_deref(), Some("my-app"));
+    assert_eq!(config.client_version.as_deref(), Some("2.0.0"));
+}
+
+#[tokio::test]
+async fn client_from_config() {
+    let transport = InMemoryTransport::new()
+        .with_response(HttpResponse::json(StatusCode::OK, &json!({"credits": 3.0})).unwrap());
+
+    let config = ClientConfig {
+        api_key: Some("sk-config".into()),
+        api_base: Some("http://localhost:8080/v1".into()),
+        organization: Some("org-config".into()),
+        client_id: Some("my-app".into()),
+        client_version: Some("1.2.0".into()),
+        ..Default::default()
+    };
+
+    let client = Client::from(config).with_transport(transport.clone());
+    client.user().balance().await.unwrap();
+
+    let requests = transport.requests();
+    assert_eq!(requests[0].url, "http://localhost:8080/v1/user/balance");
+    assert_eq!(requests[0].headers["authorization"], "Bearer sk-config");
+    assert_eq!(requests[0].headers["organization"], "org-config");
+    assert_eq!(requests[0].headers["stability-client-id"], "my-app");
+    assert_eq!(requests[0].headers["stability-client-version"], "1.2.0");
+}
@@ -0,0 +1,56 @@
+//! API keys are redacted when formatted and can be fetched before every request.
+
+use reqwest::StatusCode;
+use serde_json::json;
+use stabilityai::{
+    credentials::{ApiKey, FileCredentials},
+    error::StabilityAIError,
+    transport::{HttpResponse, InMemoryTransport},
+    Client,
+};
+
+#[test]
+fn api_key_is_redacted() {
+    let api_key = ApiKey::new("sk-0123456789abcd");
+    assert_eq!(format!("{api_key}"), "[REDACTED]");
+    assert_eq!(format!("{api_key:?}"), "ApiKey([REDACTED])");
+    assert_eq!(api_key.redacted(), "sk-…abcd");
+    assert_eq!(api_key.expose_secret(), "sk-0123456789abcd");
+
+    let client = Client::new().with_api_key("sk-0123456789abcd");
+    assert!(!format!("{client:?}").contains("0123456789"));
+}
+
+#[tokio::test]
+async fn key_is_read_from_file_for_every_request() {
+    let path = std::env::temp_dir().join("stabilityai-credentials-test");
+    std::fs::write(&path, "sk-first\n").unwrap();
+
+    let balance = json!({"credits": 1.0});
+    let transport = InMemoryTransport::new()
+        .with_response(HttpResponse::json(StatusCode::OK, &balance).unwrap())
+        .with_response(HttpResponse::json(StatusCode::OK, &balance).unwrap());
+    let client = Client::new()
+        .with_credentials(FileCredentials::new(&path))
+        .with_transport(transport.clone());
+
+    client.user().balance().await.unwrap();
+    std::fs::write(&path, "sk-rotated").unwrap();
+    client.user().balance().await.unwrap();
+
+    let requests = transport.requests();
+    assert_eq!(requests[0].headers["authorization"], "Bearer sk-first");
+    assert_eq!(requests[1].headers["authorization"], "Bearer sk-rotated");
+}
+
+#[tokio::test]
+async fn missing_credentials_file() {
+    let transport = InMemoryTransport::new();
+    let client = Client::new()
+        .with_credentials(FileCredentials::new("/nonexistent/stability-key"))
+        .with_transport(transport.clone());
+
+    let result = client.user().balance().await;
+    assert!(matches!(result, Err(StabilityAIError::FileReadError(_))));
+    assert!(transport.requests().is_empty());
+}
@@ -0,0 +1,160 @@
+//! Identical generation requests in flight at the same time are sent once.
+
+use std::{sync::Arc, time::Duration};
+
+use reqwest::StatusCode;
+use serde_json::json;
+use stabilityai::{
+    error::StabilityAIError,
+    transport::{HttpRequest, HttpResponse, HttpTransport, InMemoryTransport},
+    types::{TextToImageRequestBody, TextToImageRequestBodyArgs},
+    Client, RequestOptions,
+};
+
+/// Answers after a delay, so that concurrent requests overlap
+#[derive(Debug, Clone)]
+struct SlowTransport(InMemoryTransport);
+
+#[async_trait::async_trait]
+impl HttpTransport for SlowTransport {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, StabilityAIError> {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        self.0.send(request).await
+    }
+}
+
+fn artifacts(seed: u32) -> HttpResponse {
+    let body =
+        json!({"artifacts": [{"base64": "iVBORw0KGgo=", "finishReason": "SUCCESS", "seed": seed}]});
+    HttpResponse::json(StatusCode::OK, &body).unwrap()
+}
+
+fn request(seed: u32) -> TextToImageRequestBody {
+    TextToImageRequestBodyArgs::default()
+        .text_prompts("A lighthouse")
+        .seed(seed)
+        .build()
+        .unwrap()
+}
+
+fn client(transport: &InMemoryTransport) -> Client {
+    let backoff = backoff::ExponentialBackoffBuilder::new()
+        .with_max_elapsed_time(Some(Duration::ZERO))
+        .build();
+
+    Client::new()
+        .with_api_key("sk-test")
+        .with_backoff(backoff)
+        .with_transport(SlowTransport(transport.clone()))
+        .with_deduplication(true)
+}
+
+#[tokio::test]
+async fn coalesce_identical_requests() {
+    let transport = InMemoryTransport::new()
+        .with_response(artifacts(1))
+        .with_response(artifacts(2));
+    let client = client(&transport);
+    let generate = client.generate("stable-diffusion-v1-6");
+    // Clones of the client share requests in flight
+    let clone = client.clone();
+    let generate_clone = clone.generate("stable-diffusion-v1-6");
+
+    let (a, b, c, other) = tokio::join!(
+        generate.text_to_image(request(1)),
+        generate.text_to_image(request(1)),
+        generate_clone.text_to_image(request(1)),
+        generate.text_to_image(request(2)),
+    );
+
+    let (a, b, c) = (a.unwrap(), b.unwrap(), c.unwrap());
+    assert!(Arc::ptr_eq(&a.artifacts[0], &b.artifacts[0]));
+    assert!(Arc::ptr_eq(&a.artifacts[0], &c.artifacts[0]));
+    assert_eq!(other.unwrap().artifacts[0].seed, 2);
+    assert_eq!(transport.requests().len(), 2);
+
+    // Completed requests are sent again
+    transport.push_response(artifacts(1));
+    generate.text_to_image(request(1)).await.unwrap();
+    assert_eq!(transport.requests().len(), 3);
+}
+
+#[tokio::test]
+async fn clones_with_other_credentials_do_not_share_calls() {
+    let transport = InMemoryTransport::new()
+        .with_response(artifacts(1))
+        .with_response(artifacts(1));
+    let client = client(&transport);
+    let other = client.clone().with_api_key("sk-other");
+    let generate = client.generate("stable-diffusion-v1-6");
+    let generate_other = other.generate("stable-diffusion-v1-6");
+
+    let (a, b) = tokio::join!(
+        generate.text_to_image(request(1)),
+        generate_other.text_to_image(request(1)),
+    );
+
+    assert!(!Arc::ptr_eq(
+        &a.unwrap().artifacts[0],
+        &b.unwrap().artifacts[0]
+    ));
+    let mut keys: Vec<_> = transport
+        .requests()
+        .iter()
+        .map(|request| {
+            request.headers["authorization"]
+                .to_str()
+                .unwrap()
+                .to_string()
+        })
+        .collect();
+    keys.sort();
+    assert_eq!(keys, ["Bearer sk-other", "Bearer sk-test"]);
+}
+
+#[tokio::test]
+async fn share_errors() {
+    let error = json!({"id": "1", "name": "bad_request", "message": "invalid seed"});
+    let transport = InMemoryTransport::new()
+        .with_response(HttpResponse::json(StatusCode::BAD_REQUEST, &error).unwrap());
+    let client = client(&transport);
+    let generate = client.generate("stable-diffusion-v1-6");
+
+    let (a, b) = tokio::join!(
+        generate.text_to_image(request(1)),
+        generate.text_to_image(request(1)),
+    );
+
+    for result in [a, b] {
+        let error = result.unwrap_err();
+        assert_eq!(error.status(), Some(StatusCode::BAD_REQUEST));
+        assert!(matches!(error, StabilityAIError::Coalesced(_)));
+    }
+    assert_eq!(transport.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn callers_keep_their_own_timeout() {
+    let transport = InMemoryTransport::new().with_response(artifacts(1));
+    let client = client(&transport);
+    let generate = client.generate("stable-diffusion-v1-6");
+    let impatient = client
+        .generate("stable-diffusion-v1-6")
+        .with_options(RequestOptions::new().with_timeout(Duration::from_millis(10)));
+
+    // The call goes on for the caller still waiting
+    let (a, b) = tokio::join!(
+        impatient.text_to_image(request(1)),
+        generate.text_to_image(request(1)),
+    );
+    assert!(matches!(a, Err(StabilityAIError::Timeout)));
+    assert_eq!(b.unwrap().artifacts[0].seed, 1);
+    assert_eq!(transport.requests().len(), 1);
+
+    // The call is dropped with its only caller, the next identical request is sent again
+    transport.push_response(artifacts(1));
+    let result = impatient.text_to_image(request(1)).await;
+    assert!(matches!(result, Err(StabilityAIError::Timeout)));
+    generate.text_to_image(request(1)).await.unwrap();
+    assert_eq!(transport.requests().len(), 2);
+}
@@ -0,0 +1,119 @@
+//! Dimensions of generation requests are validated against the rules of the engine.
+
+use stabilityai::{
+    error::StabilityAIError,
+    types::{validate_dimensions, TextToImageRequestBodyArgs, SDXL_DIMENSIONS},
+};
+
+fn assert_invalid(engine_id: &str, width: Option<u16>, height: Option<u16>, message: &str) {
+    match validate_dimensions(engine_id, width, height) {
+        Err(StabilityAIError::InvalidArgument(error)) => {
+            assert!(error.contains(message), "unexpected error: {error}")
+        }
+        result => panic!("{engine_id} {width:?}x{height:?} is not invalid: {result:?}"),
+    }
+}
+
+#[test]
+fn multiples_of_64() {
+    for engine_id in ["stable-diffusion-512-v2-1", "stable-diffusion-v1-6"] {
+        assert_invalid(
+            engine_id,
+            Some(500),
+            Some(512),
+            "width 500 is not a multiple of 64",
+        );
+        assert_invalid(engine_id, None, Some(64), "height 64 is less than 128");
+        assert!(validate_dimensions(engine_id, None, None).is_ok());
+    }
+}
+
+#[test]
+fn pixel_count_of_512_and_768_engines() {
+    assert!(validate_dimensions("stable-diffusion-512-v2-1", Some(512), Some(512)).is_ok());
+    assert!(validate_dimensions("stable-diffusion-512-v2-1", Some(1024), Some(1024)).is_ok());
+    assert_invalid(
+        "stable-diffusion-512-v2-1",
+        Some(256),
+        Some(512),
+        "256x512 is 131072 pixels",
+    );
+    assert_invalid(
+        "stable-diffusion-512-v2-1",
+        Some(2112),
+        None,
+        "2112x512 is 1081344 pixels",
+    );
+
+    assert!(validate_dimensions("stable-diffusion-768-v2-1", Some(768), Some(768)).is_ok());
+    assert_invalid(
+        "stable-diffusion-768-v2-1",
+        Some(512),
+        Some(512),
+        "768 engines require between 589824 and 1048576 pixels",
+    );
+}
+
+#[test]
+fn sdxl_beta_limits() {
+    let engine_id = "stable-diffusion-xl-beta-v2-2-2";
+    assert!(validate_dimensions(engine_id, Some(896), Some(512)).is_ok());
+    assert!(validate_dimensions(engine_id, Some(128), Some(896)).is_ok());
+    assert_invalid(
+        engine_id,
+        Some(960),
+        Some(128),
+        "width 960 is greater than 896",
+    );
+    assert_invalid(
+        engine_id,
+        Some(576),
+        Some(640),
+        "width 576 is greater than 512 while height 640 is greater than 512",
+    );
+}
+
+#[test]
+fn sdxl_fixed_dimensions() {
+    for engine_id in [
+        "stable-diffusion-xl-1024-v0-9",
+        "stable-diffusion-xl-1024-v1-0",
+    ] {
+        for (width, height) in SDXL_DIMENSIONS {
+            assert!(validate_dimensions(engine_id, Some(width), Some(height)).is_ok());
+        }
+        assert_invalid(
+            engine_id,
+            Some(1024),
+            Some(768),
+            "1024x768 is not one of the SDXL dimensions",
+        );
+        // Missing sides are the default of 512
+        assert_invalid(
+            engine_id,
+            Some(1216),
+            None,
+            "1216x512 is not one of the SDXL",
+        );
+        assert_invalid(engine_id, None, None, "512x512 is not one of the SDXL");
+    }
+}
+
+#[test]
+fn build_for_engine() {
+    let mut args = TextToImageRequestBodyArgs::default();
+    args.text_prompts("A lighthouse")
+        .width(1024_u16)
+        .height(1024_u16);
+
+    let request = args
+        .build_for_engine("stable-diffusion-xl-1024-v1-0")
+        .unwrap();
+    assert_eq!(request.width, Some(1024));
+    assert!(args.build_for_engine("stable-diffusion-768-v2-1").is_ok());
+    assert!(matches!(
+        args.width(1088_u16)
+            .build_for_engine("stable-diffusion-xl-1024-v1-0"),
+        Err(StabilityAIError::InvalidArgument(_))
+    ));
+}
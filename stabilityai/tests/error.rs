@@ -0,0 +1,176 @@
+//! API errors carry the HTTP status, headers and retryability of the response.
+
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use serde_json::json;
+use stabilityai::{
+    error::{ApiErrorKind, StabilityAIError},
+    transport::{HttpResponse, InMemoryTransport},
+    Client,
+};
+
+/// Client giving up on the first transient error
+fn client(transport: &InMemoryTransport) -> Client {
+    let backoff = backoff::ExponentialBackoffBuilder::new()
+        .with_max_elapsed_time(Some(Duration::ZERO))
+        .build();
+
+    Client::new()
+        .with_api_key("sk-test")
+        .with_backoff(backoff)
+        .with_transport(transport.clone())
+}
+
+async fn balance_error(status: StatusCode, name: &str) -> StabilityAIError {
+    let error = json!({"id": "1", "name": name, "message": "failed"});
+    let transport = InMemoryTransport::new().with_response(
+        HttpResponse::json(status, &error)
+            .unwrap()
+            .with_header("x-request-id", "req-1"),
+    );
+
+    client(&transport).user().balance().await.unwrap_err()
+}
+
+#[tokio::test]
+async fn api_error_kind() {
+    let cases = [
+        (
+            StatusCode::BAD_REQUEST,
+            "bad_request",
+            ApiErrorKind::BadRequest,
+        ),
+        (
+            StatusCode::BAD_REQUEST,
+            "invalid_prompts",
+            ApiErrorKind::ContentModeration,
+        ),
+        (
+            StatusCode::UNAUTHORIZED,
+            "unauthorized",
+            ApiErrorKind::Unauthorized,
+        ),
+        (
+            StatusCode::PAYMENT_REQUIRED,
+            "insufficient_balance",
+            ApiErrorKind::InsufficientBalance,
+        ),
+        (
+            StatusCode::FORBIDDEN,
+            "permission_denied",
+            ApiErrorKind::PermissionDenied,
+        ),
+        (StatusCode::NOT_FOUND, "not_found", ApiErrorKind::NotFound),
+    ];
+
+    for (status, name, kind) in cases {
+        let error = balance_error(status, name).await;
+        assert_eq!(error.status(), Some(status));
+        assert!(!error.is_retryable(), "{status} should not be retried");
+
+        let StabilityAIError::ApiError(api_error) = error else {
+            panic!("expected an API error for {status}");
+        };
+        assert_eq!(api_error.kind(), kind);
+        assert_eq!(api_error.headers["x-request-id"], "req-1");
+    }
+}
+
+#[tokio::test]
+async fn transient_api_error_is_retryable() {
+    let cases = [
+        (
+            StatusCode::TOO_MANY_REQUESTS,
+            "rate_limit_exceeded",
+            ApiErrorKind::RateLimited,
+        ),
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "server_error",
+            ApiErrorKind::ServerError,
+        ),
+    ];
+
+    for (status, name, kind) in cases {
+        let error = balance_error(status, name).await;
+        assert!(error.is_retryable(), "{status} should be retried");
+        assert!(error.to_string().starts_with(&format!("status: {status}")));
+
+        let StabilityAIError::ApiError(api_error) = error else {
+            panic!("expected an API error for {status}");
+        };
+        assert_eq!(api_error.kind(), kind);
+    }
+}
+
+#[tokio::test]
+async fn transport_error_has_no_status() {
+    let error = client(&InMemoryTransport::new())
+        .user()
+        .balance()
+        .await
+        .unwrap_err();
+
+    assert_eq!(error.status(), None);
+    assert!(!error.is_retryable());
+}
+
+#[tokio::test]
+async fn html_error_page_is_unexpected_response() {
+    let page = format!("<html><body>{}</body></html>", "Bad Gateway ".repeat(200));
+    let transport = InMemoryTransport::new().with_response(
+        HttpResponse::new(StatusCode::BAD_GATEWAY, page.clone())
+            .with_header("content-type", "text/html"),
+    );
+
+    let error = client(&transport).user().balance().await.unwrap_err();
+    assert_eq!(error.status(), Some(StatusCode::BAD_GATEWAY));
+    assert!(error.is_retryable());
+
+    let StabilityAIError::UnexpectedResponse(response) = error else {
+        panic!("expected an unexpected response error");
+    };
+    assert_eq!(response.content_type.as_deref(), Some("text/html"));
+    assert!(response.body.starts_with("<html><body>Bad Gateway"));
+    assert!(response
+        .body
+        .ends_with(&format!("... [{} bytes]", page.len())));
+    assert!(response.body.len() < page.len());
+}
+
+#[tokio::test]
+async fn unexpected_response_redacts_base64() {
+    let body = format!(
+        r#"{{"artifacts": [{{"base64": "{}"}}]}}"#,
+        "iVBORw0K".repeat(100)
+    );
+    let transport =
+        InMemoryTransport::new().with_response(HttpResponse::new(StatusCode::BAD_REQUEST, body));
+
+    let error = client(&transport).user().balance().await.unwrap_err();
+    assert!(!error.is_retryable());
+    assert_eq!(
+        error.to_string(),
+        r#"unexpected response, status: 400 Bad Request, content-type: none, body: {"artifacts": [{"base64": "[800 base64 characters]"}]}"#
+    );
+}
+
+#[tokio::test]
+async fn unexpected_response_lossy_utf8() {
+    let mut body = b"\xff".to_vec();
+    body.extend("A".repeat(4094).as_bytes());
+    // The first character is split between two decoded chunks
+    body.extend("é".repeat(10).as_bytes());
+    let transport =
+        InMemoryTransport::new().with_response(HttpResponse::new(StatusCode::BAD_REQUEST, body));
+
+    let error = client(&transport).user().balance().await.unwrap_err();
+    let StabilityAIError::UnexpectedResponse(response) = error else {
+        panic!("expected an unexpected response error");
+    };
+    assert_eq!(
+        response.body,
+        format!("\u{FFFD}[4094 base64 characters]{}", "é".repeat(10))
+    );
+}
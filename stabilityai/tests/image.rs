@@ -0,0 +1,51 @@
+//! Images expose their decoded bytes and keep the JSON shape of the API.
+
+use serde_json::json;
+use stabilityai::{
+    error::StabilityAIError,
+    types::{FinishReason, Image},
+};
+
+#[tokio::test]
+async fn decode_deserialized_image() {
+    let image: Image = serde_json::from_value(json!({
+        "base64": "iVBORw0KGgo=",
+        "finishReason": "SUCCESS",
+        "seed": 42,
+    }))
+    .unwrap();
+    let copy = image.clone();
+
+    let bytes = image.bytes().unwrap();
+    assert_eq!(bytes.as_ref(), b"\x89PNG\r\n\x1a\n");
+    // Decoded once, then served from the cache
+    assert_eq!(image.bytes().unwrap().as_ptr(), bytes.as_ptr());
+    assert_eq!(image, copy);
+
+    let mut written = Vec::new();
+    image.write_to(&mut written).await.unwrap();
+    assert_eq!(written, bytes);
+}
+
+#[test]
+fn serialize_image_from_bytes() {
+    let image = Image::from_bytes(&b"\x89PNG\r\n\x1a\n"[..], FinishReason::Success, 7);
+
+    assert_eq!(
+        serde_json::to_value(&image).unwrap(),
+        json!({"base64": "iVBORw0KGgo=", "finishReason": "SUCCESS", "seed": 7})
+    );
+    assert_eq!(
+        image,
+        Image::from_base64("iVBORw0KGgo=", FinishReason::Success, 7)
+    );
+}
+
+#[test]
+fn invalid_base64() {
+    let image = Image::from_base64("not base64!", FinishReason::Success, 1);
+    assert!(matches!(
+        image.bytes(),
+        Err(StabilityAIError::InvalidBase64(_))
+    ));
+}
@@ -0,0 +1,144 @@
+//! Requests fail over to the next key of the pool on 401, 402 and 429 responses.
+
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use serde_json::json;
+use stabilityai::{
+    error::StabilityAIError,
+    key_pool::{ApiKeyPool, PooledKey},
+    transport::{HttpResponse, InMemoryTransport},
+    Client,
+};
+
+fn client(transport: &InMemoryTransport, pool: &ApiKeyPool) -> Client {
+    let backoff = backoff::ExponentialBackoffBuilder::new()
+        .with_initial_interval(Duration::from_millis(1))
+        .with_max_elapsed_time(Some(Duration::from_secs(1)))
+        .build();
+
+    Client::new()
+        .with_organization("org-client")
+        .with_backoff(backoff)
+        .with_api_key_pool(pool.clone())
+        .with_transport(transport.clone())
+}
+
+fn balance() -> HttpResponse {
+    HttpResponse::json(StatusCode::OK, &json!({"credits": 1.0})).unwrap()
+}
+
+fn error(status: StatusCode) -> HttpResponse {
+    let error = json!({"id": "1", "name": "error", "message": "failed"});
+    HttpResponse::json(status, &error).unwrap()
+}
+
+#[tokio::test]
+async fn round_robin() {
+    let transport = InMemoryTransport::new()
+        .with_response(balance())
+        .with_response(balance())
+        .with_response(balance());
+    let pool = ApiKeyPool::new([
+        PooledKey::new("sk-first").with_name("first"),
+        PooledKey::new("sk-second").with_organization("org-second"),
+    ]);
+    let client = client(&transport, &pool);
+
+    let first = client.user().balance_with_meta().await.unwrap();
+    let second = client.user().balance_with_meta().await.unwrap();
+    let third = client.user().balance_with_meta().await.unwrap();
+    assert_eq!(first.api_key_name.as_deref(), Some("first"));
+    assert_eq!(second.api_key_name.as_deref(), Some("sk-…cond"));
+    assert_eq!(third.api_key_name.as_deref(), Some("first"));
+
+    let requests = transport.requests();
+    assert_eq!(requests[0].headers["authorization"], "Bearer sk-first");
+    assert_eq!(requests[0].headers["organization"], "org-client");
+    assert_eq!(requests[1].headers["authorization"], "Bearer sk-second");
+    assert_eq!(requests[1].headers["organization"], "org-second");
+}
+
+#[tokio::test]
+async fn unauthorized_key_is_dropped() {
+    let transport = InMemoryTransport::new()
+        .with_response(error(StatusCode::UNAUTHORIZED))
+        .with_response(balance())
+        .with_response(balance());
+    let pool = ApiKeyPool::new(["sk-revoked", "sk-valid"]);
+    let client = client(&transport, &pool);
+
+    let response = client.user().balance_with_meta().await.unwrap();
+    assert_eq!(response.attempts, 2);
+    assert_eq!(response.api_key_name.as_deref(), Some("sk-…alid"));
+    assert_eq!(pool.keys().len(), 1);
+
+    client.user().balance().await.unwrap();
+    let requests = transport.requests();
+    assert_eq!(requests[2].headers["authorization"], "Bearer sk-valid");
+}
+
+#[tokio::test]
+async fn rate_limited_key_is_benched() {
+    let transport = InMemoryTransport::new()
+        .with_response(error(StatusCode::TOO_MANY_REQUESTS))
+        .with_response(balance());
+    let pool = ApiKeyPool::new(["sk-first", "sk-second"]);
+
+    client(&transport, &pool).user().balance().await.unwrap();
+    assert_eq!(pool.keys().len(), 2);
+    assert_eq!(pool.available(), 1);
+}
+
+#[tokio::test]
+async fn out_of_credits_without_other_key() {
+    let transport = InMemoryTransport::new().with_response(error(StatusCode::PAYMENT_REQUIRED));
+    let pool = ApiKeyPool::new(["sk-only"]);
+
+    let result = client(&transport, &pool).user().balance().await;
+    assert!(matches!(result, Err(StabilityAIError::ApiError(_))));
+    assert_eq!(transport.requests().len(), 1);
+    assert_eq!(pool.available(), 0);
+}
+
+#[tokio::test]
+async fn all_keys_dropped() {
+    let transport = InMemoryTransport::new()
+        .with_response(error(StatusCode::UNAUTHORIZED))
+        .with_response(error(StatusCode::UNAUTHORIZED));
+    let pool = ApiKeyPool::new(["sk-first", "sk-second"]);
+    let client = client(&transport, &pool);
+
+    let result = client.user().balance().await;
+    assert!(matches!(result, Err(StabilityAIError::ApiError(_))));
+
+    let result = client.user().balance().await;
+    assert!(matches!(result, Err(StabilityAIError::InvalidArgument(_))));
+    assert_eq!(transport.requests().len(), 2);
+}
+
+#[tokio::test]
+async fn failover_is_bounded_by_backoff() {
+    let transport = InMemoryTransport::new();
+    for _ in 0..5 {
+        transport.push_response(error(StatusCode::TOO_MANY_REQUESTS));
+    }
+    let pool = ApiKeyPool::new(["sk-first", "sk-second"]).with_bench_duration(Duration::ZERO);
+    let backoff = backoff::ExponentialBackoffBuilder::new()
+        .with_max_elapsed_time(Some(Duration::ZERO))
+        .build();
+
+    let result = client(&transport, &pool)
+        .with_backoff(backoff)
+        .user()
+        .balance()
+        .await;
+    // Each key is tried once, then the backoff gives up with the last error
+    match result {
+        Err(StabilityAIError::ApiError(error)) => {
+            assert_eq!(error.status, StatusCode::TOO_MANY_REQUESTS)
+        }
+        result => panic!("unexpected result: {result:?}"),
+    }
+    assert_eq!(transport.requests().len(), 2);
+}
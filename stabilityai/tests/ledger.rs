@@ -0,0 +1,235 @@
+//! Every API call is recorded into the ledger of the client.
+
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use serde_json::json;
+use stabilityai::{
+    budget::BudgetGuard,
+    cassette::{RecordedBody, RecordedPart},
+    key_pool::{ApiKeyPool, PooledKey},
+    ledger::{usage_by_day, JsonlLedger, LedgerEntry, MemoryLedger, UsageKey},
+    transport::{HttpResponse, InMemoryTransport, ResponseBody},
+    types::{FinishReason, ImageToImageRequestBodyArgs, TextToImageRequestBodyArgs},
+    Client, FINISH_REASON_HEADER, SEED_HEADER,
+};
+
+fn client(transport: &InMemoryTransport) -> Client {
+    let backoff = backoff::ExponentialBackoffBuilder::new()
+        .with_max_elapsed_time(Some(Duration::ZERO))
+        .build();
+
+    Client::new()
+        .with_api_key("sk-test")
+        .with_organization("org-a")
+        .with_backoff(backoff)
+        .with_transport(transport.clone())
+}
+
+#[tokio::test]
+async fn record_calls() {
+    let artifacts = json!({"artifacts": [
+        {"base64": "iVBORw0KGgo=", "finishReason": "SUCCESS", "seed": 1},
+        {"base64": "iVBORw0KGgo=", "finishReason": "CONTENT_FILTERED", "seed": 2},
+    ]});
+    let error = json!({"id": "1", "name": "bad_request", "message": "invalid steps"});
+    let png = HttpResponse::new(StatusCode::OK, ResponseBody::from(&b"\x89PNG"[..]))
+        .with_header("content-type", "image/png")
+        .with_header(SEED_HEADER, "9")
+        .with_header(FINISH_REASON_HEADER, "SUCCESS");
+    let transport = InMemoryTransport::new()
+        .with_response(HttpResponse::json(StatusCode::OK, &artifacts).unwrap())
+        .with_response(png)
+        .with_response(HttpResponse::json(StatusCode::BAD_REQUEST, &error).unwrap());
+    let ledger = MemoryLedger::new();
+    let client = client(&transport).with_ledger(ledger.clone());
+    let generate = client.generate("stable-diffusion-v1-6");
+
+    let text_to_image = TextToImageRequestBodyArgs::default()
+        .text_prompts("A lighthouse")
+        .steps(30_u32)
+        .samples(2)
+        .build()
+        .unwrap();
+    generate.text_to_image(text_to_image.clone()).await.unwrap();
+    generate.text_to_image_binary(text_to_image).await.unwrap();
+
+    let dir = std::env::temp_dir().join("stabilityai-ledger-tests");
+    std::fs::create_dir_all(&dir).unwrap();
+    let init_image = dir.join("init.png");
+    std::fs::write(&init_image, b"init image").unwrap();
+    let image_to_image = ImageToImageRequestBodyArgs::default()
+        .text_prompts("A crab")
+        .init_image(&init_image)
+        .build()
+        .unwrap();
+    generate.image_to_image(image_to_image).await.unwrap_err();
+
+    let entries = ledger.entries();
+    assert_eq!(entries.len(), 3);
+
+    let json = &entries[0];
+    assert_eq!(
+        json.endpoint,
+        "/generation/stable-diffusion-v1-6/text-to-image"
+    );
+    assert_eq!(json.engine_id.as_deref(), Some("stable-diffusion-v1-6"));
+    assert_eq!(json.organization.as_deref(), Some("org-a"));
+    assert_eq!(json.api_key.as_deref(), Some("sk-…test"));
+    assert_eq!(json.status, Some(200));
+    assert_eq!(json.seeds, [1, 2]);
+    assert_eq!(
+        json.finish_reasons,
+        [FinishReason::Success, FinishReason::ContentFiltered]
+    );
+    assert_eq!(json.estimated_credits, Some(0.4));
+    assert!(matches!(&json.parameters, RecordedBody::Json(value) if value["samples"] == 2));
+
+    let png = &entries[1];
+    assert_eq!(png.seeds, [9]);
+    assert_eq!(png.finish_reasons, [FinishReason::Success]);
+    assert_eq!(png.estimated_credits, Some(0.2));
+
+    let failed = &entries[2];
+    assert_eq!(failed.status, Some(400));
+    assert!(failed.error.as_deref().unwrap().contains("invalid steps"));
+    // Uploaded images are recorded by file name only
+    let RecordedBody::Multipart(parts) = &failed.parameters else {
+        panic!("unexpected parameters: {:?}", failed.parameters);
+    };
+    assert!(parts.contains(&RecordedPart::File {
+        name: "init_image".into(),
+        file_name: "init.png".into(),
+    }));
+}
+
+#[tokio::test]
+async fn attribute_calls_to_the_key_which_served_them() {
+    let artifacts =
+        json!({"artifacts": [{"base64": "iVBORw0KGgo=", "finishReason": "SUCCESS", "seed": 1}]});
+    let error = json!({"id": "1", "name": "rate_limited", "message": "slow down"});
+    let transport = InMemoryTransport::new()
+        .with_response(HttpResponse::json(StatusCode::OK, &json!({"credits": 5.0})).unwrap())
+        .with_response(HttpResponse::json(StatusCode::TOO_MANY_REQUESTS, &error).unwrap())
+        .with_response(HttpResponse::json(StatusCode::OK, &artifacts).unwrap());
+    let pool = ApiKeyPool::new([
+        PooledKey::new("sk-first")
+            .with_name("first")
+            .with_organization("org-b"),
+        PooledKey::new("sk-second").with_name("second"),
+    ]);
+    let ledger = MemoryLedger::new();
+    let client = client(&transport)
+        .with_api_key_pool(pool)
+        .with_budget(BudgetGuard::new().with_min_balance(1.0))
+        .with_ledger(ledger.clone());
+
+    let request = TextToImageRequestBodyArgs::default()
+        .text_prompts("A lighthouse")
+        .build()
+        .unwrap();
+    client
+        .generate("stable-diffusion-v1-6")
+        .text_to_image(request)
+        .await
+        .unwrap();
+
+    let entries = ledger.entries();
+    assert_eq!(entries.len(), 2);
+    // The balance checked by the budget guard is a call too
+    assert_eq!(entries[0].endpoint, "/user/balance");
+    assert_eq!(entries[0].organization.as_deref(), Some("org-b"));
+    assert_eq!(entries[0].api_key.as_deref(), Some("first"));
+    // Failed over from the second key to the first, billed to its organization
+    assert_eq!(
+        entries[1].engine_id.as_deref(),
+        Some("stable-diffusion-v1-6")
+    );
+    assert_eq!(entries[1].organization.as_deref(), Some("org-b"));
+    assert_eq!(entries[1].api_key.as_deref(), Some("first"));
+    assert_eq!(entries[1].status, Some(200));
+}
+
+#[tokio::test]
+async fn sum_usage_from_jsonl_file() {
+    let path = std::env::temp_dir()
+        .join("stabilityai-ledger-tests")
+        .join("usage.jsonl");
+    std::fs::remove_file(&path).ok();
+
+    let artifacts =
+        json!({"artifacts": [{"base64": "iVBORw0KGgo=", "finishReason": "SUCCESS", "seed": 1}]});
+    let transport = InMemoryTransport::new()
+        .with_response(HttpResponse::json(StatusCode::OK, &artifacts).unwrap())
+        .with_response(HttpResponse::json(StatusCode::OK, &json!({"credits": 3.5})).unwrap());
+    let ledger = JsonlLedger::new(&path);
+    let client = client(&transport).with_ledger(ledger.clone());
+
+    let request = TextToImageRequestBodyArgs::default()
+        .text_prompts("A lighthouse")
+        .steps(30_u32)
+        .build()
+        .unwrap();
+    client
+        .generate("stable-diffusion-v1-6")
+        .text_to_image(request)
+        .await
+        .unwrap();
+    client.user().balance().await.unwrap();
+
+    let mut entries = ledger.entries().unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[1].endpoint, "/user/balance");
+    assert_eq!(entries[1].estimated_credits, None);
+
+    // Calls of the next day
+    let next_day = LedgerEntry {
+        timestamp_ms: entries[0].timestamp_ms + 24 * 3600 * 1000,
+        ..entries[0].clone()
+    };
+    let failed = LedgerEntry {
+        status: Some(500),
+        error: Some("server error".into()),
+        seeds: Vec::new(),
+        ..next_day.clone()
+    };
+    entries.extend([next_day.clone(), failed]);
+
+    let usage = usage_by_day(&entries);
+    assert_eq!(usage.len(), 3);
+
+    let key = UsageKey {
+        organization: Some("org-a".into()),
+        engine_id: Some("stable-diffusion-v1-6".into()),
+        day: next_day.day(),
+    };
+    let next_day_usage = &usage[&key];
+    assert_eq!(next_day_usage.calls, 2);
+    assert_eq!(next_day_usage.failed_calls, 1);
+    assert_eq!(next_day_usage.images, 1);
+    assert_eq!(next_day_usage.estimated_credits, 0.2);
+}
+
+#[test]
+fn format_utc_day() {
+    let entry = |timestamp_ms| LedgerEntry {
+        timestamp_ms,
+        method: "GET".into(),
+        endpoint: "/user/balance".into(),
+        engine_id: None,
+        organization: None,
+        api_key: None,
+        parameters: RecordedBody::Empty,
+        status: Some(200),
+        error: None,
+        seeds: Vec::new(),
+        finish_reasons: Vec::new(),
+        latency_ms: 10,
+        estimated_credits: None,
+    };
+
+    assert_eq!(entry(0).day(), "1970-01-01");
+    // 2024-02-29T23:59:59.999Z
+    assert_eq!(entry(1_709_251_199_999).day(), "2024-02-29");
+    assert_eq!(entry(1_709_251_200_000).day(), "2024-03-01");
+}
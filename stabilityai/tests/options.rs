@@ -0,0 +1,99 @@
+//! Per-call options override client headers and bound the duration of a call.
+
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use reqwest::{header::HeaderValue, StatusCode};
+use serde_json::json;
+use stabilityai::{
+    error::StabilityAIError,
+    key_pool::{ApiKeyPool, PooledKey},
+    transport::{HttpRequest, HttpResponse, HttpTransport, InMemoryTransport},
+    Client, RequestOptions,
+};
+use tokio_util::sync::CancellationToken;
+
+/// Transport which never answers
+#[derive(Debug)]
+struct HangingTransport;
+
+#[async_trait]
+impl HttpTransport for HangingTransport {
+    async fn send(&self, _request: HttpRequest) -> Result<HttpResponse, StabilityAIError> {
+        futures::future::pending().await
+    }
+}
+
+fn balance() -> HttpResponse {
+    HttpResponse::json(StatusCode::OK, &json!({"credits": 1.0})).unwrap()
+}
+
+#[tokio::test]
+async fn headers_and_organization_override() {
+    let transport = InMemoryTransport::new()
+        .with_response(balance())
+        .with_response(balance());
+    let client = Client::new()
+        .with_organization("org-client")
+        .with_transport(transport.clone());
+
+    let options = RequestOptions::new()
+        .with_organization("org-tenant")
+        .with_header("x-tenant".parse().unwrap(), HeaderValue::from_static("a"));
+    client.user().with_options(options).balance().await.unwrap();
+    client.user().balance().await.unwrap();
+
+    let requests = transport.requests();
+    assert_eq!(requests[0].headers["organization"], "org-tenant");
+    assert_eq!(requests[0].headers["x-tenant"], "a");
+    assert_eq!(requests[1].headers["organization"], "org-client");
+    assert!(!requests[1].headers.contains_key("x-tenant"));
+}
+
+#[tokio::test]
+async fn organization_override_takes_precedence_over_pooled_key() {
+    let transport = InMemoryTransport::new().with_response(balance());
+    let pool = ApiKeyPool::new([PooledKey::new("sk-pooled").with_organization("org-key")]);
+    let client = Client::new()
+        .with_api_key_pool(pool)
+        .with_transport(transport.clone());
+
+    let options = RequestOptions::new().with_organization("org-tenant");
+    client.user().with_options(options).balance().await.unwrap();
+
+    assert_eq!(
+        transport.requests()[0].headers["organization"],
+        "org-tenant"
+    );
+}
+
+#[tokio::test]
+async fn timeout() {
+    let client = Client::new().with_transport(HangingTransport);
+
+    let options = RequestOptions::new().with_timeout(Duration::from_millis(20));
+    let result = client.engines().with_options(options).list().await;
+    assert!(matches!(result, Err(StabilityAIError::Timeout)));
+
+    let options = RequestOptions::new()
+        .with_timeout(Duration::from_secs(60))
+        .with_deadline(Instant::now() + Duration::from_millis(20));
+    let result = client.engines().with_options(options).list().await;
+    assert!(matches!(result, Err(StabilityAIError::Timeout)));
+}
+
+#[tokio::test]
+async fn cancellation() {
+    let client = Client::new().with_transport(HangingTransport);
+    let token = CancellationToken::new();
+
+    let cancel = token.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        cancel.cancel();
+    });
+
+    let options = RequestOptions::new().with_cancellation(token);
+    let result = client.user().with_options(options).account().await;
+    assert!(matches!(result, Err(StabilityAIError::Cancelled)));
+}
@@ -0,0 +1,76 @@
+//! Previews show the request which would be sent, rendered as a curl command.
+
+use reqwest::Method;
+use stabilityai::{
+    transport::RequestBody,
+    types::{ImageToImageRequestBodyArgs, TextToImageRequestBodyArgs},
+    Client, RequestOptions,
+};
+
+fn client() -> Client {
+    Client::new()
+        .with_api_key("sk-secret")
+        .with_organization("org-test")
+}
+
+#[test]
+fn text_to_image_preview() {
+    let request = TextToImageRequestBodyArgs::default()
+        .text_prompts("It's a lighthouse")
+        .build()
+        .unwrap();
+
+    let preview = client()
+        .generate("stable-diffusion-v1-6")
+        .preview_text_to_image(request)
+        .unwrap();
+
+    assert_eq!(preview.method, Method::POST);
+    assert_eq!(
+        preview.url,
+        "https://api.stability.ai/v1/generation/stable-diffusion-v1-6/text-to-image"
+    );
+    assert_eq!(preview.headers["authorization"], "Bearer [REDACTED]");
+    assert!(matches!(preview.body, RequestBody::Json(_)));
+
+    let curl = preview.to_curl();
+    assert!(!curl.contains("sk-secret"));
+    assert_eq!(
+        curl,
+        r#"curl -f -sS -X POST 'https://api.stability.ai/v1/generation/stable-diffusion-v1-6/text-to-image' \
+  -H 'organization: org-test' \
+  -H "Authorization: Bearer $STABILITY_API_KEY" \
+  -H 'content-type: application/json' \
+  --data-raw '{
+  "text_prompts": [
+    {
+      "text": "It'\''s a lighthouse"
+    }
+  ]
+}'"#
+    );
+}
+
+#[test]
+fn image_to_image_preview() {
+    let request = ImageToImageRequestBodyArgs::default()
+        .text_prompts("A galactic dog in space")
+        .init_image("../init_image_1024.png")
+        .samples(1)
+        .build()
+        .unwrap();
+
+    let options = RequestOptions::new().with_organization("org-override");
+    let preview = client()
+        .generate("stable-diffusion-xl-1024-v1-0")
+        .with_options(options)
+        .preview_image_to_image(request)
+        .unwrap();
+
+    assert_eq!(preview.headers["organization"], "org-override");
+    let curl = preview.to_string();
+    assert!(curl.contains(r#"-F 'init_image=@"../init_image_1024.png"'"#));
+    assert!(curl.contains("--form-string 'text_prompts[0][text]=A galactic dog in space'"));
+    assert!(curl.contains("--form-string 'samples=1'"));
+    assert!(!curl.contains("content-type"));
+}
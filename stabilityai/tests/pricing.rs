@@ -0,0 +1,139 @@
+//! Credits of generation requests are estimated offline from a pricing table.
+
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use serde_json::json;
+use stabilityai::{
+    budget::BudgetGuard,
+    pricing::{EnginePrice, PricingTable},
+    transport::{HttpResponse, InMemoryTransport},
+    types::{
+        ImageToImageRequestBodyArgs, ImageToImageUpscaleBody, RealESRGANUpscaleRequestBodyArgs,
+        TextToImageRequestBodyArgs,
+    },
+    Client,
+};
+
+fn assert_credits(actual: f64, expected: f64) {
+    assert!(
+        (actual - expected).abs() < 1e-9,
+        "{actual} credits instead of {expected}"
+    );
+}
+
+#[test]
+fn estimate_request_bodies() {
+    let pricing = PricingTable::new(EnginePrice::flat(1.0))
+        .with_price(
+            "scaled",
+            EnginePrice::flat(0.2)
+                .with_reference_steps(30)
+                .with_reference_size(512, 512),
+        )
+        .with_price("upscaler", EnginePrice::flat(0.5));
+
+    let text_to_image = TextToImageRequestBodyArgs::default()
+        .text_prompts("A lighthouse")
+        .width(1024_u16)
+        .height(512_u16)
+        .steps(60_u32)
+        .samples(3)
+        .build()
+        .unwrap();
+    assert_credits(
+        pricing.estimate("scaled", &text_to_image),
+        0.2 * 2.0 * 2.0 * 3.0,
+    );
+    // Engines missing from the table have the fallback price
+    assert_credits(pricing.estimate("unknown", &text_to_image), 3.0);
+
+    // Missing steps are the 50 steps of the API, the size is the init image size
+    let image_to_image = ImageToImageRequestBodyArgs::default()
+        .text_prompts("A crab")
+        .init_image("init.png")
+        .build()
+        .unwrap();
+    assert_credits(
+        pricing.estimate("scaled", &image_to_image),
+        0.2 * 50.0 / 30.0,
+    );
+
+    let upscale: ImageToImageUpscaleBody = RealESRGANUpscaleRequestBodyArgs::default()
+        .image("small.png")
+        .width(2048_u16)
+        .build()
+        .unwrap()
+        .into();
+    assert_credits(pricing.estimate("upscaler", &upscale), 0.5);
+}
+
+#[test]
+fn update_pricing_table() {
+    let mut pricing = PricingTable::default();
+    pricing.set_price("stable-diffusion-xl-1024-v1-0", EnginePrice::flat(0.6));
+    assert_eq!(
+        pricing.price("stable-diffusion-xl-1024-v1-0"),
+        &EnginePrice::flat(0.6)
+    );
+
+    let loaded: PricingTable = serde_json::from_value(json!({
+        "engines": {
+            "stable-diffusion-v1-6": {"credits": 0.3, "reference_steps": 30},
+        },
+    }))
+    .unwrap();
+    assert_eq!(
+        loaded.price("stable-diffusion-v1-6"),
+        &EnginePrice::flat(0.3).with_reference_steps(30)
+    );
+    assert_eq!(loaded.engines().count(), 1);
+
+    let round_trip: PricingTable =
+        serde_json::from_value(serde_json::to_value(&pricing).unwrap()).unwrap();
+    assert_eq!(round_trip, pricing);
+}
+
+#[tokio::test]
+async fn budget_guard_uses_pricing_table() {
+    let body =
+        json!({"artifacts": [{"base64": "iVBORw0KGgo=", "finishReason": "SUCCESS", "seed": 1}]});
+    let transport = InMemoryTransport::new()
+        .with_response(HttpResponse::json(StatusCode::OK, &body).unwrap())
+        .with_response(HttpResponse::json(StatusCode::OK, &body).unwrap());
+    let pricing = PricingTable::new(EnginePrice::flat(0.1))
+        .with_price("expensive", EnginePrice::flat(2.0).with_reference_steps(10));
+    let guard = BudgetGuard::new().with_budget(5.0).with_pricing(pricing);
+    let backoff = backoff::ExponentialBackoffBuilder::new()
+        .with_max_elapsed_time(Some(Duration::ZERO))
+        .build();
+    let client = Client::new()
+        .with_api_key("sk-test")
+        .with_backoff(backoff)
+        .with_transport(transport.clone())
+        .with_budget(guard.clone());
+
+    let request = TextToImageRequestBodyArgs::default()
+        .text_prompts("A lighthouse")
+        .steps(20_u32)
+        .build()
+        .unwrap();
+    client
+        .generate("cheap")
+        .text_to_image(request.clone())
+        .await
+        .unwrap();
+    client
+        .generate("expensive")
+        .text_to_image(request.clone())
+        .await
+        .unwrap();
+    assert_credits(guard.spent(), 4.1);
+
+    assert!(client
+        .generate("expensive")
+        .text_to_image(request)
+        .await
+        .is_err());
+    assert_eq!(transport.requests().len(), 2);
+}
@@ -0,0 +1,78 @@
+//! Requests of all clones of a client share the same rate limiter.
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use reqwest::StatusCode;
+use stabilityai::{
+    error::StabilityAIError,
+    rate_limit::RateLimit,
+    transport::{HttpRequest, HttpResponse, HttpTransport},
+    Client,
+};
+
+/// Transport answering after a delay and recording the highest number of concurrent requests
+#[derive(Debug, Clone, Default)]
+struct SlowTransport {
+    in_flight: Arc<AtomicUsize>,
+    max_in_flight: Arc<AtomicUsize>,
+    requests: Arc<AtomicUsize>,
+}
+
+#[async_trait::async_trait]
+impl HttpTransport for SlowTransport {
+    async fn send(&self, _request: HttpRequest) -> Result<HttpResponse, StabilityAIError> {
+        self.requests.fetch_add(1, Ordering::SeqCst);
+        let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        Ok(HttpResponse::new(StatusCode::OK, r#"{"credits": 1.0}"#))
+    }
+}
+
+#[tokio::test]
+async fn limit_requests_per_interval() {
+    let transport = SlowTransport::default();
+    let client = Client::new()
+        .with_transport(transport.clone())
+        .with_rate_limit(RateLimit::new(2, Duration::from_millis(200)));
+
+    let started = Instant::now();
+    let calls = (0..4).map(|_| {
+        let client = client.clone();
+        async move { client.user().balance().await }
+    });
+    for result in futures::future::join_all(calls).await {
+        result.unwrap();
+    }
+
+    // Two requests are sent right away, the other two wait 100ms each for a token
+    assert!(started.elapsed() >= Duration::from_millis(190));
+    assert_eq!(transport.requests.load(Ordering::SeqCst), 4);
+}
+
+#[tokio::test]
+async fn limit_requests_in_flight() {
+    let transport = SlowTransport::default();
+    let client = Client::new()
+        .with_transport(transport.clone())
+        .with_rate_limit(RateLimit::new(100, Duration::from_secs(1)).with_max_in_flight(2));
+
+    let calls = (0..6).map(|_| {
+        let client = client.clone();
+        async move { client.user().balance().await }
+    });
+    for result in futures::future::join_all(calls).await {
+        result.unwrap();
+    }
+
+    assert_eq!(transport.max_in_flight.load(Ordering::SeqCst), 2);
+}
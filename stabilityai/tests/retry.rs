@@ -0,0 +1,44 @@
+use std::time::{Duration, SystemTime};
+
+use reqwest::{
+    header::{HeaderMap, HeaderValue, RETRY_AFTER},
+    StatusCode,
+};
+use stabilityai::retry::{parse_retry_after, DefaultRetryPolicy, RetryPolicy};
+
+#[test]
+fn retry_after_in_seconds() {
+    let mut headers = HeaderMap::new();
+    headers.insert(RETRY_AFTER, HeaderValue::from_static("7"));
+    assert_eq!(parse_retry_after(&headers), Some(Duration::from_secs(7)));
+}
+
+#[test]
+fn retry_after_as_http_date() {
+    let date = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(120));
+    let mut headers = HeaderMap::new();
+    headers.insert(RETRY_AFTER, date.parse().unwrap());
+
+    let delay = parse_retry_after(&headers).unwrap();
+    assert!(delay > Duration::from_secs(100) && delay <= Duration::from_secs(120));
+
+    headers.insert(RETRY_AFTER, HeaderValue::from_static("soon"));
+    assert_eq!(parse_retry_after(&headers), None);
+}
+
+#[test]
+fn default_policy_statuses() {
+    let policy = DefaultRetryPolicy::default();
+    assert!(policy.is_retryable_status(StatusCode::TOO_MANY_REQUESTS));
+    assert!(policy.is_retryable_status(StatusCode::BAD_GATEWAY));
+    assert!(!policy.is_retryable_status(StatusCode::BAD_REQUEST));
+    assert!(!policy.is_retryable_status(StatusCode::UNAUTHORIZED));
+
+    let policy = DefaultRetryPolicy::rate_limit_only();
+    assert!(!policy.is_retryable_status(StatusCode::SERVICE_UNAVAILABLE));
+
+    let mut headers = HeaderMap::new();
+    headers.insert(RETRY_AFTER, HeaderValue::from_static("3"));
+    let policy = DefaultRetryPolicy::new().with_retry_after(false);
+    assert_eq!(policy.retry_after(&headers), None);
+}
@@ -0,0 +1,52 @@
+#![cfg(feature = "tower")]
+//! Requests go through tower layers stacked on top of [TransportService].
+
+use reqwest::{header::HeaderValue, StatusCode};
+use serde_json::json;
+use stabilityai::{
+    error::StabilityAIError,
+    service::TransportService,
+    transport::{HttpRequest, HttpResponse, InMemoryTransport},
+    Client,
+};
+use tower::ServiceBuilder;
+
+#[tokio::test]
+async fn layers_wrap_requests() {
+    let transport = InMemoryTransport::new()
+        .with_response(HttpResponse::json(StatusCode::OK, &json!({"credits": 3.0})).unwrap());
+
+    let service = ServiceBuilder::new()
+        .map_request(|mut request: HttpRequest| {
+            request
+                .headers
+                .insert("x-trace-id", HeaderValue::from_static("trace-1"));
+            request
+        })
+        .concurrency_limit(1)
+        .service(TransportService::new(transport.clone()));
+
+    let client = Client::new().with_api_key("sk-test").with_service(service);
+
+    let balance = client.user().balance().await.unwrap();
+    assert_eq!(balance.credits, 3.0);
+    assert_eq!(transport.requests()[0].headers["x-trace-id"], "trace-1");
+}
+
+#[tokio::test]
+async fn client_errors_pass_through_layers() {
+    let error = json!({"id": "1", "name": "unauthorized", "message": "bad key"});
+    let transport = InMemoryTransport::new()
+        .with_response(HttpResponse::json(StatusCode::UNAUTHORIZED, &error).unwrap());
+
+    let service = ServiceBuilder::new()
+        .concurrency_limit(1)
+        .service(TransportService::new(transport));
+
+    let client = Client::new().with_service(service);
+
+    match client.user().account().await {
+        Err(StabilityAIError::ApiError(error)) => assert_eq!(error.name, "unauthorized"),
+        other => panic!("expected api error, got {other:?}"),
+    }
+}
@@ -0,0 +1,169 @@
+//! Images of JSON responses are decoded incrementally into sinks.
+
+use base64::{engine::general_purpose, Engine as _};
+use bytes::Bytes;
+use futures::stream;
+use reqwest::StatusCode;
+use serde_json::json;
+use stabilityai::{
+    error::StabilityAIError,
+    streaming::DirSink,
+    transport::{HttpResponse, InMemoryTransport, ResponseBody},
+    types::{FinishReason, TextToImageRequestBody, TextToImageRequestBodyArgs},
+    Client,
+};
+
+fn request() -> TextToImageRequestBody {
+    TextToImageRequestBodyArgs::default()
+        .text_prompts("A lighthouse")
+        .samples(3)
+        .build()
+        .unwrap()
+}
+
+/// Response body split in chunks of `chunk_size` bytes
+fn chunked(body: String, chunk_size: usize) -> HttpResponse {
+    let chunks: Vec<Result<Bytes, StabilityAIError>> = body
+        .into_bytes()
+        .chunks(chunk_size)
+        .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+        .collect();
+    HttpResponse::new(
+        StatusCode::OK,
+        ResponseBody::from_stream(stream::iter(chunks)),
+    )
+}
+
+fn client(transport: &InMemoryTransport) -> Client {
+    Client::new()
+        .with_api_key("sk-test")
+        .with_transport(transport.clone())
+}
+
+#[tokio::test]
+async fn decode_into_writers() {
+    let images: Vec<Vec<u8>> = (0..3_u32)
+        .map(|i| (0..100_000 * (i + 1)).map(|b| (b * 7 + i) as u8).collect())
+        .collect();
+    let artifacts: Vec<_> = images
+        .iter()
+        .enumerate()
+        .map(|(i, image)| {
+            json!({
+                "base64": general_purpose::STANDARD.encode(image),
+                "finishReason": if i == 1 { "CONTENT_FILTERED" } else { "SUCCESS" },
+                "seed": 40 + i,
+            })
+        })
+        .collect();
+    let body = serde_json::to_string_pretty(&json!({ "artifacts": artifacts })).unwrap();
+
+    for chunk_size in [1, 7, 4096] {
+        let transport = InMemoryTransport::new().with_response(chunked(body.clone(), chunk_size));
+
+        let streamed = client(&transport)
+            .generate("stable-diffusion-v1-6")
+            .text_to_image_streamed(request(), |_| Vec::new())
+            .await
+            .unwrap();
+
+        assert_eq!(streamed.len(), 3);
+        for (i, image) in streamed.iter().enumerate() {
+            assert_eq!(image.output, images[i]);
+            assert_eq!(image.seed, 40 + i as i64);
+        }
+        assert_eq!(streamed[1].finish_reason, FinishReason::ContentFiltered);
+    }
+}
+
+#[tokio::test]
+async fn decode_into_dir() {
+    let png = b"\x89PNG\r\n\x1a\n fake";
+    // Slashes and other characters may be escaped in JSON strings
+    let base64 = general_purpose::STANDARD
+        .encode([&png[..], &[0xff; 12]].concat())
+        .replace('/', "\\/")
+        .replacen('K', "\\u004B", 1);
+    let body = format!(
+        r#"{{"artifacts":[{{"seed":7,"base64":"{base64}","extra":{{"seed":1}},"finishReason":"SUCCESS"}}]}}"#
+    );
+    let transport = InMemoryTransport::new().with_response(chunked(body, 5));
+
+    let dir = std::env::temp_dir().join(format!(
+        "stabilityai-streaming-decode-{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    let streamed = client(&transport)
+        .generate("stable-diffusion-v1-6")
+        .text_to_image_streamed(request(), DirSink::new(&dir))
+        .await
+        .unwrap();
+
+    assert_eq!(streamed.len(), 1);
+    assert_eq!(streamed[0].seed, 7);
+    assert_eq!(streamed[0].output.parent(), Some(dir.as_path()));
+    let written = std::fs::read(&streamed[0].output).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(written, [&png[..], &[0xff; 12]].concat());
+}
+
+#[tokio::test]
+async fn remove_partial_file() {
+    let body = r#"{"artifacts":[{"base64":"AAAA\u002aAAAA","finishReason":"SUCCESS","seed":1}]}"#;
+    let transport = InMemoryTransport::new().with_response(chunked(body.into(), 16));
+
+    let dir = std::env::temp_dir().join(format!(
+        "stabilityai-streaming-partial-{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    let result = client(&transport)
+        .generate("stable-diffusion-v1-6")
+        .text_to_image_streamed(request(), DirSink::new(&dir))
+        .await;
+
+    let files = std::fs::read_dir(&dir).unwrap().count();
+    std::fs::remove_dir_all(&dir).unwrap();
+    // `*` is not a base64 character
+    assert!(matches!(result, Err(StabilityAIError::JSONDeserialize(_))));
+    assert_eq!(files, 0);
+}
+
+#[tokio::test]
+async fn remove_finished_files() {
+    // The first image is written completely before the response is cut off
+    let body =
+        r#"{"artifacts":[{"base64":"AAAA","finishReason":"SUCCESS","seed":1},{"base64":"AAAA"#;
+    let transport = InMemoryTransport::new().with_response(chunked(body.into(), 16));
+
+    let dir = std::env::temp_dir()
+        .join(format!(
+            "stabilityai-streaming-finished-{}",
+            std::process::id()
+        ))
+        .join("images");
+    let result = client(&transport)
+        .generate("stable-diffusion-v1-6")
+        .text_to_image_streamed(request(), DirSink::new(&dir))
+        .await;
+
+    // The missing directory was created for the first image
+    let files = std::fs::read_dir(&dir).unwrap().count();
+    std::fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    assert!(matches!(result, Err(StabilityAIError::JSONDeserialize(_))));
+    assert_eq!(files, 0);
+}
+
+#[tokio::test]
+async fn truncated_response() {
+    let body = r#"{"artifacts":[{"base64":"AAAA","finishReason":"SUCCESS","seed":1}"#.to_string();
+    let transport = InMemoryTransport::new().with_response(chunked(body, 16));
+
+    let result = client(&transport)
+        .generate("stable-diffusion-v1-6")
+        .text_to_image_streamed(request(), |_| Vec::new())
+        .await;
+
+    assert!(matches!(result, Err(StabilityAIError::JSONDeserialize(_))));
+}
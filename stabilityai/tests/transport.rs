@@ -0,0 +1,232 @@
+//! Requests made through the client are checked against scripted responses of [InMemoryTransport].
+
+use std::time::Duration;
+
+use reqwest::{Method, StatusCode};
+use serde_json::json;
+use stabilityai::{
+    transport::{HttpResponse, InMemoryTransport, RequestBody},
+    types::{FinishReason, ImageToImageRequestBodyArgs, TextToImageRequestBodyArgs},
+    Client,
+};
+
+fn client(transport: &InMemoryTransport) -> Client {
+    let backoff = backoff::ExponentialBackoffBuilder::new()
+        .with_initial_interval(Duration::from_millis(1))
+        .with_max_elapsed_time(Some(Duration::from_secs(1)))
+        .build();
+
+    Client::new()
+        .with_api_key("sk-test")
+        .with_organization("org-test")
+        .with_backoff(backoff)
+        .with_transport(transport.clone())
+}
+
+#[tokio::test]
+async fn get_balance() {
+    let transport = InMemoryTransport::new()
+        .with_response(HttpResponse::json(StatusCode::OK, &json!({"credits": 42.5})).unwrap());
+
+    let balance = client(&transport).user().balance().await.unwrap();
+    assert_eq!(balance.credits, 42.5);
+
+    let requests = transport.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].method, Method::GET);
+    assert_eq!(requests[0].url, "https://api.stability.ai/v1/user/balance");
+    assert_eq!(requests[0].headers["authorization"], "Bearer sk-test");
+    assert_eq!(requests[0].headers["organization"], "org-test");
+    assert_eq!(requests[0].body, RequestBody::Empty);
+}
+
+#[tokio::test]
+async fn retry_gateway_error() {
+    let transport = InMemoryTransport::new()
+        .with_response(HttpResponse::new(
+            StatusCode::BAD_GATEWAY,
+            "<html>Bad Gateway</html>",
+        ))
+        .with_response(HttpResponse::json(StatusCode::OK, &json!({"credits": 1.0})).unwrap());
+
+    let balance = client(&transport).user().balance().await.unwrap();
+    assert_eq!(balance.credits, 1.0);
+    assert_eq!(transport.requests().len(), 2);
+}
+
+#[tokio::test]
+async fn retry_after_does_not_extend_max_elapsed_time() {
+    let transport = InMemoryTransport::new();
+    for _ in 0..5 {
+        transport.push_response(
+            HttpResponse::new(StatusCode::SERVICE_UNAVAILABLE, "unavailable")
+                .with_header("retry-after", "1"),
+        );
+    }
+    transport.push_response(HttpResponse::json(StatusCode::OK, &json!({"credits": 1.0})).unwrap());
+    let backoff = backoff::ExponentialBackoffBuilder::new()
+        .with_initial_interval(Duration::from_millis(1))
+        .with_max_elapsed_time(Some(Duration::from_millis(200)))
+        .build();
+
+    let started = std::time::Instant::now();
+    let result = client(&transport)
+        .with_backoff(backoff)
+        .user()
+        .balance()
+        .await;
+
+    assert!(result.is_err());
+    // The Retry-After delay ends after the maximum elapsed time, so gave up without waiting
+    assert!(started.elapsed() < Duration::from_millis(500));
+    assert_eq!(transport.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn retry_after_within_max_elapsed_time() {
+    let transport = InMemoryTransport::new()
+        .with_response(
+            HttpResponse::new(StatusCode::SERVICE_UNAVAILABLE, "unavailable")
+                .with_header("retry-after", "1"),
+        )
+        .with_response(HttpResponse::json(StatusCode::OK, &json!({"credits": 1.0})).unwrap());
+    let backoff = backoff::ExponentialBackoffBuilder::new()
+        .with_initial_interval(Duration::from_millis(1))
+        .with_max_elapsed_time(Some(Duration::from_secs(5)))
+        .build();
+
+    let started = std::time::Instant::now();
+    let balance = client(&transport)
+        .with_backoff(backoff)
+        .user()
+        .balance()
+        .await
+        .unwrap();
+
+    assert_eq!(balance.credits, 1.0);
+    assert!(started.elapsed() >= Duration::from_secs(1));
+    assert_eq!(transport.requests().len(), 2);
+}
+
+#[tokio::test]
+async fn api_error_is_not_retried() {
+    let error = json!({"id": "1", "name": "bad_request", "message": "invalid height"});
+    let transport = InMemoryTransport::new()
+        .with_response(HttpResponse::json(StatusCode::BAD_REQUEST, &error).unwrap());
+
+    let request = TextToImageRequestBodyArgs::default()
+        .text_prompts("A lighthouse")
+        .height(100_u16)
+        .build()
+        .unwrap();
+
+    let result = client(&transport)
+        .generate("stable-diffusion-v1-6")
+        .text_to_image(request)
+        .await;
+
+    assert!(result.is_err());
+    assert_eq!(transport.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn image_to_image_form() {
+    let artifacts = json!({"artifacts": [{"base64": "", "finishReason": "SUCCESS", "seed": 7}]});
+    let transport = InMemoryTransport::new()
+        .with_response(HttpResponse::json(StatusCode::OK, &artifacts).unwrap());
+
+    let request = ImageToImageRequestBodyArgs::default()
+        .text_prompts(("A crab", 0.5))
+        .init_image("./image-data/crab.png")
+        .samples(2)
+        .build()
+        .unwrap();
+
+    let artifacts = client(&transport)
+        .generate("stable-diffusion-v1-6")
+        .image_to_image(request)
+        .await
+        .unwrap();
+    assert_eq!(artifacts.artifacts[0].seed, 7);
+
+    let requests = transport.requests();
+    let RequestBody::Multipart(form) = &requests[0].body else {
+        panic!("expected multipart body");
+    };
+    assert_eq!(
+        form.file_path("init_image").unwrap().to_str(),
+        Some("./image-data/crab.png")
+    );
+    assert_eq!(form.text_value("text_prompts[0][text]"), Some("A crab"));
+    assert_eq!(form.text_value("text_prompts[0][weight]"), Some("0.5"));
+    assert_eq!(form.text_value("samples"), Some("2"));
+}
+
+#[tokio::test]
+async fn reqwest_form_from_request() {
+    let request = |init_image: &str| {
+        ImageToImageRequestBodyArgs::default()
+            .text_prompts("A crab")
+            .init_image(init_image)
+            .build()
+            .unwrap()
+    };
+
+    // Files are read when the form is built
+    let init_image = concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml");
+    let form: Result<reqwest::multipart::Form, _> =
+        async_convert::TryFrom::try_from(request(init_image)).await;
+    assert!(form.is_ok());
+
+    let form: Result<reqwest::multipart::Form, _> =
+        async_convert::TryFrom::try_from(request("./missing.png")).await;
+    assert!(form.is_err());
+}
+
+#[tokio::test]
+async fn text_to_image_binary() {
+    let transport = InMemoryTransport::new().with_response(
+        HttpResponse::new(StatusCode::OK, &b"\x89PNG"[..])
+            .with_header("content-type", "image/png")
+            .with_header("seed", "3817857576")
+            .with_header("finish-reason", "SUCCESS"),
+    );
+
+    let request = TextToImageRequestBodyArgs::default()
+        .text_prompts("A lighthouse")
+        .build()
+        .unwrap();
+
+    let image = client(&transport)
+        .generate("stable-diffusion-v1-6")
+        .text_to_image_binary(request)
+        .await
+        .unwrap();
+
+    assert_eq!(image.bytes.as_ref(), b"\x89PNG");
+    assert_eq!(image.seed, 3817857576);
+    assert_eq!(image.finish_reason, FinishReason::Success);
+    assert_eq!(transport.requests()[0].headers["accept"], "image/png");
+}
+
+#[tokio::test]
+async fn response_metadata() {
+    let transport = InMemoryTransport::new()
+        .with_response(HttpResponse::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "unavailable",
+        ))
+        .with_response(
+            HttpResponse::json(StatusCode::OK, &json!([]))
+                .unwrap()
+                .with_header("x-request-id", "req-123"),
+        );
+
+    let response = client(&transport).engines().list_with_meta().await.unwrap();
+
+    assert!(response.body.is_empty());
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.request_id(), Some("req-123"));
+    assert_eq!(response.content_type(), Some("application/json"));
+    assert_eq!(response.attempts, 2);
+}